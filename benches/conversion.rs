@@ -0,0 +1,101 @@
+//! Measures how fast notes are read from a track and ticks are converted to
+//! seconds, and compares each with the last run
+//!
+//! Run with `cargo bench --bench conversion`. Each run's results are saved to
+//! `target/conversion-baseline.txt`, and the next run prints how far it is
+//! from them. Delete that file to start over from a new baseline.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::hint::black_box;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use midly::{Event, EventKind, MidiMessage};
+use midi2json::{NoteOptions, get_notes, get_tempo_map, get_time_seconds};
+
+const EVENT_COUNT: usize = 10_000;
+const TIME_CALLS: usize = 1_000_000;
+const RUNS: u32 = 20;
+
+/// A track of `EVENT_COUNT` events, alternating between NoteOn and NoteOff
+fn alternating_track() -> Vec<Event<'static>> {
+    (0..EVENT_COUNT)
+        .map(|index| {
+            let key = (36 + (index / 2) % 48) as u8;
+            let message = if index % 2 == 0 {
+                MidiMessage::NoteOn(key.into(), 100.into())
+            } else {
+                MidiMessage::NoteOff(key.into(), 0.into())
+            };
+            Event {
+                delta: 24.into(),
+                kind: EventKind::Midi { channel: 0.into(), message }
+            }
+        })
+        .collect()
+}
+
+/// The average time of a run, and how many events or calls it got through
+/// per second
+fn measure<F: FnMut()>(items_per_run: usize, mut run: F) -> (Duration, f64) {
+    // Once first, so the timed runs don't pay for warming up
+    run();
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        run();
+    }
+    let elapsed = start.elapsed() / RUNS;
+    (elapsed, items_per_run as f64 / elapsed.as_secs_f64())
+}
+
+fn baseline_path() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target").join("conversion-baseline.txt")
+}
+
+/// The throughput of each benchmark from the last run, one `name per_second`
+/// line each
+fn read_baseline() -> BTreeMap<String, f64> {
+    let contents = fs::read_to_string(baseline_path()).unwrap_or_default();
+    contents.lines()
+        .filter_map(|line| {
+            let (name, per_second) = line.split_once(' ')?;
+            Some((name.to_string(), per_second.parse().ok()?))
+        })
+        .collect()
+}
+
+fn report(name: &str, unit: &str, (elapsed, per_second): (Duration, f64), baseline: &BTreeMap<String, f64>) {
+    let change = match baseline.get(name) {
+        Some(previous) => format!("{:+.1}% from baseline", (per_second / previous - 1.0) * 100.0),
+        None => "no baseline".to_string()
+    };
+    println!("{:>18}: {:>8.2?} per run, {:>12.0} {}/s ({})", name, elapsed, per_second, unit, change);
+}
+
+fn main() {
+    let baseline = read_baseline();
+
+    let track = alternating_track();
+    let tempo_map = get_tempo_map(std::slice::from_ref(&track), Some(120.0)).unwrap();
+    let options = NoteOptions::default();
+    let get_notes_result = measure(EVENT_COUNT, || {
+        black_box(get_notes(black_box(&track), &tempo_map, 96, &options).unwrap());
+    });
+    report("get_notes", "events", get_notes_result, &baseline);
+
+    let get_time_seconds_result = measure(TIME_CALLS, || {
+        let mut total = 0.0;
+        for index in 0..TIME_CALLS {
+            let ticks = (index * 7) as u32;
+            let bpm = 60.0 + (index % 120) as f32;
+            total += get_time_seconds(black_box(ticks), black_box(bpm), 96);
+        }
+        black_box(total);
+    });
+    report("get_time_seconds", "calls", get_time_seconds_result, &baseline);
+
+    let saved = format!("get_notes {}\nget_time_seconds {}\n", get_notes_result.1, get_time_seconds_result.1);
+    if let Err(error) = fs::write(baseline_path(), saved) {
+        eprintln!("Could not save the baseline: {}", error);
+    }
+}
@@ -0,0 +1,67 @@
+//! Compares converting the tracks of a large file one at a time and in parallel
+//!
+//! Run with `cargo bench --bench tracks`.
+
+use std::time::{Duration, Instant};
+use midly::{Event, Smf};
+use rayon::prelude::*;
+use midi2json::{Note, NoteOptions, Result, get_notes, get_tempo_map};
+
+const TRACK_COUNT: usize = 32;
+const NOTES_PER_TRACK: usize = 20_000;
+const RUNS: u32 = 10;
+
+/// A Type 1 file at 96 ticks per beat with `TRACK_COUNT` tracks of
+/// sixteenth notes, like an orchestral template
+fn orchestral_file() -> Vec<u8> {
+    let mut bytes = b"MThd\x00\x00\x00\x06\x00\x01".to_vec();
+    bytes.extend_from_slice(&(TRACK_COUNT as u16).to_be_bytes());
+    bytes.extend_from_slice(&96u16.to_be_bytes());
+
+    for track_index in 0..TRACK_COUNT {
+        let channel = (track_index % 16) as u8;
+        let mut track = Vec::new();
+        for note_index in 0..NOTES_PER_TRACK {
+            let pitch = 36 + ((track_index + note_index) % 48) as u8;
+            track.extend_from_slice(&[0x00, 0x90 | channel, pitch, 100]);
+            track.extend_from_slice(&[0x18, 0x80 | channel, pitch, 0]);
+        }
+        track.extend_from_slice(&[0x00, 0xff, 0x2f, 0x00]);
+
+        bytes.extend_from_slice(b"MTrk");
+        bytes.extend_from_slice(&(track.len() as u32).to_be_bytes());
+        bytes.extend(track);
+    }
+
+    bytes
+}
+
+fn time<F: FnMut() -> usize>(name: &str, mut convert: F) -> Duration {
+    let start = Instant::now();
+    let mut note_count = 0;
+    for _ in 0..RUNS {
+        note_count = convert();
+    }
+    let elapsed = start.elapsed() / RUNS;
+    println!("{:>10}: {:>8.2?} per run ({} notes)", name, elapsed, note_count);
+    elapsed
+}
+
+fn main() {
+    let bytes = orchestral_file();
+    let smf: Smf<Vec<Event>> = Smf::read(&bytes).unwrap();
+    let tempo_map = get_tempo_map(&smf.tracks, Some(120.0)).unwrap();
+    let options = NoteOptions::default();
+    let convert = |track: &Vec<Event>| get_notes(track, &tempo_map, 96, &options);
+
+    println!("{} tracks of {} notes, {} threads", TRACK_COUNT, NOTES_PER_TRACK, rayon::current_num_threads());
+    let sequential = time("sequential", || {
+        let track_notes = smf.tracks.iter().map(convert).collect::<Result<Vec<Vec<Note>>>>().unwrap();
+        track_notes.iter().map(Vec::len).sum()
+    });
+    let parallel = time("parallel", || {
+        let track_notes = smf.tracks.par_iter().map(convert).collect::<Result<Vec<Vec<Note>>>>().unwrap();
+        track_notes.iter().map(Vec::len).sum()
+    });
+    println!("{:>10}: {:.1}x", "speedup", sequential.as_secs_f64() / parallel.as_secs_f64());
+}
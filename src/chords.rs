@@ -0,0 +1,110 @@
+//! Groups of notes that start together
+
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use crate::{Note, TimeValue};
+
+/// Notes that start within a short window of each other, as written to the output
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Chord {
+    /// When the first note starts
+    pub time_start: TimeValue,
+    /// When the last note to end ends
+    pub time_end: TimeValue,
+    pub notes: Vec<Note>
+}
+
+/// Groups the notes into chords, in order of start time. Each chord starts with
+/// the earliest note that isn't in a chord yet, and takes every note that
+/// starts less than `window` after it. The window is in the same unit as the
+/// note times.
+///
+/// Every note ends up in exactly one chord, so a note played alone is a chord
+/// of one.
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::{group_chords, Note};
+///
+/// let notes = vec![
+///     Note { time_start: 0.0.into(), time_end: 1.0.into(), pitch_value: 60, ..Default::default() },
+///     Note { time_start: 0.01.into(), time_end: 1.0.into(), pitch_value: 64, ..Default::default() },
+///     Note { time_start: 1.0.into(), time_end: 2.0.into(), pitch_value: 67, ..Default::default() }
+/// ];
+/// let chords = group_chords(notes.iter(), 0.05);
+/// assert_eq!(chords.len(), 2);
+/// assert_eq!(chords[0].notes.len(), 2);
+/// ```
+pub fn group_chords<'a>(notes: impl Iterator<Item = &'a Note>, window: f64) -> Vec<Chord> {
+    let mut notes: Vec<Note> = notes.cloned().collect();
+    notes.sort_by(|a, b| a.time_start.as_f64().partial_cmp(&b.time_start.as_f64()).unwrap());
+
+    let mut chords: Vec<Chord> = Vec::new();
+    for note in notes {
+        match chords.last_mut() {
+            Some(chord) if note.time_start.as_f64() - chord.time_start.as_f64() < window => {
+                if note.time_end.as_f64() > chord.time_end.as_f64() {
+                    chord.time_end = note.time_end;
+                }
+                chord.notes.push(note);
+            },
+            _ => chords.push(Chord {
+                time_start: note.time_start,
+                time_end: note.time_end,
+                notes: vec![note]
+            })
+        }
+    }
+
+    chords
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn note(time_start: f64, time_end: f64, pitch_value: u32) -> Note {
+        Note {
+            time_start: time_start.into(),
+            time_end: time_end.into(),
+            pitch_value,
+            ..Default::default()
+        }
+    }
+
+    fn get_pitches(chords: &[Chord]) -> Vec<Vec<u32>> {
+        chords.iter()
+            .map(|chord| chord.notes.iter().map(|note| note.pitch_value).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_group_chords_window() {
+        // Strummed 5 ms apart, then a note 200 ms later
+        let notes = [
+            note(0.0, 1.0, 60),
+            note(0.005, 1.0, 64),
+            note(0.01, 1.5, 67),
+            note(0.21, 1.0, 72)
+        ];
+        let chords = group_chords(notes.iter(), 0.05);
+        assert_eq!(get_pitches(&chords), vec![vec![60, 64, 67], vec![72]]);
+        assert_eq!(chords[0].time_start, 0.0.into());
+        assert_eq!(chords[0].time_end, 1.5.into());
+        assert_eq!(chords[1].time_start, 0.21.into());
+    }
+
+    #[test]
+    fn test_group_chords_unsorted() {
+        let notes = [note(0.5, 1.0, 67), note(0.0, 0.5, 60), note(0.502, 1.0, 71)];
+        assert_eq!(get_pitches(&group_chords(notes.iter(), 0.01)), vec![vec![60], vec![67, 71]]);
+    }
+
+    #[test]
+    fn test_group_chords_anchored_to_first_note() {
+        // Each note is within the window of the previous one, but not of the first
+        let notes = [note(0.0, 1.0, 60), note(0.03, 1.0, 62), note(0.06, 1.0, 64)];
+        assert_eq!(get_pitches(&group_chords(notes.iter(), 0.05)), vec![vec![60, 62], vec![64]]);
+    }
+}
@@ -0,0 +1,103 @@
+//! The error type for everything that can go wrong while converting a file
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// Everything that can go wrong while converting a MIDI file
+#[derive(Debug)]
+pub enum Midi2JsonError {
+    /// Reading the input or writing the output failed
+    IoError(io::Error),
+    /// The input isn't a valid MIDI file
+    ParseError(String),
+    /// The input isn't valid note information in JSON
+    JsonError(String),
+    /// A note was still held when its track ended
+    MissingNoteOff { pitch: u8, channel: u8 },
+    /// The BPM given isn't a positive number
+    InvalidBpm(String),
+    /// The file has no tempo events and no BPM was given
+    MissingTempo,
+    /// Some other command-line argument has an unusable value
+    InvalidArgument(String),
+    /// The file uses a feature that isn't supported yet
+    Unsupported(String),
+    /// Some of the files in a batch couldn't be converted
+    BatchFailed { failed: usize, total: usize }
+}
+
+/// Shorthand for results that fail with a `Midi2JsonError`
+pub type Result<T> = std::result::Result<T, Midi2JsonError>;
+
+impl fmt::Display for Midi2JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Midi2JsonError::IoError(error) => write!(f, "{}", error),
+            Midi2JsonError::ParseError(message) => write!(f, "Could not parse MIDI file: {}", message),
+            Midi2JsonError::JsonError(message) => write!(f, "Could not parse note JSON: {}", message),
+            Midi2JsonError::MissingNoteOff { pitch, channel } => {
+                write!(f, "Note {} on channel {} has no matching NoteOff", pitch, channel)
+            },
+            Midi2JsonError::InvalidBpm(bpm) => write!(f, "Invalid BPM: {}", bpm),
+            Midi2JsonError::MissingTempo => {
+                write!(f, "The file has no tempo events, so a BPM must be specified")
+            },
+            Midi2JsonError::InvalidArgument(message) => write!(f, "{}", message),
+            Midi2JsonError::Unsupported(message) => write!(f, "Unsupported MIDI file: {}", message),
+            Midi2JsonError::BatchFailed { failed, total } => {
+                write!(f, "{} of {} files could not be converted", failed, total)
+            }
+        }
+    }
+}
+
+impl Error for Midi2JsonError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Midi2JsonError::IoError(error) => Some(error),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for Midi2JsonError {
+    fn from(error: io::Error) -> Self {
+        Midi2JsonError::IoError(error)
+    }
+}
+
+impl From<midly::Error> for Midi2JsonError {
+    fn from(error: midly::Error) -> Self {
+        // midly's errors are chained, with the most specific cause last
+        let messages: Vec<String> = error.iter()
+            .map(|cause| cause.to_string())
+            .collect();
+        Midi2JsonError::ParseError(messages.join(": "))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_error_messages() {
+        let error = Midi2JsonError::MissingNoteOff { pitch: 60, channel: 9 };
+        assert_eq!(error.to_string(), "Note 60 on channel 9 has no matching NoteOff");
+
+        let error = Midi2JsonError::InvalidBpm("fast".to_string());
+        assert_eq!(error.to_string(), "Invalid BPM: fast");
+    }
+
+    #[test]
+    fn test_parse_error_from_midly() {
+        let error: Midi2JsonError = midly::Smf::<Vec<midly::Event>>::read(b"not a midi file")
+            .unwrap_err()
+            .into();
+        match error {
+            Midi2JsonError::ParseError(message) => assert!(!message.is_empty()),
+            other => panic!("Expected a parse error, got {:?}", other)
+        }
+    }
+}
@@ -0,0 +1,722 @@
+//! MIDI events other than notes, collected from every track
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use schemars::JsonSchema;
+use midly::{
+    Event,
+    EventKind,
+    MetaMessage,
+    MidiMessage
+};
+use crate::names;
+use crate::time::{TempoChange, get_time_seconds_tempo_map};
+
+/// Finds a track's name, from the first TrackName event in it
+///
+/// # Examples
+///
+/// ```
+/// use midly::{Event, EventKind, MetaMessage};
+/// use midly::number::VarlenInt;
+/// use midi2json::get_track_name;
+///
+/// let track = vec![Event { delta: VarlenInt::from(0), kind: EventKind::Meta(MetaMessage::TrackName(b"Piano")) }];
+/// assert_eq!(get_track_name(&track), Some("Piano".to_string()));
+/// ```
+pub fn get_track_name(track: &[Event]) -> Option<String> {
+    track.iter().find_map(|event| match event.kind {
+        // Names aren't required to be UTF-8, so anything else is replaced rather than rejected
+        EventKind::Meta(MetaMessage::TrackName(name)) => Some(String::from_utf8_lossy(name).into_owned()),
+        _ => None
+    })
+}
+
+/// A control change (CC) message, such as the sustain pedal (64) or modulation (1)
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ControlChange {
+    pub tick: u32,
+    pub time_seconds: f64,
+    pub channel: u8,
+    pub controller: u8,
+    pub value: u8
+}
+
+/// Collects the control changes from every track, in tick order.
+///
+/// If `controllers` is given, only those controller numbers are collected.
+///
+/// # Examples
+///
+/// ```
+/// use midly::{Smf, Event};
+/// use midi2json::{get_control_changes, get_tempo_map};
+///
+/// let bytes = include_bytes!("../input/sample.mid");
+/// let smf: Smf<Vec<Event>> = Smf::read(bytes).unwrap();
+///
+/// let tempo_map = get_tempo_map(&smf.tracks, Some(120.0)).unwrap();
+/// let sustain_changes = get_control_changes(&smf.tracks, &tempo_map, 96, Some(&[64]));
+/// assert!(sustain_changes.iter().all(|control_change| control_change.controller == 64));
+/// ```
+pub fn get_control_changes<'a, T: AsRef<[Event<'a>]>>(
+    tracks: &[T],
+    tempo_map: &[TempoChange],
+    ticks_per_beat: u16,
+    controllers: Option<&[u8]>
+) -> Vec<ControlChange> {
+    let mut control_changes = Vec::<ControlChange>::new();
+    for track in tracks {
+        let mut cur_time: u32 = 0;
+        for event in track.as_ref() {
+            cur_time += event.delta.as_int();
+
+            if let EventKind::Midi { message: MidiMessage::Controller(controller, value), channel } = event.kind {
+                let controller = controller.as_int();
+                if controllers.is_some_and(|controllers| !controllers.contains(&controller)) {
+                    continue;
+                }
+
+                control_changes.push(ControlChange {
+                    tick: cur_time,
+                    time_seconds: get_time_seconds_tempo_map(cur_time, tempo_map, ticks_per_beat),
+                    channel: channel.as_int(),
+                    controller,
+                    value: value.as_int()
+                });
+            }
+        }
+    }
+    control_changes.sort_by_key(|control_change| control_change.tick);
+
+    control_changes
+}
+
+/// A program change, which selects the instrument for a channel
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ProgramChange {
+    pub tick: u32,
+    pub time_seconds: f64,
+    pub channel: u8,
+    pub program: u8,
+    /// The General MIDI instrument name, if requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub program_name: Option<String>
+}
+
+/// Collects the program changes from every track, in tick order.
+///
+/// With `gm_names`, each one is also named with its General MIDI instrument.
+///
+/// # Examples
+///
+/// ```
+/// use midly::{Smf, Event};
+/// use midi2json::{get_program_changes, get_tempo_map};
+///
+/// let bytes = include_bytes!("../input/sample.mid");
+/// let smf: Smf<Vec<Event>> = Smf::read(bytes).unwrap();
+///
+/// let tempo_map = get_tempo_map(&smf.tracks, Some(120.0)).unwrap();
+/// for program_change in get_program_changes(&smf.tracks, &tempo_map, 96, true) {
+///     println!("Channel {}: {}", program_change.channel, program_change.program_name.unwrap());
+/// }
+/// ```
+pub fn get_program_changes<'a, T: AsRef<[Event<'a>]>>(
+    tracks: &[T],
+    tempo_map: &[TempoChange],
+    ticks_per_beat: u16,
+    gm_names: bool
+) -> Vec<ProgramChange> {
+    let mut program_changes = Vec::<ProgramChange>::new();
+    for track in tracks {
+        let mut cur_time: u32 = 0;
+        for event in track.as_ref() {
+            cur_time += event.delta.as_int();
+
+            if let EventKind::Midi { message: MidiMessage::ProgramChange(program), channel } = event.kind {
+                let program = program.as_int();
+                program_changes.push(ProgramChange {
+                    tick: cur_time,
+                    time_seconds: get_time_seconds_tempo_map(cur_time, tempo_map, ticks_per_beat),
+                    channel: channel.as_int(),
+                    program,
+                    program_name: if gm_names {
+                        Some(names::program_name(program).to_string())
+                    } else {
+                        None
+                    }
+                });
+            }
+        }
+    }
+    program_changes.sort_by_key(|program_change| program_change.tick);
+
+    program_changes
+}
+
+/// A pitch bend, relative to the center of the pitch wheel
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct PitchBend {
+    pub tick: u32,
+    pub time_seconds: f64,
+    pub channel: u8,
+    /// From -8192 (all the way down) to 8191 (all the way up)
+    pub value: i16,
+    /// The bend in semitones, if a bend range was given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub semitones: Option<f64>
+}
+
+/// Converts a pitch bend value to semitones, for a synth that bends up to `range` semitones each way
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::events::pitch_bend_semitones;
+///
+/// assert_eq!(pitch_bend_semitones(-8192, 2.0), -2.0);
+/// assert_eq!(pitch_bend_semitones(-4096, 2.0), -1.0);
+/// assert_eq!(pitch_bend_semitones(8191, 2.0), 2.0);
+/// ```
+pub fn pitch_bend_semitones(value: i16, range: f64) -> f64 {
+    // The wheel goes one step further down than up, so each side is scaled
+    // separately to reach the full range at both ends
+    if value < 0 {
+        (value as f64) / 8192.0 * range
+    } else {
+        (value as f64) / 8191.0 * range
+    }
+}
+
+/// Collects the pitch bends from every track, in tick order.
+///
+/// If `semitone_range` is given, each bend is also converted to semitones.
+/// General MIDI synths default to a range of 2 semitones.
+///
+/// # Examples
+///
+/// ```
+/// use midly::{Smf, Event};
+/// use midi2json::{get_pitch_bends, get_tempo_map};
+///
+/// let bytes = include_bytes!("../input/sample.mid");
+/// let smf: Smf<Vec<Event>> = Smf::read(bytes).unwrap();
+///
+/// let tempo_map = get_tempo_map(&smf.tracks, Some(120.0)).unwrap();
+/// let pitch_bends = get_pitch_bends(&smf.tracks, &tempo_map, 96, Some(2.0));
+/// assert!(pitch_bends.iter().all(|pitch_bend| pitch_bend.semitones.is_some()));
+/// ```
+pub fn get_pitch_bends<'a, T: AsRef<[Event<'a>]>>(
+    tracks: &[T],
+    tempo_map: &[TempoChange],
+    ticks_per_beat: u16,
+    semitone_range: Option<f64>
+) -> Vec<PitchBend> {
+    let mut pitch_bends = Vec::<PitchBend>::new();
+    for track in tracks {
+        let mut cur_time: u32 = 0;
+        for event in track.as_ref() {
+            cur_time += event.delta.as_int();
+
+            if let EventKind::Midi { message: MidiMessage::PitchBend(bend), channel } = event.kind {
+                // The raw value is 14 bits, centered on 0x2000
+                let value = (bend.as_int() as i16) - 0x2000;
+                pitch_bends.push(PitchBend {
+                    tick: cur_time,
+                    time_seconds: get_time_seconds_tempo_map(cur_time, tempo_map, ticks_per_beat),
+                    channel: channel.as_int(),
+                    value,
+                    semitones: semitone_range.map(|range| pitch_bend_semitones(value, range))
+                });
+            }
+        }
+    }
+    pitch_bends.sort_by_key(|pitch_bend| pitch_bend.tick);
+
+    pitch_bends
+}
+
+/// A key signature change
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct KeySignature {
+    pub tick: u32,
+    pub time_seconds: f64,
+    /// The number of sharps (positive) or flats (negative)
+    pub sharps_flats: i8,
+    pub major: bool,
+    /// The key's name (e.g. "D major"), or "Unknown" if there are too many sharps or flats
+    pub key_name: String
+}
+
+/// Collects the key signature changes from every track, in tick order
+///
+/// # Examples
+///
+/// ```
+/// use midly::{Smf, Event};
+/// use midi2json::{get_key_signatures, get_tempo_map};
+///
+/// let bytes = include_bytes!("../input/sample.mid");
+/// let smf: Smf<Vec<Event>> = Smf::read(bytes).unwrap();
+///
+/// let tempo_map = get_tempo_map(&smf.tracks, Some(120.0)).unwrap();
+/// for key_signature in get_key_signatures(&smf.tracks, &tempo_map, 96) {
+///     println!("{}", key_signature.key_name);
+/// }
+/// ```
+pub fn get_key_signatures<'a, T: AsRef<[Event<'a>]>>(
+    tracks: &[T],
+    tempo_map: &[TempoChange],
+    ticks_per_beat: u16
+) -> Vec<KeySignature> {
+    let mut key_signatures = Vec::<KeySignature>::new();
+    for track in tracks {
+        let mut cur_time: u32 = 0;
+        for event in track.as_ref() {
+            cur_time += event.delta.as_int();
+
+            if let EventKind::Meta(MetaMessage::KeySignature(sharps_flats, minor)) = event.kind {
+                key_signatures.push(KeySignature {
+                    tick: cur_time,
+                    time_seconds: get_time_seconds_tempo_map(cur_time, tempo_map, ticks_per_beat),
+                    sharps_flats,
+                    major: !minor,
+                    key_name: names::key_name(sharps_flats, !minor).unwrap_or_else(|| "Unknown".to_string())
+                });
+            }
+        }
+    }
+    key_signatures.sort_by_key(|key_signature| key_signature.tick);
+
+    key_signatures
+}
+
+/// A text meta event, such as a lyric or a copyright notice
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct TextEvent {
+    pub tick: u32,
+    pub time_seconds: f64,
+    /// The kind of meta event, e.g. "lyric", "text", or "copyright"
+    #[serde(rename = "type")]
+    pub event_type: String,
+    pub text: String
+}
+
+/// The type and contents of a text meta event, or `None` for any other event
+fn get_text<'a>(message: &MetaMessage<'a>) -> Option<(&'static str, &'a [u8])> {
+    match *message {
+        MetaMessage::Text(text) => Some(("text", text)),
+        MetaMessage::Copyright(text) => Some(("copyright", text)),
+        MetaMessage::TrackName(text) => Some(("track_name", text)),
+        MetaMessage::InstrumentName(text) => Some(("instrument_name", text)),
+        MetaMessage::Lyric(text) => Some(("lyric", text)),
+        MetaMessage::Marker(text) => Some(("marker", text)),
+        MetaMessage::CuePoint(text) => Some(("cue_point", text)),
+        MetaMessage::ProgramName(text) => Some(("program_name", text)),
+        MetaMessage::DeviceName(text) => Some(("device_name", text)),
+        _ => None
+    }
+}
+
+/// Decodes text as UTF-8, writing any bytes that aren't valid UTF-8 as `\xNN`
+/// escapes so that nothing is lost
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::events::decode_text;
+///
+/// assert_eq!(decode_text("Café".as_bytes()), "Café");
+/// assert_eq!(decode_text(b"Caf\xe9"), "Caf\\xE9");
+/// ```
+pub fn decode_text(mut bytes: &[u8]) -> String {
+    let mut text = String::new();
+    loop {
+        match std::str::from_utf8(bytes) {
+            Ok(valid) => {
+                text.push_str(valid);
+                return text;
+            },
+            Err(error) => {
+                let (valid, rest) = bytes.split_at(error.valid_up_to());
+                text.push_str(std::str::from_utf8(valid).unwrap());
+                // A sequence cut off at the end has no error length, so the rest is invalid
+                let invalid_len = error.error_len().unwrap_or(rest.len());
+                for byte in &rest[..invalid_len] {
+                    text.push_str(&format!("\\x{:02X}", byte));
+                }
+                bytes = &rest[invalid_len..];
+            }
+        }
+    }
+}
+
+/// Collects the text meta events (lyrics, markers, copyright notices, and so
+/// on) from every track, in tick order
+pub fn get_text_events<'a, T: AsRef<[Event<'a>]>>(
+    tracks: &[T],
+    tempo_map: &[TempoChange],
+    ticks_per_beat: u16
+) -> Vec<TextEvent> {
+    let mut text_events = Vec::<TextEvent>::new();
+    for track in tracks {
+        let mut cur_time: u32 = 0;
+        for event in track.as_ref() {
+            cur_time += event.delta.as_int();
+
+            if let EventKind::Meta(ref message) = event.kind {
+                if let Some((event_type, text)) = get_text(message) {
+                    text_events.push(TextEvent {
+                        tick: cur_time,
+                        time_seconds: get_time_seconds_tempo_map(cur_time, tempo_map, ticks_per_beat),
+                        event_type: event_type.to_string(),
+                        text: decode_text(text)
+                    });
+                }
+            }
+        }
+    }
+    // Stable, so events at the same tick stay in file order, which matters for lyrics
+    text_events.sort_by_key(|text_event| text_event.tick);
+
+    text_events
+}
+
+/// One event from a track, of any kind, for seeing exactly what a file
+/// contains when its notes come out unexpectedly
+#[derive(Clone, Debug, PartialEq, Serialize, JsonSchema)]
+pub struct LoggedEvent {
+    pub tick: u32,
+    /// The index of the track the event is in
+    pub track: usize,
+    /// What sort of event it is, like "note_on", "controller", "sysex", or
+    /// for meta events their type, like "tempo" or "end_of_track"
+    pub kind: String,
+    /// The channel, for channel messages, or `null` for everything else
+    pub channel: Option<u8>,
+    /// The event's values, which depend on its kind
+    pub data: Value
+}
+
+/// The kind and values of a channel message
+fn describe_midi_message(message: &MidiMessage) -> (&'static str, Value) {
+    match *message {
+        MidiMessage::NoteOff(pitch, velocity) => ("note_off", json!({ "pitch_value": pitch.as_int(), "velocity": velocity.as_int() })),
+        MidiMessage::NoteOn(pitch, velocity) => ("note_on", json!({ "pitch_value": pitch.as_int(), "velocity": velocity.as_int() })),
+        MidiMessage::Aftertouch(pitch, pressure) => ("aftertouch", json!({ "pitch_value": pitch.as_int(), "pressure": pressure.as_int() })),
+        MidiMessage::Controller(controller, value) => ("controller", json!({ "controller": controller.as_int(), "value": value.as_int() })),
+        MidiMessage::ProgramChange(program) => ("program_change", json!({ "program": program.as_int() })),
+        MidiMessage::ChannelAftertouch(pressure) => ("channel_aftertouch", json!({ "pressure": pressure.as_int() })),
+        // Centered on 0, as in `PitchBend`
+        MidiMessage::PitchBend(bend) => ("pitch_bend", json!({ "value": (bend.as_int() as i16) - 0x2000 }))
+    }
+}
+
+/// The type and value of a meta event
+fn describe_meta_message(message: &MetaMessage) -> (&'static str, Value) {
+    if let Some((event_type, text)) = get_text(message) {
+        return (event_type, json!({ "text": decode_text(text) }));
+    }
+
+    match *message {
+        MetaMessage::TrackNumber(number) => ("track_number", json!({ "number": number })),
+        MetaMessage::MidiChannel(channel) => ("midi_channel", json!({ "channel": channel.as_int() })),
+        MetaMessage::MidiPort(port) => ("midi_port", json!({ "port": port.as_int() })),
+        MetaMessage::EndOfTrack => ("end_of_track", json!({})),
+        MetaMessage::Tempo(micros_per_beat) => ("tempo", json!({
+            "micros_per_beat": micros_per_beat.as_int(),
+            "bpm": 60_000_000.0 / (micros_per_beat.as_int() as f64)
+        })),
+        MetaMessage::SmpteOffset(offset) => ("smpte_offset", json!({
+            "hour": offset.hour(),
+            "minute": offset.minute(),
+            "second": offset.second(),
+            "frame": offset.frame(),
+            "subframe": offset.subframe(),
+            "frames_per_second": offset.fps().as_int()
+        })),
+        MetaMessage::TimeSignature(numerator, denominator_power, clocks_per_click, thirty_seconds) => ("time_signature", json!({
+            "numerator": numerator,
+            "denominator": 2u8.saturating_pow(denominator_power as u32),
+            "clocks_per_click": clocks_per_click,
+            "thirty_second_notes_per_quarter": thirty_seconds
+        })),
+        MetaMessage::KeySignature(sharps_flats, minor) => ("key_signature", json!({
+            "sharps_flats": sharps_flats,
+            "major": !minor,
+            "key_name": names::key_name(sharps_flats, !minor).unwrap_or_else(|| "Unknown".to_string())
+        })),
+        MetaMessage::SequencerSpecific(bytes) => ("sequencer_specific", json!({ "bytes": bytes })),
+        _ => unreachable!("Every other meta event is a text event")
+    }
+}
+
+/// Lists every event in every track, of any kind, in tick order. Events at the
+/// same tick stay in file order, and earlier tracks' come first.
+///
+/// # Examples
+///
+/// ```
+/// use midly::{Smf, Event};
+/// use midi2json::get_event_log;
+///
+/// let bytes = include_bytes!("../input/sample.mid");
+/// let smf: Smf<Vec<Event>> = Smf::read(bytes).unwrap();
+///
+/// let event_log = get_event_log(&smf.tracks);
+/// assert_eq!(event_log.len(), smf.tracks[0].len());
+/// assert_eq!(event_log.last().unwrap().kind, "end_of_track");
+/// ```
+pub fn get_event_log<'a, T: AsRef<[Event<'a>]>>(tracks: &[T]) -> Vec<LoggedEvent> {
+    let mut event_log = Vec::<LoggedEvent>::new();
+    for (track_index, track) in tracks.iter().enumerate() {
+        let mut cur_time: u32 = 0;
+        for event in track.as_ref() {
+            cur_time += event.delta.as_int();
+
+            let (kind, channel, data) = match event.kind {
+                EventKind::Midi { channel, ref message } => {
+                    let (kind, data) = describe_midi_message(message);
+                    (kind, Some(channel.as_int()), data)
+                },
+                EventKind::SysEx(bytes) => ("sysex", None, json!({ "bytes": bytes })),
+                EventKind::Escape(bytes) => ("escape", None, json!({ "bytes": bytes })),
+                EventKind::Meta(ref message) => {
+                    let (kind, data) = describe_meta_message(message);
+                    (kind, None, data)
+                }
+            };
+            event_log.push(LoggedEvent {
+                tick: cur_time,
+                track: track_index,
+                kind: kind.to_string(),
+                channel,
+                data
+            });
+        }
+    }
+    event_log.sort_by_key(|logged_event| logged_event.tick);
+
+    event_log
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use midly::number::{u4, u7, u14, u24, VarlenInt};
+
+    fn controller_event(delta: u32, channel: u8, controller: u8, value: u8) -> Event<'static> {
+        Event {
+            delta: VarlenInt::from(delta),
+            kind: EventKind::Midi {
+                channel: u4::from(channel),
+                message: MidiMessage::Controller(u7::from(controller), u7::from(value))
+            }
+        }
+    }
+
+    #[test]
+    fn test_get_track_name() {
+        let track = vec![
+            controller_event(0, 0, 64, 127),
+            Event {
+                delta: VarlenInt::from(0),
+                kind: EventKind::Meta(MetaMessage::TrackName(b"Strings"))
+            },
+            Event {
+                delta: VarlenInt::from(96),
+                kind: EventKind::Meta(MetaMessage::TrackName(b"Renamed"))
+            }
+        ];
+        assert_eq!(get_track_name(&track), Some("Strings".to_string()));
+        assert_eq!(get_track_name(&track[..1]), None);
+
+        let latin1_track = vec![Event {
+            delta: VarlenInt::from(0),
+            kind: EventKind::Meta(MetaMessage::TrackName(b"Caf\xe9"))
+        }];
+        assert_eq!(get_track_name(&latin1_track), Some("Caf\u{fffd}".to_string()));
+    }
+
+    #[test]
+    fn test_get_control_changes_sustain() {
+        let track = vec![
+            controller_event(0, 0, 64, 127),
+            controller_event(48, 0, 1, 20),
+            controller_event(48, 0, 64, 0)
+        ];
+        let tempo_map = vec![TempoChange { tick: 0, micros_per_beat: 500_000 }];
+        assert_eq!(get_control_changes(&[&track], &tempo_map, 96, Some(&[64])), vec![
+            ControlChange { tick: 0, time_seconds: 0.0, channel: 0, controller: 64, value: 127 },
+            ControlChange { tick: 96, time_seconds: 0.5, channel: 0, controller: 64, value: 0 }
+        ]);
+        assert_eq!(get_control_changes(&[&track], &tempo_map, 96, None).len(), 3);
+    }
+
+    #[test]
+    fn test_get_program_changes() {
+        let track = vec![
+            Event {
+                delta: VarlenInt::from(0),
+                kind: EventKind::Midi {
+                    channel: u4::from(2),
+                    message: MidiMessage::ProgramChange(u7::from(56))
+                }
+            }
+        ];
+        let tempo_map = vec![TempoChange { tick: 0, micros_per_beat: 500_000 }];
+        assert_eq!(get_program_changes(&[&track], &tempo_map, 96, true), vec![
+            ProgramChange {
+                tick: 0,
+                time_seconds: 0.0,
+                channel: 2,
+                program: 56,
+                program_name: Some("Trumpet".to_string())
+            }
+        ]);
+        assert_eq!(get_program_changes(&[&track], &tempo_map, 96, false)[0].program_name, None);
+    }
+
+    fn pitch_bend_event(delta: u32, bend: u16) -> Event<'static> {
+        Event {
+            delta: VarlenInt::from(delta),
+            kind: EventKind::Midi {
+                channel: u4::from(0),
+                message: MidiMessage::PitchBend(u14::from(bend))
+            }
+        }
+    }
+
+    #[test]
+    fn test_get_pitch_bends() {
+        let track = vec![
+            pitch_bend_event(0, 0x2000),
+            pitch_bend_event(96, 0x3fff),
+            pitch_bend_event(96, 0)
+        ];
+        let tempo_map = vec![TempoChange { tick: 0, micros_per_beat: 500_000 }];
+        let pitch_bends = get_pitch_bends(&[&track], &tempo_map, 96, Some(2.0));
+        let values: Vec<(i16, Option<f64>)> = pitch_bends.iter()
+            .map(|pitch_bend| (pitch_bend.value, pitch_bend.semitones))
+            .collect();
+        assert_eq!(values, vec![
+            (0, Some(0.0)),
+            (8191, Some(2.0)),
+            (-8192, Some(-2.0))
+        ]);
+        assert_eq!(pitch_bends[1].time_seconds, 0.5);
+
+        assert_eq!(get_pitch_bends(&[&track], &tempo_map, 96, None)[1].semitones, None);
+    }
+
+    #[test]
+    fn test_pitch_bend_semitones_range() {
+        assert_eq!(pitch_bend_semitones(0, 12.0), 0.0);
+        assert_eq!(pitch_bend_semitones(8191, 12.0), 12.0);
+        assert_eq!(pitch_bend_semitones(-8192, 12.0), -12.0);
+        assert_eq!(pitch_bend_semitones(-2048, 12.0), -3.0);
+    }
+
+    #[test]
+    fn test_get_key_signatures() {
+        let track = vec![
+            Event {
+                delta: VarlenInt::from(0),
+                kind: EventKind::Meta(MetaMessage::KeySignature(2, false))
+            },
+            Event {
+                delta: VarlenInt::from(192),
+                kind: EventKind::Meta(MetaMessage::KeySignature(-3, true))
+            }
+        ];
+        let tempo_map = vec![TempoChange { tick: 0, micros_per_beat: 500_000 }];
+        assert_eq!(get_key_signatures(&[&track], &tempo_map, 96), vec![
+            KeySignature {
+                tick: 0,
+                time_seconds: 0.0,
+                sharps_flats: 2,
+                major: true,
+                key_name: "D major".to_string()
+            },
+            KeySignature {
+                tick: 192,
+                time_seconds: 1.0,
+                sharps_flats: -3,
+                major: false,
+                key_name: "C minor".to_string()
+            }
+        ]);
+    }
+
+    fn meta_event(delta: u32, message: MetaMessage<'static>) -> Event<'static> {
+        Event { delta: VarlenInt::from(delta), kind: EventKind::Meta(message) }
+    }
+
+    #[test]
+    fn test_get_text_events() {
+        let lyrics = vec![
+            meta_event(0, MetaMessage::Lyric(b"Hel")),
+            meta_event(0, MetaMessage::Lyric(b"lo")),
+            meta_event(96, MetaMessage::TimeSignature(4, 2, 24, 8)),
+            meta_event(0, MetaMessage::Marker(b"Chorus"))
+        ];
+        let credits = vec![meta_event(48, MetaMessage::Copyright(b"(c) 1999"))];
+        let tempo_map = vec![TempoChange { tick: 0, micros_per_beat: 500_000 }];
+        let text_events = get_text_events(&[&lyrics, &credits], &tempo_map, 96);
+        let summary: Vec<(u32, &str, &str)> = text_events.iter()
+            .map(|text_event| (text_event.tick, text_event.event_type.as_str(), text_event.text.as_str()))
+            .collect();
+        assert_eq!(summary, vec![
+            (0, "lyric", "Hel"),
+            (0, "lyric", "lo"),
+            (48, "copyright", "(c) 1999"),
+            (96, "marker", "Chorus")
+        ]);
+        assert_eq!(text_events[3].time_seconds, 0.5);
+    }
+
+    #[test]
+    fn test_get_event_log() {
+        let first = vec![
+            meta_event(0, MetaMessage::Tempo(u24::from(500_000))),
+            controller_event(0, 3, 64, 127),
+            Event { delta: VarlenInt::from(0), kind: EventKind::SysEx(&[0x7e, 0x7f, 0x09, 0x01, 0xf7]) },
+            pitch_bend_event(96, 0),
+            meta_event(0, MetaMessage::EndOfTrack)
+        ];
+        let second = vec![
+            meta_event(48, MetaMessage::Lyric(b"La")),
+            meta_event(48, MetaMessage::EndOfTrack)
+        ];
+        let event_log = get_event_log(&[&first, &second]);
+        assert_eq!(event_log.iter().filter(|logged_event| logged_event.track == 0).count(), first.len());
+        let summary: Vec<(u32, usize, &str, Option<u8>)> = event_log.iter()
+            .map(|logged_event| (logged_event.tick, logged_event.track, logged_event.kind.as_str(), logged_event.channel))
+            .collect();
+        assert_eq!(summary, vec![
+            (0, 0, "tempo", None),
+            (0, 0, "controller", Some(3)),
+            (0, 0, "sysex", None),
+            (48, 1, "lyric", None),
+            (96, 0, "pitch_bend", Some(0)),
+            (96, 0, "end_of_track", None),
+            (96, 1, "end_of_track", None)
+        ]);
+        assert_eq!(event_log[0].data, json!({ "micros_per_beat": 500_000, "bpm": 120.0 }));
+        assert_eq!(event_log[1].data, json!({ "controller": 64, "value": 127 }));
+        assert_eq!(event_log[2].data, json!({ "bytes": [0x7e, 0x7f, 0x09, 0x01, 0xf7] }));
+        assert_eq!(event_log[3].data, json!({ "text": "La" }));
+        assert_eq!(event_log[4].data, json!({ "value": -8192 }));
+    }
+
+    #[test]
+    fn test_decode_text_multi_byte() {
+        assert_eq!(decode_text("さくら".as_bytes()), "さくら");
+        assert_eq!(decode_text("Ünïcødé ♪".as_bytes()), "Ünïcødé ♪");
+        // Latin-1 and a UTF-8 sequence that is cut off partway through
+        assert_eq!(decode_text(b"Caf\xe9 au lait"), "Caf\\xE9 au lait");
+        assert_eq!(decode_text(b"\xe3\x81\x95\xe3\x81"), "さ\\xE3\\x81");
+        assert_eq!(decode_text(b""), "");
+    }
+}
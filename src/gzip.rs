@@ -0,0 +1,55 @@
+//! Gzip-compressed MIDI files, like the `.mid.gz` files some libraries export
+
+use std::io::{self, Read};
+use flate2::read::GzDecoder;
+
+/// The first two bytes of every gzip stream
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Whether the bytes look gzip-compressed. A MIDI file always starts with
+/// "MThd", so the two can't be confused.
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::gzip::is_gzip;
+///
+/// assert!(is_gzip(&[0x1f, 0x8b, 0x08]));
+/// assert!(!is_gzip(b"MThd"));
+/// ```
+pub fn is_gzip(bytes: &[u8]) -> bool {
+    bytes.starts_with(&GZIP_MAGIC)
+}
+
+/// Decompresses a whole gzip stream into memory
+pub fn decompress(bytes: &[u8]) -> io::Result<Vec<u8>> {
+    let mut decompressed = Vec::new();
+    GzDecoder::new(bytes).read_to_end(&mut decompressed)?;
+
+    Ok(decompressed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Write;
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+
+    #[test]
+    fn test_decompress_round_trip() {
+        let bytes = include_bytes!("../input/sample.mid");
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(bytes).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        assert!(is_gzip(&compressed));
+        assert!(!is_gzip(bytes));
+        assert_eq!(decompress(&compressed).unwrap(), bytes.to_vec());
+    }
+
+    #[test]
+    fn test_decompress_invalid() {
+        assert!(decompress(&[0x1f, 0x8b, 0x00, 0x00]).is_err());
+    }
+}
@@ -0,0 +1,170 @@
+//! The file-level information from the MIDI header
+
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use midly::{
+    Format,
+    Header,
+    Timing
+};
+
+/// The MIDI header, as written to the output
+///
+/// Metrical files have `ticks_per_quarter_note`, and timecode (SMPTE) files
+/// have `frames_per_second` and `ticks_per_frame` instead.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct MidiHeader {
+    /// The SMF format: 0 (single track), 1 (parallel tracks), or 2 (independent patterns)
+    pub format: u8,
+    pub track_count: u16,
+    /// "metrical" or "timecode"
+    pub timing_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ticks_per_quarter_note: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frames_per_second: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ticks_per_frame: Option<u8>
+}
+
+/// Describes a parsed header. `midly` checks the track count against the
+/// header, so it's taken from the tracks.
+///
+/// # Examples
+///
+/// ```
+/// use midly::{Smf, Event};
+/// use midi2json::get_midi_header;
+///
+/// let bytes = include_bytes!("../input/sample.mid");
+/// let smf: Smf<Vec<Event>> = Smf::read(bytes).unwrap();
+///
+/// let midi_header = get_midi_header(&smf.header, smf.tracks.len());
+/// assert_eq!(midi_header.timing_type, "metrical");
+/// assert_eq!(midi_header.ticks_per_quarter_note, Some(96));
+/// ```
+pub fn get_midi_header(header: &Header, track_count: usize) -> MidiHeader {
+    let format = match header.format {
+        Format::SingleTrack => 0,
+        Format::Parallel => 1,
+        Format::Sequential => 2
+    };
+
+    let (timing_type, ticks_per_quarter_note, frames_per_second, ticks_per_frame) = match header.timing {
+        Timing::Metrical(ticks) => ("metrical", Some(ticks.as_int()), None, None),
+        Timing::Timecode(fps, subframes) => ("timecode", None, Some(fps.as_int()), Some(subframes))
+    };
+
+    MidiHeader {
+        format,
+        track_count: track_count as u16,
+        timing_type: timing_type.to_string(),
+        ticks_per_quarter_note,
+        frames_per_second,
+        ticks_per_frame
+    }
+}
+
+/// Where one chunk of a MIDI file is, like the `MThd` header or an `MTrk` track
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Chunk {
+    /// The chunk's four-letter type
+    pub kind: String,
+    /// The chunk's byte offset from the start of the file, where its type is
+    pub offset: usize,
+    /// The length of the chunk's data, after its eight-byte type and length
+    pub length: u32
+}
+
+/// Finds each chunk in the file's bytes, without parsing what's in them. A
+/// chunk cut off by the end of the file is still listed, but nothing after it.
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::header::get_chunks;
+///
+/// let bytes = include_bytes!("../input/sample.mid");
+/// let chunks = get_chunks(bytes);
+/// assert_eq!(chunks[0].kind, "MThd");
+/// assert_eq!(chunks[1].kind, "MTrk");
+/// assert_eq!(chunks[1].offset, 14);
+/// ```
+pub fn get_chunks(data: &[u8]) -> Vec<Chunk> {
+    let mut chunks = Vec::new();
+    let mut offset = 0;
+    while let Some(chunk_header) = data.get(offset..offset + 8) {
+        let length = u32::from_be_bytes([chunk_header[4], chunk_header[5], chunk_header[6], chunk_header[7]]);
+        chunks.push(Chunk {
+            kind: String::from_utf8_lossy(&chunk_header[..4]).into_owned(),
+            offset,
+            length
+        });
+        offset += 8 + length as usize;
+    }
+
+    chunks
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use midly::Fps;
+    use midly::number::u15;
+
+    #[test]
+    fn test_get_midi_header_formats() {
+        let timing = Timing::Metrical(u15::from(480));
+        let formats: Vec<u8> = [Format::SingleTrack, Format::Parallel, Format::Sequential].iter()
+            .map(|&format| get_midi_header(&Header::new(format, timing, 1), 1).format)
+            .collect();
+        assert_eq!(formats, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_get_midi_header_metrical() {
+        let header = Header::new(Format::Parallel, Timing::Metrical(u15::from(480)), 3);
+        assert_eq!(get_midi_header(&header, 3), MidiHeader {
+            format: 1,
+            track_count: 3,
+            timing_type: "metrical".to_string(),
+            ticks_per_quarter_note: Some(480),
+            frames_per_second: None,
+            ticks_per_frame: None
+        });
+    }
+
+    #[test]
+    fn test_get_midi_header_timecode() {
+        let header = Header::new(Format::SingleTrack, Timing::Timecode(Fps::Fps25, 40), 1);
+        let midi_header = get_midi_header(&header, 1);
+        assert_eq!(midi_header, MidiHeader {
+            format: 0,
+            track_count: 1,
+            timing_type: "timecode".to_string(),
+            ticks_per_quarter_note: None,
+            frames_per_second: Some(25),
+            ticks_per_frame: Some(40)
+        });
+
+        let json = serde_json::to_value(&midi_header).unwrap();
+        assert!(json.get("ticks_per_quarter_note").is_none());
+    }
+
+    #[test]
+    fn test_get_chunks() {
+        let mut bytes = b"MThd\x00\x00\x00\x06\x00\x01\x00\x02\x00\x60".to_vec();
+        bytes.extend_from_slice(b"MTrk\x00\x00\x00\x04\x00\xFF\x2F\x00");
+        bytes.extend_from_slice(b"MTrk\x00\x00\x00\x10\x00\xFF");
+        let chunks: Vec<(String, usize, u32)> = get_chunks(&bytes).into_iter()
+            .map(|chunk| (chunk.kind, chunk.offset, chunk.length))
+            .collect();
+        assert_eq!(chunks, vec![
+            ("MThd".to_string(), 0, 6),
+            ("MTrk".to_string(), 14, 4),
+            // Cut off partway through
+            ("MTrk".to_string(), 26, 16)
+        ]);
+        assert!(get_chunks(b"MTh").is_empty());
+    }
+}
@@ -0,0 +1,140 @@
+//! Random timing jitter, so quantized notes sound less mechanical
+
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::{Note, TimeValue};
+
+/// A small random number generator (SplitMix64) that always gives the same
+/// numbers for the same seed. It's only meant for jitter, not for anything that
+/// needs to be unpredictable.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng { state: seed }
+    }
+
+    /// A generator seeded from the system clock, for when there's no seed to repeat
+    pub fn from_clock() -> Rng {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_nanos() as u64)
+            .unwrap_or(0);
+        Rng::new(nanos)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number from `low` up to but not including `high`
+    pub fn next_f64(&mut self, low: f64, high: f64) -> f64 {
+        // The top 53 bits fill an f64's mantissa exactly
+        let fraction = (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+        low + (high - low) * fraction
+    }
+}
+
+/// Moves each note's start and end by a random amount of at most
+/// `max_offset / 2` either way, in place, then puts the notes back in order of
+/// start time. The end always stays after the start, and the start stays at or
+/// after 0. `max_offset` is in seconds, and only note times in seconds are
+/// moved; notes in ticks or beats are left alone.
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::{humanize_notes, Note, Rng};
+///
+/// let mut notes = vec![Note { time_start: 1.0.into(), time_end: 2.0.into(), ..Default::default() }];
+/// humanize_notes(&mut notes, 0.02, &mut Rng::new(7));
+/// assert!((notes[0].time_start.as_f64() - 1.0).abs() <= 0.01);
+/// assert!((notes[0].time_end.as_f64() - 2.0).abs() <= 0.01);
+/// ```
+pub fn humanize_notes(notes: &mut [Note], max_offset: f64, rng: &mut Rng) {
+    let half = max_offset / 2.0;
+    for note in notes.iter_mut() {
+        let (start, end) = match (note.time_start, note.time_end) {
+            (TimeValue::Seconds(start), TimeValue::Seconds(end)) => (start, end),
+            _ => continue
+        };
+
+        let time_start = (start + rng.next_f64(-half, half)).max(0.0);
+        // The start moved less than `half` past it, so there's always room
+        // for the end to land after it
+        let lowest_end_offset = (time_start - end).max(-half);
+        let time_end = end + half - rng.next_f64(0.0, half - lowest_end_offset);
+
+        note.time_start = TimeValue::Seconds(time_start);
+        note.time_end = TimeValue::Seconds(time_end);
+        if note.duration.is_some() {
+            note.duration = Some(TimeValue::Seconds(time_end - time_start));
+        }
+    }
+
+    notes.sort_by(|a, b| a.time_start.as_f64().total_cmp(&b.time_start.as_f64()));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rng_repeats_for_a_seed() {
+        let numbers = |seed| {
+            let mut rng = Rng::new(seed);
+            (0..4).map(|_| rng.next_u64()).collect::<Vec<u64>>()
+        };
+        assert_eq!(numbers(42), numbers(42));
+        assert_ne!(numbers(42), numbers(43));
+
+        let mut rng = Rng::new(42);
+        assert!((0..1000).map(|_| rng.next_f64(-1.0, 1.0)).all(|number| (-1.0..1.0).contains(&number)));
+    }
+
+    #[test]
+    fn test_humanize_notes() {
+        // Quantized sixteenths, including a very short note and one at time 0
+        let originals: Vec<Note> = (0..200)
+            .map(|index| {
+                let time_start = index as f64 * 0.125;
+                let length = if index % 10 == 3 { 0.001 } else { 0.1 };
+                Note {
+                    time_start: time_start.into(),
+                    time_end: (time_start + length).into(),
+                    duration: Some(length.into()),
+                    pitch_value: index,
+                    ..Default::default()
+                }
+            })
+            .collect();
+        let mut notes = originals.clone();
+        humanize_notes(&mut notes, 0.02, &mut Rng::new(1234));
+
+        let mut moved = 0;
+        for note in &notes {
+            let original = &originals[note.pitch_value as usize];
+            let start_offset = note.time_start.as_f64() - original.time_start.as_f64();
+            let end_offset = note.time_end.as_f64() - original.time_end.as_f64();
+            assert!(start_offset.abs() <= 0.01 && end_offset.abs() <= 0.01);
+            assert!(note.time_end.as_f64() > note.time_start.as_f64() && note.time_start.as_f64() >= 0.0);
+            assert_eq!(note.duration.unwrap().as_f64(), note.time_end.as_f64() - note.time_start.as_f64());
+            if start_offset != 0.0 {
+                moved += 1;
+            }
+        }
+        assert!(moved > 150);
+
+        // The same seed moves them the same way
+        let mut again = originals.clone();
+        humanize_notes(&mut again, 0.02, &mut Rng::new(1234));
+        let times = |notes: &[Note]| notes.iter().map(|note| (note.time_start, note.time_end)).collect::<Vec<_>>();
+        assert_eq!(times(&notes), times(&again));
+    }
+}
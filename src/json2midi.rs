@@ -0,0 +1,345 @@
+//! Converts note information in JSON back into a MIDI file
+//!
+//! `midly` can only read MIDI files, so the file is written out by hand. It's
+//! always an SMF Type 1 file, with the tempo changes in a track of their own.
+
+use crate::{Midi2JsonError, Note, Result, TempoMapEntry, TimeUnit, TimeValue, QUARTER_NOTE, parse_note_info};
+
+/// The ticks per beat to write when neither the options nor the JSON say
+pub const DEFAULT_TICKS_PER_BEAT: u16 = 480;
+/// The tempo to write when neither the options nor the JSON say
+pub const DEFAULT_BPM: f32 = 120.0;
+/// The velocity for notes that don't have one
+const DEFAULT_VELOCITY: u8 = 64;
+/// The longest beat a tempo event can hold, in microseconds
+const MAX_MICROS_PER_BEAT: u32 = 0xff_ffff;
+
+/// Settings for turning JSON back into MIDI
+#[derive(Clone, Debug, Default)]
+pub struct Json2MidiOptions {
+    /// The unit the note times in the JSON are in
+    pub time_unit: TimeUnit,
+    /// The tempo for JSON without a `tempo_map`
+    pub bpm: Option<f32>,
+    /// The resolution of the MIDI file, instead of the one in the JSON's
+    /// `midi_header` or `DEFAULT_TICKS_PER_BEAT`
+    pub ticks_per_beat: Option<u16>
+}
+
+/// A stretch of constant tempo, for converting seconds to beats
+struct TempoSegment {
+    time_seconds: f64,
+    beat: f64,
+    bpm: f64
+}
+
+/// Converts note information in JSON, like `midi2json` writes, back into the
+/// bytes of a MIDI file.
+///
+/// The notes can be merged, split up by track, or split up by channel; each
+/// track or channel gets a track of its own. The tempo comes from the JSON's
+/// `tempo_map` if it has one, or from the options.
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::{process_bytes, Options};
+/// use midi2json::json2midi::json_to_midi;
+///
+/// let json = r#"{"notes": [{"time_start": 0.0, "time_end": 0.5, "pitch_value": 60, "channel": 0, "velocity": 100}]}"#;
+/// let bytes = json_to_midi(json, &Default::default()).unwrap();
+///
+/// let note_info = process_bytes(&bytes, &Options::default()).unwrap();
+/// let note = note_info.all_notes().next().unwrap();
+/// assert_eq!((note.pitch_value, note.time_end.as_f64()), (60, 0.5));
+/// ```
+pub fn json_to_midi(json: &str, options: &Json2MidiOptions) -> Result<Vec<u8>> {
+    let note_info = parse_note_info(json)?;
+
+    let ticks_per_beat = options.ticks_per_beat
+        .or_else(|| note_info.midi_header.as_ref().and_then(|header| header.ticks_per_quarter_note))
+        .unwrap_or(DEFAULT_TICKS_PER_BEAT);
+    let tempo_map = note_info.tempo_map.unwrap_or_default();
+    let segments = get_tempo_segments(&tempo_map, options.bpm.unwrap_or(DEFAULT_BPM) as f64);
+    let to_ticks = |time: f64| -> u32 {
+        let beats = match options.time_unit {
+            TimeUnit::Ticks => return time.round().max(0.0) as u32,
+            TimeUnit::Beats(subdivision) => time * QUARTER_NOTE as f64 / subdivision as f64,
+            TimeUnit::Seconds => {
+                let segment = segments.iter()
+                    .rev()
+                    .find(|segment| segment.time_seconds <= time)
+                    .unwrap_or(&segments[0]);
+                segment.beat + (time - segment.time_seconds) * segment.bpm / 60.0
+            }
+        };
+        (beats * ticks_per_beat as f64).round().max(0.0) as u32
+    };
+
+    let mut tempo_events: Vec<(u32, u8, Vec<u8>)> = if options.time_unit == TimeUnit::Ticks && !tempo_map.is_empty() {
+        // The ticks are already in the original file's resolution
+        tempo_map.iter()
+            .map(|tempo| Ok((tempo.tick, 0, tempo_event(tempo.bpm)?)))
+            .collect::<Result<_>>()?
+    } else {
+        segments.iter()
+            .map(|segment| Ok(((segment.beat * ticks_per_beat as f64).round() as u32, 0, tempo_event(segment.bpm)?)))
+            .collect::<Result<_>>()?
+    };
+    let mut tracks = vec![write_track(&mut tempo_events)];
+
+    let note_lists: Vec<(Option<String>, Vec<Note>)> = if let Some(tracks) = note_info.tracks {
+        tracks.into_iter().map(|track| (track.name, track.notes)).collect()
+    } else if let Some(channels) = note_info.channels {
+        channels.into_iter().map(|channel| (None, channel.notes)).collect()
+    } else {
+        vec![(None, note_info.notes.unwrap_or_default())]
+    };
+    for (name, notes) in note_lists {
+        let mut events = Vec::new();
+        if let Some(name) = name {
+            let mut event = vec![0xff, 0x03];
+            write_varlen(&mut event, name.len() as u32);
+            event.extend_from_slice(name.as_bytes());
+            events.push((0, 0, event));
+        }
+        for note in notes {
+            if note.pitch_value > 127 || note.channel > 15 {
+                return Err(Midi2JsonError::InvalidArgument(format!(
+                    "Note {} on channel {} is outside the MIDI range",
+                    note.pitch_value, note.channel
+                )));
+            }
+            let pitch = note.pitch_value as u8;
+            let start = to_ticks(note_time(note.time_start, note.time_start_ticks, options.time_unit)?);
+            let end = to_ticks(note_time(note.time_end, note.time_end_ticks, options.time_unit)?).max(start);
+            // MIDI has no NoteOn with velocity 0, so that's a note read in without one
+            let velocity = match note.velocity {
+                0 => DEFAULT_VELOCITY,
+                velocity => velocity.min(127)
+            };
+            // A note without a release velocity was ended by a NoteOn with velocity 0
+            let note_off = match note.release_velocity {
+                Some(velocity) => vec![0x80 | note.channel, pitch, velocity.min(127)],
+                None => vec![0x90 | note.channel, pitch, 0]
+            };
+            // Notes end before others start on the same tick, so re-triggered keys come out right
+            events.push((start, 2, vec![0x90 | note.channel, pitch, velocity]));
+            events.push((end, 1, note_off));
+        }
+        tracks.push(write_track(&mut events));
+    }
+
+    let mut bytes = b"MThd\x00\x00\x00\x06\x00\x01".to_vec();
+    bytes.extend_from_slice(&(tracks.len() as u16).to_be_bytes());
+    bytes.extend_from_slice(&ticks_per_beat.to_be_bytes());
+    for track in tracks {
+        bytes.extend_from_slice(b"MTrk");
+        bytes.extend_from_slice(&(track.len() as u32).to_be_bytes());
+        bytes.extend(track);
+    }
+
+    Ok(bytes)
+}
+
+/// Works out where each tempo change falls in beats, from the times in seconds
+/// that `midi2json` writes. Without a tempo map, the whole file is at `bpm`.
+fn get_tempo_segments(tempo_map: &[TempoMapEntry], bpm: f64) -> Vec<TempoSegment> {
+    let mut segments: Vec<TempoSegment> = Vec::new();
+    for tempo in tempo_map {
+        let beat = match segments.last() {
+            Some(previous) => previous.beat + (tempo.time_seconds - previous.time_seconds) * previous.bpm / 60.0,
+            None => 0.0
+        };
+        segments.push(TempoSegment { time_seconds: tempo.time_seconds, beat, bpm: tempo.bpm });
+    }
+    if segments.is_empty() {
+        segments.push(TempoSegment { time_seconds: 0.0, beat: 0.0, bpm });
+    }
+
+    segments
+}
+
+/// A note's time, or its time in ticks when that's all it has and the JSON is
+/// read in ticks
+fn note_time(time: TimeValue, ticks: Option<u32>, time_unit: TimeUnit) -> Result<f64> {
+    match (time, ticks) {
+        (TimeValue::Unknown, Some(ticks)) if time_unit == TimeUnit::Ticks => Ok(ticks as f64),
+        (TimeValue::Unknown, _) => Err(Midi2JsonError::InvalidArgument(
+            "A note has no time, or only one in ticks when the times aren't read as ticks".to_string()
+        )),
+        (time, _) => Ok(time.as_f64())
+    }
+}
+
+/// A tempo event, which holds the microseconds per beat in 24 bits, so tempos
+/// slower than about 3.58 BPM can't be written
+fn tempo_event(bpm: f64) -> Result<Vec<u8>> {
+    let micros_per_beat = (60_000_000.0 / bpm).round();
+    if !(1.0..=MAX_MICROS_PER_BEAT as f64).contains(&micros_per_beat) {
+        return Err(Midi2JsonError::InvalidArgument(
+            format!("A tempo of {} BPM can't be written to a MIDI file, which needs at least about 3.58 BPM", bpm)
+        ));
+    }
+
+    let mut event = vec![0xff, 0x51, 0x03];
+    event.extend_from_slice(&(micros_per_beat as u32).to_be_bytes()[1..]);
+    Ok(event)
+}
+
+/// Writes the events of a track, sorted by tick and then by their order key,
+/// with the end of track event after them
+fn write_track(events: &mut [(u32, u8, Vec<u8>)]) -> Vec<u8> {
+    events.sort_by_key(|(tick, order, _)| (*tick, *order));
+
+    let mut track = Vec::new();
+    let mut cur_time = 0;
+    for (tick, _, event) in events.iter() {
+        write_varlen(&mut track, tick - cur_time);
+        track.extend_from_slice(event);
+        cur_time = *tick;
+    }
+    track.extend_from_slice(&[0x00, 0xff, 0x2f, 0x00]);
+
+    track
+}
+
+/// Writes a MIDI variable-length number: seven bits at a time, most
+/// significant first, with the high bit set on all but the last
+fn write_varlen(bytes: &mut Vec<u8>, value: u32) {
+    let mut groups = vec![(value & 0x7f) as u8];
+    let mut rest = value >> 7;
+    while rest > 0 {
+        groups.push((rest & 0x7f) as u8 | 0x80);
+        rest >>= 7;
+    }
+    bytes.extend(groups.iter().rev());
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{process_bytes, Options};
+
+    #[test]
+    fn test_write_varlen() {
+        let encode = |value| {
+            let mut bytes = Vec::new();
+            write_varlen(&mut bytes, value);
+            bytes
+        };
+        assert_eq!(encode(0), vec![0x00]);
+        assert_eq!(encode(0x7f), vec![0x7f]);
+        assert_eq!(encode(0x80), vec![0x81, 0x00]);
+        assert_eq!(encode(0x0fffffff), vec![0xff, 0xff, 0xff, 0x7f]);
+    }
+
+    #[test]
+    fn test_json_to_midi_tempo_map() {
+        // A beat at 120 BPM, then a beat at 60 BPM
+        let json = r#"{
+            "tempo_map": [
+                {"tick": 0, "bpm": 120.0, "time_seconds": 0.0},
+                {"tick": 96, "bpm": 60.0, "time_seconds": 0.5}
+            ],
+            "tracks": [
+                {"index": 0, "name": "Lead", "notes": [
+                    {"time_start": 0.0, "time_end": 0.5, "pitch_value": 60, "channel": 0, "velocity": 90, "release_velocity": 40},
+                    {"time_start": 0.5, "time_end": 1.5, "pitch_value": 62, "channel": 0, "velocity": 80, "release_velocity": null}
+                ]},
+                {"index": 1, "name": null, "notes": [
+                    {"time_start": 0.25, "time_end": 1.0, "pitch_value": 36, "channel": 9, "velocity": 100}
+                ]}
+            ]
+        }"#;
+        let bytes = json_to_midi(json, &Json2MidiOptions { ticks_per_beat: Some(96), ..Default::default() }).unwrap();
+        let note_info = process_bytes(&bytes, &Options { include_tempo_map: true, ..Default::default() }).unwrap();
+
+        let tempo_map: Vec<(u32, f64)> = note_info.tempo_map.unwrap().iter()
+            .map(|tempo| (tempo.tick, tempo.bpm))
+            .collect();
+        assert_eq!(tempo_map, vec![(0, 120.0), (96, 60.0)]);
+
+        let tracks = note_info.tracks.unwrap();
+        // The tempo track comes first
+        assert_eq!(tracks.len(), 3);
+        assert!(tracks[0].notes.is_empty());
+        assert_eq!(tracks[1].name, Some("Lead".to_string()));
+        let notes: Vec<(f64, f64, u32, u8, Option<u8>)> = tracks[1].notes.iter()
+            .map(|note| (note.time_start.as_f64(), note.time_end.as_f64(), note.pitch_value, note.velocity, note.release_velocity))
+            .collect();
+        assert_eq!(notes, vec![(0.0, 0.5, 60, 90, Some(40)), (0.5, 1.5, 62, 80, None)]);
+        assert_eq!(tracks[2].notes[0].channel, 9);
+        assert_eq!(tracks[2].notes[0].time_end.as_f64(), 1.0);
+    }
+
+    #[test]
+    fn test_json_to_midi_retrigger() {
+        // The second note starts on the tick the first one ends
+        let json = r#"{"notes": [
+            {"time_start": 0, "time_end": 96, "pitch_value": 60, "velocity": 100},
+            {"time_start": 96, "time_end": 192, "pitch_value": 60, "velocity": 100}
+        ]}"#;
+        let options = Json2MidiOptions {
+            time_unit: TimeUnit::Ticks,
+            ticks_per_beat: Some(96),
+            ..Default::default()
+        };
+        let bytes = json_to_midi(json, &options).unwrap();
+        let note_info = process_bytes(&bytes, &Options {
+            merge_tracks: true,
+            ..Default::default()
+        }).unwrap();
+        let notes: Vec<(f64, f64)> = note_info.notes.unwrap().iter()
+            .map(|note| (note.time_start.as_f64(), note.time_end.as_f64()))
+            .collect();
+        assert_eq!(notes, vec![(0.0, 0.5), (0.5, 1.0)]);
+    }
+
+    #[test]
+    fn test_json_to_midi_ticks_only() {
+        // Like `--no-bpm-required` writes, with no velocity
+        let json = r#"{"notes": [{"time_start_ticks": 96, "time_end_ticks": 192, "pitch_value": 60}]}"#;
+        let options = Json2MidiOptions {
+            time_unit: TimeUnit::Ticks,
+            ticks_per_beat: Some(96),
+            ..Default::default()
+        };
+        let bytes = json_to_midi(json, &options).unwrap();
+        let note_info = process_bytes(&bytes, &Options { merge_tracks: true, ..Default::default() }).unwrap();
+        let note = &note_info.notes.unwrap()[0];
+        assert_eq!((note.time_start.as_f64(), note.time_end.as_f64(), note.velocity), (0.5, 1.0, DEFAULT_VELOCITY));
+
+        assert!(matches!(json_to_midi(json, &Default::default()), Err(Midi2JsonError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn test_json_to_midi_slowest_tempo() {
+        let json = |bpm: f64| format!(
+            r#"{{"tempo_map": [{{"tick": 0, "bpm": {}, "time_seconds": 0.0}}], "notes": [{{"time_start": 0.0, "time_end": 2.0, "pitch_value": 60}}]}}"#,
+            bpm
+        );
+
+        // The longest beat that fits in 24 bits, and then one microsecond longer
+        let slowest = 60_000_000.0 / 16_777_215.0;
+        let bytes = json_to_midi(&json(slowest), &Default::default()).unwrap();
+        let note_info = process_bytes(&bytes, &Options { merge_tracks: true, ..Default::default() }).unwrap();
+        // A tick is about 35 ms at this tempo, so the end is rounded to within half of one
+        assert!((note_info.notes.unwrap()[0].time_end.as_f64() - 2.0).abs() < 0.018);
+        assert!(matches!(json_to_midi(&json(60_000_000.0 / 16_777_216.0), &Default::default()), Err(Midi2JsonError::InvalidArgument(_))));
+
+        let options = |bpm| Json2MidiOptions { bpm: Some(bpm), ..Default::default() };
+        assert!(json_to_midi(&json(120.0).replace(r#""tempo_map""#, r#""ignored""#), &options(3.6)).is_ok());
+        assert!(matches!(
+            json_to_midi(&json(120.0).replace(r#""tempo_map""#, r#""ignored""#), &options(2.0)),
+            Err(Midi2JsonError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn test_json_to_midi_invalid() {
+        assert!(matches!(json_to_midi("{\"notes\": 5}", &Default::default()), Err(Midi2JsonError::JsonError(_))));
+        let json = r#"{"notes": [{"time_start": 0, "time_end": 1, "pitch_value": 128}]}"#;
+        assert!(matches!(json_to_midi(json, &Default::default()), Err(Midi2JsonError::InvalidArgument(_))));
+    }
+}
@@ -0,0 +1,2288 @@
+//! Converts MIDI files into note information.
+//!
+//! The `midi2json` binary is a thin wrapper around `process_bytes`, which
+//! converts a whole file in memory. The functions it's built from can also be
+//! used directly with tracks parsed by `midly`.
+
+pub mod chords;
+pub mod error;
+pub mod events;
+pub mod gzip;
+pub mod header;
+pub mod humanize;
+pub mod json2midi;
+pub mod meter;
+pub mod names;
+pub mod output;
+pub mod overlaps;
+pub mod piano_roll;
+pub mod precision;
+pub mod process;
+pub mod segments;
+pub mod sort;
+pub mod stats;
+pub mod time;
+pub mod typescript;
+
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::time::Instant;
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use midly::{
+    Event,
+    EventKind,
+    MidiMessage
+};
+
+pub use crate::chords::{Chord, group_chords};
+pub use crate::error::{Midi2JsonError, Result};
+pub use crate::events::{
+    ControlChange,
+    KeySignature,
+    LoggedEvent,
+    PitchBend,
+    ProgramChange,
+    TextEvent,
+    get_control_changes,
+    get_event_log,
+    get_key_signatures,
+    get_pitch_bends,
+    get_program_changes,
+    get_text_events,
+    get_track_name
+};
+pub use crate::header::{MidiHeader, get_midi_header};
+pub use crate::humanize::{Rng, humanize_notes};
+pub use crate::names::PitchSpeller;
+pub use crate::overlaps::{Overlap, classify_articulation, find_overlaps, merge_legato, remove_duplicates, resolve_overlaps};
+pub use crate::process::{
+    Options,
+    get_measure_starts,
+    process_bytes,
+    process_bytes_with_log,
+    process_files,
+    process_files_with_log,
+    read_event_log,
+    stream_bytes
+};
+pub use crate::segments::{remove_long_notes, split_long_notes};
+pub use crate::sort::{SortDirection, SortKey, sort_notes};
+pub use crate::stats::{
+    DensitySample,
+    InterOnsetIntervals,
+    NoteDensity,
+    Statistics,
+    get_density_timeline,
+    get_inter_onset_intervals,
+    get_inter_onset_intervals_per_pitch,
+    get_note_density,
+    get_pitch_entropy,
+    get_pitch_histogram,
+    get_statistics
+};
+pub use crate::meter::{MeasureMap, TimeSignature, get_time_signatures};
+pub use crate::time::{
+    TempoChange,
+    TempoMapEntry,
+    TimeUnit,
+    TimeValue,
+    QUARTER_NOTE,
+    get_tempo_map,
+    get_tempo_map_entries,
+    get_time,
+    get_time_seconds,
+    get_time_seconds_tempo_map,
+    get_time_seconds_timecode,
+    get_timecode_tempo_map,
+    quantize_ticks,
+    swing_delay_ticks
+};
+
+/// A single note, with its start and end times in seconds (or ticks)
+///
+/// Any field missing from JSON being read back in gets its default.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct Note {
+    /// Left out if it's unknown, with `time_start_ticks` instead
+    #[serde(default = "unknown_time", skip_serializing_if = "TimeValue::is_unknown")]
+    pub time_start: TimeValue,
+    #[serde(default = "unknown_time", skip_serializing_if = "TimeValue::is_unknown")]
+    pub time_end: TimeValue,
+    /// The start in ticks, when there's no tempo to work out `time_start` from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time_start_ticks: Option<u32>,
+    /// The end in ticks, when there's no tempo to work out `time_end` from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time_end_ticks: Option<u32>,
+    /// `time_end - time_start`, or `None` if the note never ended
+    pub duration: Option<TimeValue>,
+    /// The time since the previous note started, with relative times
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delta_start: Option<TimeValue>,
+    /// The 1-indexed measure the note starts in, if requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub measure: Option<u32>,
+    /// The beat within the measure the note starts on, counting from 1.0
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub beat: Option<f64>,
+    pub pitch_value: u32,
+    /// The pitch in scientific pitch notation (e.g. "C4"), if requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pitch_name: Option<String>,
+    /// The pitch's position within its octave (0-11, where 0 is C), if requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pitch_class: Option<u8>,
+    /// The pitch class's name (e.g. "C#"), if requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pitch_class_name: Option<String>,
+    /// The equal-tempered frequency of the pitch, if requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_hz: Option<f64>,
+    /// The General MIDI percussion sound, if requested and the note is on the drum channel
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub drum_name: Option<String>,
+    /// The MIDI channel (0-15) the note was played on
+    pub channel: u8,
+    /// The NoteOn velocity (1-127)
+    pub velocity: u8,
+    /// The NoteOff velocity, if the note was ended by a NoteOff message
+    pub release_velocity: Option<u8>,
+    /// The NoteOn velocity scaled to [0.0, 1.0], if requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub velocity_f: Option<f64>,
+    /// Whether the note was still held when its track ended, only written when true
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+    /// "legato" or "staccato", by how soon the next note of the same pitch
+    /// starts, if requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub articulation: Option<String>,
+    /// The input file the note came from, when several files are merged
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_file: Option<String>,
+    /// The track the note came from, if requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_track: Option<SourceTrack>
+}
+
+/// The track a note came from
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum SourceTrack {
+    /// The track's position in the file, from 0
+    Index(usize),
+    /// `"{file_index}:{track_index}"` when several files are merged, both from 0
+    File(String)
+}
+
+impl SourceTrack {
+    /// The track of one of several merged files
+    pub fn in_file(file_index: usize, track_index: usize) -> SourceTrack {
+        SourceTrack::File(format!("{}:{}", file_index, track_index))
+    }
+}
+
+/// Notes read back in without a time leave it unknown, as it was written
+fn unknown_time() -> TimeValue {
+    TimeValue::Unknown
+}
+
+impl Note {
+    /// Completes a note that was started earlier
+    fn ended_at(self, time_end: TimeValue) -> Note {
+        Note {
+            time_end,
+            duration: Some(time_end.since(self.time_start)),
+            ..self
+        }
+    }
+
+    /// Moves the note's start and end, in ticks, to `time_start_ticks` and
+    /// `time_end_ticks`, leaving `time_start` and `time_end` unknown. The
+    /// duration stays in ticks.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use midi2json::{Note, TimeValue};
+    ///
+    /// let mut note = Note { time_start: TimeValue::Ticks(96), time_end: TimeValue::Ticks(192), ..Default::default() };
+    /// note.move_times_to_ticks();
+    /// assert_eq!((note.time_start_ticks, note.time_end_ticks), (Some(96), Some(192)));
+    /// assert!(note.time_start.is_unknown());
+    /// ```
+    pub fn move_times_to_ticks(&mut self) {
+        if let (TimeValue::Ticks(time_start), TimeValue::Ticks(time_end)) = (self.time_start, self.time_end) {
+            self.time_start_ticks = Some(time_start);
+            self.time_end_ticks = Some(time_end);
+            self.time_start = TimeValue::Unknown;
+            self.time_end = TimeValue::Unknown;
+        }
+    }
+
+    /// The start to put notes in order by: `time_start`, or `time_start_ticks`
+    /// once the start has been moved there
+    fn start_order(&self) -> f64 {
+        match (self.time_start, self.time_start_ticks) {
+            (TimeValue::Unknown, Some(ticks)) => ticks as f64,
+            (time_start, _) => time_start.as_f64()
+        }
+    }
+}
+
+/// The root of the JSON output
+///
+/// Notes are either merged into a single `notes` array, split up by track, or
+/// split up by channel for SMF Type 0 files. The other fields are only filled
+/// in when asked for.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct NoteInfo {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub midi_header: Option<MidiHeader>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notes: Option<Vec<Note>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tracks: Option<Vec<TrackNotes>>,
+    /// The notes of each pattern of an SMF Type 2 file, which each have their
+    /// own timeline from time 0, so they're never merged
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub patterns: Option<Vec<TrackNotes>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channels: Option<Vec<ChannelNotes>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chords: Option<Vec<Chord>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tempo_map: Option<Vec<TempoMapEntry>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time_signatures: Option<Vec<TimeSignature>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub control_changes: Option<Vec<ControlChange>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub program_changes: Option<Vec<ProgramChange>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pitch_bends: Option<Vec<PitchBend>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_signatures: Option<Vec<KeySignature>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text_events: Option<Vec<TextEvent>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub overlaps: Option<Vec<Overlap>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note_density: Option<Vec<NoteDensity>>,
+    /// How many notes a second start in each window of time, if requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub density_timeline: Option<Vec<DensitySample>>,
+    /// How many notes there are of each pitch, keyed by MIDI number or name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pitch_histogram: Option<BTreeMap<String, u32>>,
+    /// The time between each note's start and the next one's, if requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inter_onset_intervals: Option<InterOnsetIntervals>,
+    /// How much was subtracted from every note time by `normalize_start`, in
+    /// the same unit as the note times
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset_seconds: Option<TimeValue>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub statistics: Option<Statistics>
+}
+
+impl NoteInfo {
+    /// Iterates over every note, whichever way they are grouped
+    pub fn all_notes(&self) -> impl Iterator<Item = &Note> {
+        let merged = self.notes.iter().flatten();
+        let by_track = self.tracks.iter().chain(&self.patterns).flatten().flat_map(|track| &track.notes);
+        let by_channel = self.channels.iter().flatten().flat_map(|channel| &channel.notes);
+        merged.chain(by_track).chain(by_channel)
+    }
+
+    fn all_notes_mut(&mut self) -> impl Iterator<Item = &mut Note> {
+        self.note_lists_mut().flatten()
+    }
+
+    /// The merged notes, or each track's, pattern's, or channel's notes
+    fn note_lists_mut(&mut self) -> impl Iterator<Item = &mut Vec<Note>> {
+        let merged = self.notes.iter_mut();
+        let by_track = self.tracks.iter_mut().chain(&mut self.patterns).flatten().map(|track| &mut track.notes);
+        let by_channel = self.channels.iter_mut().flatten().map(|channel| &mut channel.notes);
+        merged.chain(by_track).chain(by_channel)
+    }
+
+    /// Every time in the notes, chords, overlaps, and inter-onset intervals
+    fn times_mut(&mut self) -> Vec<&mut TimeValue> {
+        let NoteInfo { notes, tracks, patterns, channels, chords, overlaps, inter_onset_intervals, offset_seconds, .. } = self;
+        let mut times = Vec::new();
+        let by_track = tracks.iter_mut().chain(patterns).flatten().flat_map(|track| &mut track.notes);
+        let by_channel = channels.iter_mut().flatten().flat_map(|channel| &mut channel.notes);
+        let mut chord_notes = Vec::new();
+        for chord in chords.iter_mut().flatten() {
+            times.push(&mut chord.time_start);
+            times.push(&mut chord.time_end);
+            chord_notes.extend(&mut chord.notes);
+        }
+        for note in notes.iter_mut().flatten().chain(by_track).chain(by_channel).chain(chord_notes) {
+            times.push(&mut note.time_start);
+            times.push(&mut note.time_end);
+            times.extend(note.duration.as_mut());
+            times.extend(note.delta_start.as_mut());
+        }
+        for overlap in overlaps.iter_mut().flatten() {
+            times.extend([&mut overlap.first_start, &mut overlap.first_end, &mut overlap.second_start, &mut overlap.second_end]);
+        }
+        match inter_onset_intervals {
+            Some(InterOnsetIntervals::All(intervals)) => times.extend(intervals),
+            Some(InterOnsetIntervals::PerPitch(intervals)) => times.extend(intervals.values_mut().flatten()),
+            None => {}
+        }
+        times.extend(offset_seconds.as_mut());
+        times
+    }
+
+    /// Moves every note's times to its tick fields, including the notes in
+    /// chords. See `Note::move_times_to_ticks`.
+    pub fn move_times_to_ticks(&mut self) {
+        for note in self.all_notes_mut() {
+            note.move_times_to_ticks();
+        }
+        for note in self.chords.iter_mut().flatten().flat_map(|chord| &mut chord.notes) {
+            note.move_times_to_ticks();
+        }
+    }
+
+    /// Shifts every note earlier so the first one starts at 0, and records the
+    /// offset as `offset_seconds`.
+    ///
+    /// Only the notes are shifted; the other event lists keep their times.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use midi2json::{Note, NoteInfo};
+    ///
+    /// let mut note_info = NoteInfo {
+    ///     notes: Some(vec![Note { time_start: 2.0.into(), time_end: 3.0.into(), ..Default::default() }]),
+    ///     ..Default::default()
+    /// };
+    /// note_info.normalize_start();
+    /// assert_eq!(note_info.offset_seconds, Some(2.0.into()));
+    /// assert_eq!(note_info.all_notes().next().unwrap().time_end.as_f64(), 1.0);
+    /// ```
+    pub fn normalize_start(&mut self) {
+        let offset = self.all_notes()
+            .map(|note| note.time_start)
+            .min_by(|a, b| a.as_f64().partial_cmp(&b.as_f64()).unwrap());
+
+        if let Some(offset) = offset {
+            for note in self.all_notes_mut() {
+                note.time_start = note.time_start.since(offset);
+                note.time_end = note.time_end.since(offset);
+            }
+        }
+        self.offset_seconds = offset;
+    }
+
+    /// Removes exact duplicate notes, separately for each track or channel
+    /// when the notes are split up, and returns how many were removed. See
+    /// `remove_duplicates`.
+    pub fn remove_duplicates(&mut self) -> usize {
+        self.note_lists_mut()
+            .map(remove_duplicates)
+            .sum()
+    }
+
+    /// Merges runs of legato notes, separately for each track or channel when
+    /// the notes are split up. See `merge_legato`.
+    pub fn merge_legato(&mut self, gap: f64) {
+        for notes in self.note_lists_mut() {
+            merge_legato(notes, gap);
+        }
+    }
+
+    /// Splits the notes longer than `max_duration` into equal segments. See
+    /// `split_long_notes`.
+    pub fn split_long_notes(&mut self, max_duration: f64) {
+        for notes in self.note_lists_mut() {
+            split_long_notes(notes, max_duration);
+        }
+    }
+
+    /// Removes the notes longer than `max_duration`. See `remove_long_notes`.
+    pub fn remove_long_notes(&mut self, max_duration: f64) {
+        for notes in self.note_lists_mut() {
+            remove_long_notes(notes, max_duration);
+        }
+    }
+
+    /// Moves the notes' times randomly by at most `max_offset / 2` seconds, and
+    /// puts them back in order, separately for each track or channel when the
+    /// notes are split up. See `humanize_notes`.
+    pub fn humanize(&mut self, max_offset: f64, rng: &mut Rng) {
+        for notes in self.note_lists_mut() {
+            humanize_notes(notes, max_offset, rng);
+        }
+    }
+
+    /// Splits the notes up into a `NoteInfo` for each channel that has any,
+    /// in order of channel, with the channel's notes from every track merged
+    /// into `notes` in order of start time. With `keep_other_fields`, each one also gets a copy of
+    /// every other field, like the tempo map and statistics, as they are for
+    /// the whole file.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use midi2json::{Note, NoteInfo};
+    ///
+    /// let note_info = NoteInfo {
+    ///     notes: Some(vec![
+    ///         Note { channel: 9, pitch_value: 36, ..Default::default() },
+    ///         Note { channel: 0, pitch_value: 60, ..Default::default() }
+    ///     ]),
+    ///     offset_seconds: Some(1.0.into()),
+    ///     ..Default::default()
+    /// };
+    /// let channels = note_info.split_channels(false);
+    /// assert_eq!(channels.len(), 2);
+    /// assert_eq!(channels[1].0, 9);
+    /// assert_eq!(channels[1].1.notes.as_ref().unwrap()[0].pitch_value, 36);
+    /// assert_eq!(channels[1].1.offset_seconds, None);
+    /// ```
+    pub fn split_channels(&self, keep_other_fields: bool) -> Vec<(u8, NoteInfo)> {
+        // Merged notes keep their order, in case they've been sorted
+        let notes = match &self.notes {
+            Some(notes) => notes.clone(),
+            None => merge_notes(vec![self.all_notes().cloned().collect()])
+        };
+        split_by_channel(notes).into_iter()
+            .map(|channel_notes| {
+                let notes = Some(channel_notes.notes);
+                let note_info = if keep_other_fields {
+                    NoteInfo { notes, tracks: None, channels: None, ..self.clone() }
+                } else {
+                    NoteInfo { notes, ..Default::default() }
+                };
+                (channel_notes.channel, note_info)
+            })
+            .collect()
+    }
+
+    /// Splits the notes up into a `NoteInfo` for each track, in order, each
+    /// with just that track in `tracks`. Tracks without any notes are left out
+    /// unless `include_empty` is set. With `keep_other_fields`, each one also
+    /// gets a copy of every other field, as with `split_channels`.
+    ///
+    /// Only notes that are split up by track can be split this way; merged
+    /// notes give an empty list.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use midi2json::{Note, NoteInfo, TrackNotes};
+    ///
+    /// let note_info = NoteInfo {
+    ///     tracks: Some(vec![
+    ///         TrackNotes { index: 0, name: Some("Tempo".to_string()), notes: vec![] },
+    ///         TrackNotes { index: 1, name: None, notes: vec![Note::default()] }
+    ///     ]),
+    ///     ..Default::default()
+    /// };
+    /// let tracks = note_info.split_tracks(false, false);
+    /// assert_eq!(tracks.len(), 1);
+    /// assert_eq!(tracks[0].tracks.as_ref().unwrap()[0].index, 1);
+    /// assert_eq!(note_info.split_tracks(true, false).len(), 2);
+    /// ```
+    pub fn split_tracks(&self, include_empty: bool, keep_other_fields: bool) -> Vec<NoteInfo> {
+        self.tracks.iter().flatten()
+            .filter(|track| include_empty || !track.notes.is_empty())
+            .map(|track| {
+                let tracks = Some(vec![track.clone()]);
+                if keep_other_fields {
+                    NoteInfo { notes: None, tracks, channels: None, ..self.clone() }
+                } else {
+                    NoteInfo { tracks, ..Default::default() }
+                }
+            })
+            .collect()
+    }
+
+    /// Tags each note as legato or staccato, separately for each track or
+    /// channel when the notes are split up. See `classify_articulation`.
+    pub fn classify_articulation(&mut self, threshold: f64) {
+        for notes in self.note_lists_mut() {
+            classify_articulation(notes, threshold);
+        }
+    }
+
+    /// Fills in `overlaps` with the overlapping notes of each track (or of the
+    /// merged notes), then shortens them so they no longer overlap if `resolve`
+    /// is set. See `find_overlaps` and `resolve_overlaps`.
+    pub fn check_overlaps(&mut self, resolve: bool) {
+        let mut overlaps = Vec::new();
+        // Overlaps are always within a channel, so notes split up by channel
+        // are no different from merged ones here
+        let by_channel = self.channels.iter_mut().flatten().map(|channel| &mut channel.notes);
+        for notes in self.notes.iter_mut().chain(by_channel) {
+            overlaps.extend(find_overlaps(notes));
+            if resolve {
+                resolve_overlaps(notes);
+            }
+        }
+        // Only one of these is set, so the positions count from 0 either way
+        for (index, track) in self.tracks.iter_mut().chain(&mut self.patterns).flatten().enumerate() {
+            overlaps.extend(find_overlaps(&track.notes).into_iter().map(|overlap| Overlap {
+                track: Some(index),
+                ..overlap
+            }));
+            if resolve {
+                resolve_overlaps(&mut track.notes);
+            }
+        }
+        self.overlaps = Some(overlaps);
+    }
+
+    /// Sorts the notes, separately for each track or channel when the notes
+    /// are split up. See `sort_notes`.
+    pub fn sort_notes(&mut self, key: SortKey, direction: Option<SortDirection>) {
+        for notes in self.note_lists_mut() {
+            sort_notes(notes, key, direction);
+        }
+    }
+
+    /// Adds each note's `delta_start`, separately for each track or channel
+    /// when the notes are split up. See `set_relative_times`.
+    pub fn set_relative_times(&mut self) {
+        for notes in self.note_lists_mut() {
+            set_relative_times(notes);
+        }
+    }
+}
+
+/// Reads note information back in from JSON, like `midi2json` writes.
+///
+/// The JSON doesn't say which unit the times are in, so they're read as ticks
+/// if every one is a whole number, and otherwise all as seconds, including
+/// whole numbers like `2`. Any other field a note is missing gets its default,
+/// and a missing time is unknown.
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::{parse_note_info, TimeValue};
+///
+/// let note_info = parse_note_info(r#"{"notes": [{"time_start": 0.5, "time_end": 1.0, "pitch_value": 60}]}"#).unwrap();
+/// let note = note_info.all_notes().next().unwrap();
+/// assert_eq!((note.time_start, note.pitch_value, note.velocity), (TimeValue::Seconds(0.5), 60, 0));
+/// ```
+pub fn parse_note_info(json: &str) -> Result<NoteInfo> {
+    let mut note_info: NoteInfo = serde_json::from_str(json)
+        .map_err(|error| Midi2JsonError::JsonError(error.to_string()))?;
+
+    // Each time was read on its own, so whole seconds came out as ticks
+    let mut times = note_info.times_mut();
+    if times.iter().any(|time| matches!(time, TimeValue::Seconds(_))) {
+        for time in times.iter_mut() {
+            if let TimeValue::Ticks(ticks) = **time {
+                **time = TimeValue::Seconds(ticks as f64);
+            }
+        }
+    }
+    Ok(note_info)
+}
+
+/// The notes from one track of the file
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct TrackNotes {
+    /// The track's position in the file, from 0
+    pub index: usize,
+    /// The track's name from its TrackName event, or `None` if it has none
+    pub name: Option<String>,
+    pub notes: Vec<Note>
+}
+
+/// The notes played on one channel, for files that only have one track
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ChannelNotes {
+    /// The MIDI channel (0-15)
+    pub channel: u8,
+    pub notes: Vec<Note>
+}
+
+/// What to do with notes that are still held when their track ends
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum TruncatedNotes {
+    /// Fails with `MissingNoteOff`
+    #[default]
+    Error,
+    /// Ends them at the end of the track, and marks them as `truncated`
+    Include,
+    /// Leaves them out
+    Discard
+}
+
+/// Settings for how notes are collected by `get_notes`
+#[derive(Clone, Debug, Default)]
+pub struct NoteOptions {
+    /// Fills in `velocity_f` on every note
+    pub velocity_normalized: bool,
+    /// Only collects notes on this channel, if set
+    pub channel: Option<u8>,
+    /// Leaves out notes on the General MIDI drum channel
+    pub exclude_drums: bool,
+    /// Only collects notes at or above this pitch, if set
+    pub min_pitch: Option<u8>,
+    /// Only collects notes at or below this pitch, if set
+    pub max_pitch: Option<u8>,
+    /// Only collects notes whose pitch class is set in this mask, with bit 0
+    /// for C up to bit 11 for B, if set. See `names::key_pitch_classes`.
+    pub key_filter: Option<u16>,
+    /// Only collects notes played at or above this velocity, if set
+    pub min_velocity: Option<u8>,
+    /// Only collects notes played at or below this velocity, if set
+    pub max_velocity: Option<u8>,
+    /// Fills in `pitch_name` on every note
+    pub pitch_names: bool,
+    /// Spells pitch names with flats instead of sharps
+    pub flat_names: bool,
+    /// Spells pitch names and pitch class names the way they're spelled in
+    /// this key instead, if set
+    pub key: Option<PitchSpeller>,
+    /// Fills in `pitch_class` on every note
+    pub pitch_class: bool,
+    /// Fills in `pitch_class_name` on every note
+    pub pitch_class_names: bool,
+    /// Fills in `frequency_hz` on every note, tuned to this frequency for A4, if set
+    pub a4_hz: Option<f64>,
+    /// Fills in `drum_name` on every note on the drum channel
+    pub gm_drum_names: bool,
+    /// The unit that note times are written in
+    pub time_unit: TimeUnit,
+    /// Fills in `measure` and `beat` on every note, if set
+    pub measure_map: Option<MeasureMap>,
+    /// Holds notes released while the sustain pedal (CC 64) is down until the pedal comes up
+    pub sustain_pedal: bool,
+    /// What to do with notes that never end
+    pub truncated_notes: TruncatedNotes,
+    /// Snaps note times to the nearest multiple of this many beats, if set
+    pub quantize: Option<f64>,
+    /// Only snaps the start times when quantizing, keeping each note's end where it was played
+    pub quantize_start_only: bool,
+    /// Delays the notes on the off-beat eighths, and their ends with them, so
+    /// they fall this fraction of the way through the beat, if set
+    pub swing: Option<f64>,
+    /// Only collects notes that start from the first tick up to (but not
+    /// including) the second, if set, ending any still held at the second
+    pub time_range: Option<(u32, u32)>,
+    /// Leaves out notes shorter than this, in the same unit as the note times, if set
+    pub min_duration: Option<f64>,
+    /// Stops once this many notes have ended, if set, leaving out any notes
+    /// that are still held then
+    pub max_notes: Option<usize>
+}
+
+impl NoteOptions {
+    /// Names a pitch for `pitch_name`, in the key if there is one
+    pub fn pitch_name(&self, pitch: u32) -> String {
+        match &self.key {
+            Some(key) => key.pitch_name(pitch),
+            None => names::pitch_name(pitch, self.flat_names)
+        }
+    }
+
+    /// Names a pitch class for `pitch_class_name`, in the key if there is one
+    pub fn pitch_class_name(&self, pitch_class: u8) -> String {
+        match &self.key {
+            Some(key) => key.pitch_class_name(pitch_class).to_string(),
+            None => names::pitch_class_name(pitch_class).to_string()
+        }
+    }
+}
+
+/// Extracts the notes from a track, converting their ticks to seconds with the tempo map.
+///
+/// Notes are returned in order of their start time. A note that is still held
+/// when the track ends is an error.
+///
+/// # Examples
+///
+/// ```
+/// use midly::{Smf, Event};
+/// use midi2json::{get_notes, get_tempo_map};
+///
+/// let bytes = include_bytes!("../input/sample.mid");
+/// let smf: Smf<Vec<Event>> = Smf::read(bytes).unwrap();
+/// let track = &smf.tracks[0];
+///
+/// let tempo_map = get_tempo_map(&smf.tracks, Some(120.0)).unwrap();
+/// let notes = get_notes(track, &tempo_map, 96, &Default::default()).unwrap();
+/// assert_eq!(notes[0].pitch_value, 60);
+/// assert_eq!(notes[0].time_start.as_f64(), 0.0);
+/// assert_eq!(notes[0].time_end.as_f64(), 0.25);
+/// ```
+pub fn get_notes(
+    track: &[Event],
+    tempo_map: &[TempoChange],
+    ticks_per_beat: u16,
+    options: &NoteOptions
+) -> Result<Vec<Note>> {
+    collect_notes(track, tempo_map, ticks_per_beat, options, || Ok(()))
+}
+
+/// Extracts the notes from a track like `get_notes`, counting each one with
+/// `counter` as it's collected, which writes the progress to `log` every so
+/// many notes
+///
+/// # Examples
+///
+/// ```
+/// use midly::{Smf, Event};
+/// use midi2json::{get_notes_counted, get_tempo_map, NoteCounter};
+///
+/// let bytes = include_bytes!("../input/sample.mid");
+/// let smf: Smf<Vec<Event>> = Smf::read(bytes).unwrap();
+///
+/// let tempo_map = get_tempo_map(&smf.tracks, Some(120.0)).unwrap();
+/// let mut counter = NoteCounter::new(2);
+/// let mut log = Vec::new();
+/// let notes = get_notes_counted(&smf.tracks[0], &tempo_map, 96, &Default::default(), &mut counter, &mut log).unwrap();
+/// assert_eq!(counter.count(), notes.len());
+/// assert!(String::from_utf8(log).unwrap().starts_with("Processed 2 notes ("));
+/// ```
+pub fn get_notes_counted(
+    track: &[Event],
+    tempo_map: &[TempoChange],
+    ticks_per_beat: u16,
+    options: &NoteOptions,
+    counter: &mut NoteCounter,
+    log: &mut dyn Write
+) -> Result<Vec<Note>> {
+    collect_notes(track, tempo_map, ticks_per_beat, options, || counter.add(log))
+}
+
+/// Collects the notes from a track in start order, calling `on_note` as each
+/// one is completed
+fn collect_notes<F: FnMut() -> Result<()>>(
+    track: &[Event],
+    tempo_map: &[TempoChange],
+    ticks_per_beat: u16,
+    options: &NoteOptions,
+    mut on_note: F
+) -> Result<Vec<Note>> {
+    // Each note is paired with its position in NoteOn order, so notes can be put
+    // back in start order once they have all been completed
+    let mut notes = Vec::<(usize, Note)>::new();
+    stream_notes(track, tempo_map, ticks_per_beat, options, |index, note| {
+        notes.push((index, note));
+        on_note()
+    })?;
+
+    // Notes are completed in NoteOff order, but consumers expect them in start order
+    notes.sort_by_key(|(index, _)| *index);
+
+    Ok(notes.into_iter()
+        .map(|(_, note)| note)
+        .collect())
+}
+
+/// Counts notes as they're collected, writing the progress every so many notes
+#[derive(Clone, Debug)]
+pub struct NoteCounter {
+    interval: usize,
+    count: usize,
+    started: Instant
+}
+
+impl NoteCounter {
+    /// A counter that writes the progress every `interval` notes, timed from now
+    pub fn new(interval: usize) -> NoteCounter {
+        NoteCounter {
+            interval: interval.max(1),
+            count: 0,
+            started: Instant::now()
+        }
+    }
+
+    /// How many notes have been counted
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Counts one more note, writing the progress to `log` if that makes a
+    /// multiple of the interval
+    pub fn add(&mut self, log: &mut dyn Write) -> Result<()> {
+        self.count += 1;
+        if self.count.is_multiple_of(self.interval) {
+            self.write_progress(log)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the progress for the last notes counted, unless `add` just did,
+    /// so the final count is always written
+    pub fn finish(&self, log: &mut dyn Write) -> Result<()> {
+        if self.count == 0 || !self.count.is_multiple_of(self.interval) {
+            self.write_progress(log)?;
+        }
+        Ok(())
+    }
+
+    fn write_progress(&self, log: &mut dyn Write) -> Result<()> {
+        writeln!(log, "Processed {} notes ({:.2}s elapsed)", self.count, self.started.elapsed().as_secs_f64())?;
+        Ok(())
+    }
+}
+
+/// Extracts the notes from a track like `get_notes`, but hands each one to
+/// `on_note` as soon as it ends instead of collecting them.
+///
+/// Notes arrive in the order they end, along with their index in start order.
+/// An error from `on_note` stops the stream and is returned.
+///
+/// # Examples
+///
+/// ```
+/// use midly::{Smf, Event};
+/// use midi2json::{stream_notes, get_tempo_map};
+///
+/// let bytes = include_bytes!("../input/sample.mid");
+/// let smf: Smf<Vec<Event>> = Smf::read(bytes).unwrap();
+/// let track = &smf.tracks[0];
+///
+/// let tempo_map = get_tempo_map(&smf.tracks, Some(120.0)).unwrap();
+/// let mut note_count = 0;
+/// stream_notes(track, &tempo_map, 96, &Default::default(), |_, _| {
+///     note_count += 1;
+///     Ok(())
+/// }).unwrap();
+/// assert!(note_count > 0);
+/// ```
+pub fn stream_notes<F: FnMut(usize, Note) -> Result<()>>(
+    track: &[Event],
+    tempo_map: &[TempoChange],
+    ticks_per_beat: u16,
+    options: &NoteOptions,
+    mut on_note: F
+) -> Result<()> {
+    // Notes that are too short are dropped as they end, however they end, and
+    // so is any note past the maximum
+    let ended_count = Cell::new(0);
+    let reached_max_notes = || options.max_notes.is_some_and(|max_notes| ended_count.get() >= max_notes);
+    let mut on_note = |index, note: Note| {
+        let too_short = options.min_duration
+            .is_some_and(|min_duration| note.time_end.since(note.time_start).as_f64() < min_duration);
+        if too_short || reached_max_notes() {
+            Ok(())
+        } else {
+            ended_count.set(ended_count.get() + 1);
+            on_note(index, note)
+        }
+    };
+    let mut cur_time: u32 = 0;
+    let mut started_count: usize = 0;
+    // Notes that have started but not ended yet, keyed on (channel, pitch)
+    let mut open_notes = HashMap::<(u8, u8), OpenNote>::new();
+    // Notes that were released while their channel's sustain pedal was down
+    let mut sustained_notes = HashMap::<(u8, u8), OpenNote>::new();
+    let mut pedal_down = [false; 16];
+    let swing_delay = |start_tick| options.swing.map_or(0, |ratio| swing_delay_ticks(start_tick, ratio, ticks_per_beat));
+    let start_ticks = |ticks| match options.quantize {
+        Some(grid) => quantize_ticks(ticks, grid, ticks_per_beat),
+        None => ticks
+    };
+    let end_ticks = |ticks| if options.quantize_start_only { ticks } else { start_ticks(ticks) };
+    // The end of a note that starts at `start_tick` and was let go at `ticks`.
+    // It's never before the start, which quantizing only the start can round
+    // up past it, and it's swung as much as the start, before it's cut off at
+    // the end of the time range.
+    let end_time = |start_tick: u32, ticks: u32| {
+        let ticks = ticks.max(start_tick) + swing_delay(start_tick);
+        let ticks = match options.time_range {
+            Some((_, range_end)) => ticks.min(range_end),
+            None => ticks
+        };
+        get_time(ticks, options.time_unit, tempo_map, ticks_per_beat)
+    };
+    for event in track {
+        if reached_max_notes() {
+            break;
+        }
+
+        let delta = event.delta.as_int();
+        let kind = event.kind;
+        cur_time += delta;
+
+        if let EventKind::Midi{ message, channel } = kind {
+            let channel = channel.as_int();
+            if options.channel.is_some_and(|filter_channel| filter_channel != channel)
+                || (options.exclude_drums && channel == names::DRUM_CHANNEL) {
+                continue;
+            }
+
+            match message {
+                MidiMessage::NoteOn(pitch, velocity) if velocity.as_int() > 0 => {
+                    // Notes outside the pitch or velocity range are never opened, so their
+                    // NoteOffs are ignored too
+                    let in_range = options.min_pitch.is_none_or(|min_pitch| pitch.as_int() >= min_pitch)
+                        && options.max_pitch.is_none_or(|max_pitch| pitch.as_int() <= max_pitch)
+                        && options.key_filter.is_none_or(|mask| mask & (1 << (pitch.as_int() % 12)) != 0)
+                        && options.min_velocity.is_none_or(|min_velocity| velocity.as_int() >= min_velocity)
+                        && options.max_velocity.is_none_or(|max_velocity| velocity.as_int() <= max_velocity);
+                    if !in_range {
+                        continue;
+                    }
+
+                    // Swinging can only move a note later, so it can push one out of the range
+                    let start_time = start_ticks(cur_time);
+                    let swung_start_time = start_time + swing_delay(start_time);
+                    if options.time_range.is_some_and(|(range_start, range_end)| start_time < range_start || swung_start_time >= range_end) {
+                        continue;
+                    }
+                    let time_value = get_time(swung_start_time, options.time_unit, tempo_map, ticks_per_beat);
+                    let velocity = velocity.as_int();
+                    let pitch_value = pitch.as_int() as u32;
+                    let position = options.measure_map.as_ref()
+                        .map(|measure_map| measure_map.get_position(start_time));
+                    let new_note = Note {
+                        pitch_value,
+                        pitch_name: if options.pitch_names {
+                            Some(options.pitch_name(pitch_value))
+                        } else {
+                            None
+                        },
+                        pitch_class: if options.pitch_class {
+                            Some((pitch_value % 12) as u8)
+                        } else {
+                            None
+                        },
+                        pitch_class_name: if options.pitch_class_names {
+                            Some(options.pitch_class_name((pitch_value % 12) as u8))
+                        } else {
+                            None
+                        },
+                        frequency_hz: options.a4_hz.map(|a4_hz| pitch_frequency(pitch_value, a4_hz)),
+                        drum_name: if options.gm_drum_names && channel == names::DRUM_CHANNEL {
+                            names::drum_name(pitch.as_int()).map(String::from)
+                        } else {
+                            None
+                        },
+                        time_start: time_value,
+                        time_end: time_value,
+                        time_start_ticks: None,
+                        time_end_ticks: None,
+                        duration: None,
+                        delta_start: None,
+                        measure: position.map(|(measure, _)| measure),
+                        beat: position.map(|(_, beat)| beat),
+                        channel,
+                        velocity,
+                        release_velocity: None,
+                        velocity_f: if options.velocity_normalized {
+                            Some((velocity as f64) / 127.0)
+                        } else {
+                            None
+                        },
+                        truncated: false,
+                        articulation: None,
+                        source_file: None,
+                        source_track: None
+                    };
+
+                    // Re-triggering a key that is still held (or sustained) ends the earlier note
+                    let key = (channel, pitch.as_int());
+                    if let Some((index, start_tick, partial_note)) = open_notes.insert(key, (started_count, start_time, new_note)) {
+                        on_note(index, partial_note.ended_at(end_time(start_tick, start_time)))?;
+                    }
+                    if let Some((index, start_tick, sustained_note)) = sustained_notes.remove(&key) {
+                        on_note(index, sustained_note.ended_at(end_time(start_tick, start_time)))?;
+                    }
+                    started_count += 1;
+                },
+                // Per the MIDI spec, a NoteOn with velocity 0 is a NoteOff
+                MidiMessage::NoteOn(pitch, _) | MidiMessage::NoteOff(pitch, _) => {
+                    let release_velocity = match message {
+                        MidiMessage::NoteOff(_, velocity) => Some(velocity.as_int()),
+                        _ => None
+                    };
+
+                    let key = (channel, pitch.as_int());
+                    if let Some((index, start_tick, partial_note)) = open_notes.remove(&key) {
+                        let released_note = Note {
+                            release_velocity,
+                            ..partial_note
+                        };
+
+                        if pedal_down[channel as usize] {
+                            sustained_notes.insert(key, (index, start_tick, released_note));
+                        } else {
+                            on_note(index, released_note.ended_at(end_time(start_tick, end_ticks(cur_time))))?;
+                        }
+                    }
+                },
+                MidiMessage::Controller(controller, value) if options.sustain_pedal && controller.as_int() == 64 => {
+                    let is_down = value.as_int() >= 64;
+                    if pedal_down[channel as usize] && !is_down {
+                        let release_time = |start_tick| end_time(start_tick, end_ticks(cur_time));
+                        end_sustained_notes(&mut sustained_notes, &mut on_note, release_time, Some(channel))?;
+                    }
+                    pedal_down[channel as usize] = is_down;
+                },
+                _ => {}
+            }
+        }
+    }
+
+    // The notes still held when the maximum was reached never end, so they're left out
+    if reached_max_notes() {
+        return Ok(());
+    }
+
+    // A pedal that is never released holds its notes until the end of the track
+    let track_end = |start_tick| end_time(start_tick, end_ticks(cur_time));
+    end_sustained_notes(&mut sustained_notes, &mut on_note, track_end, None)?;
+
+    let mut unended_notes: Vec<((u8, u8), OpenNote)> = open_notes.into_iter().collect();
+    unended_notes.sort_by_key(|(_, (index, _, _))| *index);
+    match options.truncated_notes {
+        TruncatedNotes::Error => {
+            // Report the earliest note that never ended
+            if let Some(((channel, pitch), _)) = unended_notes.first() {
+                return Err(Midi2JsonError::MissingNoteOff {
+                    pitch: *pitch,
+                    channel: *channel
+                });
+            }
+        },
+        TruncatedNotes::Include => {
+            for (_, (index, start_tick, partial_note)) in unended_notes {
+                let truncated_note = Note {
+                    truncated: true,
+                    ..partial_note.ended_at(track_end(start_tick))
+                };
+                on_note(index, truncated_note)?;
+            }
+        },
+        TruncatedNotes::Discard => {}
+    }
+
+    Ok(())
+}
+
+/// A note that hasn't ended yet, with its index and the tick it starts at
+type OpenNote = (usize, u32, Note);
+
+/// Ends the sustained notes on a channel (or on every channel), each at the
+/// time `time_end` gives for its start tick
+fn end_sustained_notes<F: FnMut(usize, Note) -> Result<()>, T: Fn(u32) -> TimeValue>(
+    sustained_notes: &mut HashMap<(u8, u8), OpenNote>,
+    on_note: &mut F,
+    time_end: T,
+    channel: Option<u8>
+) -> Result<()> {
+    let keys: Vec<(u8, u8)> = sustained_notes.keys()
+        .filter(|(note_channel, _)| channel.is_none_or(|channel| channel == *note_channel))
+        .copied()
+        .collect();
+
+    for key in keys {
+        let (index, start_tick, sustained_note) = sustained_notes.remove(&key).unwrap();
+        on_note(index, sustained_note.ended_at(time_end(start_tick)))?;
+    }
+
+    Ok(())
+}
+
+/// The equal-tempered frequency of a MIDI pitch, where pitch 69 (A4) is `a4_hz`
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::pitch_frequency;
+///
+/// assert_eq!(pitch_frequency(69, 440.0), 440.0);
+/// assert_eq!(pitch_frequency(81, 415.0), 830.0);
+/// ```
+pub fn pitch_frequency(pitch: u32, a4_hz: f64) -> f64 {
+    a4_hz * 2f64.powf((pitch as f64 - 69.0) / 12.0)
+}
+
+/// What to do with notes that are transposed outside the MIDI range (0-127)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum TransposeMode {
+    /// Moves them to the nearest valid pitch
+    #[default]
+    Clamp,
+    /// Leaves them out
+    Discard
+}
+
+/// Shifts every note's pitch by some number of semitones, updating pitch names
+/// if they were filled in, spelled as `note_options` says.
+///
+/// Returns the number of notes that went outside the MIDI range, which were
+/// clamped or discarded depending on the mode.
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::{transpose_notes, Note, TransposeMode};
+///
+/// let mut notes = vec![Note { pitch_value: 60, ..Default::default() }];
+/// assert_eq!(transpose_notes(&mut notes, -12, TransposeMode::Clamp, &Default::default()), 0);
+/// assert_eq!(notes[0].pitch_value, 48);
+/// ```
+pub fn transpose_notes(notes: &mut Vec<Note>, semitones: i32, mode: TransposeMode, note_options: &NoteOptions) -> usize {
+    let mut out_of_range_count = 0;
+    notes.retain_mut(|note| {
+        if transpose_note(note, semitones, note_options) {
+            true
+        } else {
+            out_of_range_count += 1;
+            mode == TransposeMode::Clamp
+        }
+    });
+
+    out_of_range_count
+}
+
+/// Shifts one note's pitch by some number of semitones, clamping it to the MIDI range.
+///
+/// Returns false if the note had to be clamped.
+pub fn transpose_note(note: &mut Note, semitones: i32, note_options: &NoteOptions) -> bool {
+    let pitch = note.pitch_value as i32 + semitones;
+    let old_pitch = note.pitch_value;
+    note.pitch_value = pitch.clamp(0, 127) as u32;
+    if note.pitch_name.is_some() {
+        note.pitch_name = Some(note_options.pitch_name(note.pitch_value));
+    }
+    let pitch_class = (note.pitch_value % 12) as u8;
+    if note.pitch_class.is_some() {
+        note.pitch_class = Some(pitch_class);
+    }
+    if note.pitch_class_name.is_some() {
+        note.pitch_class_name = Some(note_options.pitch_class_name(pitch_class));
+    }
+    if let Some(frequency_hz) = note.frequency_hz {
+        let a4_hz = frequency_hz / pitch_frequency(old_pitch, 1.0);
+        note.frequency_hz = Some(pitch_frequency(note.pitch_value, a4_hz));
+    }
+    if note.drum_name.is_some() {
+        note.drum_name = names::drum_name(note.pitch_value as u8).map(String::from);
+    }
+
+    (0..=127).contains(&pitch)
+}
+
+/// Merges the notes from several tracks into one list, in order of start time
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::{merge_notes, Note};
+///
+/// let first_track = vec![Note { time_start: 0.0.into(), time_end: 1.0.into(), pitch_value: 60, ..Default::default() }];
+/// let second_track = vec![Note { time_start: 0.5.into(), time_end: 1.0.into(), pitch_value: 64, ..Default::default() }];
+///
+/// let notes = merge_notes(vec![second_track, first_track]);
+/// assert_eq!(notes[0].pitch_value, 60);
+/// assert_eq!(notes[1].pitch_value, 64);
+/// ```
+pub fn merge_notes(tracks: Vec<Vec<Note>>) -> Vec<Note> {
+    let mut notes: Vec<Note> = tracks.into_iter().flatten().collect();
+    notes.sort_by(|a, b| a.start_order().total_cmp(&b.start_order()));
+
+    notes
+}
+
+/// Splits the notes up by channel, in order of channel. Each channel's notes
+/// keep their order.
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::{split_by_channel, Note};
+///
+/// let notes = vec![
+///     Note { channel: 9, pitch_value: 36, ..Default::default() },
+///     Note { channel: 0, pitch_value: 60, ..Default::default() },
+///     Note { channel: 9, pitch_value: 42, ..Default::default() }
+/// ];
+/// let channels = split_by_channel(notes);
+/// assert_eq!(channels.len(), 2);
+/// assert_eq!(channels[0].channel, 0);
+/// assert_eq!(channels[1].notes.len(), 2);
+/// ```
+pub fn split_by_channel(notes: Vec<Note>) -> Vec<ChannelNotes> {
+    let mut channels = BTreeMap::<u8, Vec<Note>>::new();
+    for note in notes {
+        channels.entry(note.channel).or_default().push(note);
+    }
+
+    channels.into_iter()
+        .map(|(channel, notes)| ChannelNotes { channel, notes })
+        .collect()
+}
+
+/// Sorts the notes by start time and sets each one's `delta_start` to the time
+/// since the previous note started. The first note's is always 0.
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::{set_relative_times, Note};
+///
+/// let mut notes = vec![
+///     Note { time_start: 1.5.into(), ..Default::default() },
+///     Note { time_start: 0.5.into(), ..Default::default() }
+/// ];
+/// set_relative_times(&mut notes);
+/// assert_eq!(notes[0].delta_start, Some(0.0.into()));
+/// assert_eq!(notes[1].delta_start, Some(1.0.into()));
+/// ```
+pub fn set_relative_times(notes: &mut [Note]) {
+    notes.sort_by(|a, b| a.time_start.as_f64().partial_cmp(&b.time_start.as_f64()).unwrap());
+
+    let mut previous_start = notes.first().map(|note| note.time_start);
+    for note in notes.iter_mut() {
+        if let Some(previous_start) = previous_start {
+            note.delta_start = Some(note.time_start.since(previous_start));
+        }
+        previous_start = Some(note.time_start);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use midly::MetaMessage;
+    use midly::number::{u4, u7, u24, VarlenInt};
+
+    fn note_on(delta: u32, channel: u8, pitch: u8, velocity: u8) -> Event<'static> {
+        Event {
+            delta: VarlenInt::from(delta),
+            kind: EventKind::Midi {
+                channel: u4::from(channel),
+                message: MidiMessage::NoteOn(u7::from(pitch), u7::from(velocity))
+            }
+        }
+    }
+
+    fn note_off(delta: u32, channel: u8, pitch: u8) -> Event<'static> {
+        note_off_velocity(delta, channel, pitch, 64)
+    }
+
+    fn note_off_velocity(delta: u32, channel: u8, pitch: u8, velocity: u8) -> Event<'static> {
+        Event {
+            delta: VarlenInt::from(delta),
+            kind: EventKind::Midi {
+                channel: u4::from(channel),
+                message: MidiMessage::NoteOff(u7::from(pitch), u7::from(velocity))
+            }
+        }
+    }
+
+    fn tempo(delta: u32, micros_per_beat: u32) -> Event<'static> {
+        Event {
+            delta: VarlenInt::from(delta),
+            kind: EventKind::Meta(MetaMessage::Tempo(u24::from(micros_per_beat)))
+        }
+    }
+
+    /// Returns (time_start, time_end, pitch_value) for each note, at 60 BPM and 96 PPQ
+    fn get_note_times(track: &[Event]) -> Vec<(f64, f64, u32)> {
+        let tempo_map = get_tempo_map(&[track], Some(60.0)).unwrap();
+        get_notes(track, &tempo_map, 96, &Default::default())
+            .unwrap()
+            .iter()
+            .map(|note| (note.time_start.as_f64(), note.time_end.as_f64(), note.pitch_value))
+            .collect()
+    }
+
+    #[test]
+    fn test_get_notes_simultaneous() {
+        let track = vec![
+            note_on(0, 0, 60, 100),
+            note_on(0, 0, 64, 100),
+            note_off(96, 0, 60),
+            note_off(0, 0, 64)
+        ];
+        assert_eq!(get_note_times(&track), vec![
+            (0.0, 1.0, 60),
+            (0.0, 1.0, 64)
+        ]);
+    }
+
+    #[test]
+    fn test_get_notes_quantize() {
+        // At 96 ticks per beat, a sixteenth-note grid is 24 ticks
+        let track = vec![
+            note_on(11, 0, 60, 100),
+            note_off(40, 0, 60),
+            note_on(14, 0, 62, 100),
+            note_off(25, 0, 62),
+            note_on(0, 0, 64, 100),
+            note_off(100, 0, 64)
+        ];
+        let tempo_map = get_tempo_map(&[&track], Some(60.0)).unwrap();
+        let get_ticks = |options: &NoteOptions| -> Vec<(f64, f64)> {
+            get_notes(&track, &tempo_map, 96, options)
+                .unwrap()
+                .iter()
+                .map(|note| (note.time_start.as_f64(), note.time_end.as_f64()))
+                .collect()
+        };
+
+        let options = NoteOptions {
+            time_unit: TimeUnit::Ticks,
+            quantize: Some(0.25),
+            ..Default::default()
+        };
+        assert_eq!(get_ticks(&options), vec![(0.0, 48.0), (72.0, 96.0), (96.0, 192.0)]);
+
+        let options = NoteOptions {
+            quantize_start_only: true,
+            ..options
+        };
+        assert_eq!(get_ticks(&options), vec![(0.0, 51.0), (72.0, 90.0), (96.0, 190.0)]);
+
+        // A note shorter than half a grid step can start after it was let go,
+        // so it ends where it starts
+        let short_track = vec![note_on(13, 0, 60, 100), note_off(1, 0, 60), note_on(34, 0, 62, 100), note_off(2, 0, 62)];
+        let notes = get_notes(&short_track, &tempo_map, 96, &options).unwrap();
+        let times: Vec<(TimeValue, TimeValue, Option<TimeValue>)> = notes.iter()
+            .map(|note| (note.time_start, note.time_end, note.duration))
+            .collect();
+        assert_eq!(times, vec![
+            (TimeValue::Ticks(24), TimeValue::Ticks(24), Some(TimeValue::Ticks(0))),
+            (TimeValue::Ticks(48), TimeValue::Ticks(50), Some(TimeValue::Ticks(2)))
+        ]);
+
+        let options = NoteOptions { time_unit: TimeUnit::Seconds, ..options };
+        let notes = get_notes(&short_track, &tempo_map, 96, &options).unwrap();
+        assert!(notes.iter().all(|note| note.time_end.as_f64() >= note.time_start.as_f64() && note.duration.unwrap().as_f64() >= 0.0));
+    }
+
+    #[test]
+    fn test_get_notes_swing() {
+        // Straight eighths at 120 BPM, then one off the eighth grid, and an
+        // off-beat eighth held with the pedal
+        let track = vec![
+            note_on(0, 0, 60, 100),
+            note_off(48, 0, 60),
+            note_on(0, 0, 62, 100),
+            note_off(48, 0, 62),
+            note_on(0, 0, 64, 100),
+            note_off(48, 0, 64),
+            note_on(0, 0, 65, 100),
+            note_off(48, 0, 65),
+            note_on(24, 0, 67, 100),
+            note_off(24, 0, 67),
+            Event {
+                delta: VarlenInt::from(0),
+                kind: EventKind::Midi { channel: u4::from(0), message: MidiMessage::Controller(u7::from(64), u7::from(127)) }
+            },
+            note_on(0, 0, 69, 100),
+            note_off(24, 0, 69),
+            Event {
+                delta: VarlenInt::from(72),
+                kind: EventKind::Midi { channel: u4::from(0), message: MidiMessage::Controller(u7::from(64), u7::from(0)) }
+            }
+        ];
+        let tempo_map = get_tempo_map(&[&track], Some(120.0)).unwrap();
+        let get_times = |swing| -> Vec<(f64, f64)> {
+            let options = NoteOptions { swing, sustain_pedal: true, ..Default::default() };
+            get_notes(&track, &tempo_map, 96, &options)
+                .unwrap()
+                .iter()
+                .map(|note| (note.time_start.as_f64(), note.time_end.as_f64()))
+                .collect()
+        };
+
+        let straight = vec![(0.0, 0.25), (0.25, 0.5), (0.5, 0.75), (0.75, 1.0), (1.125, 1.25), (1.25, 1.75)];
+        assert_eq!(get_times(None), straight);
+        assert_eq!(get_times(Some(0.5)), straight);
+
+        // The off-beat eighths move a sixth of a beat (1/12 s) later, ends and all
+        let swung = get_times(Some(2.0 / 3.0));
+        let expected = vec![(0.0, 0.25), (0.25 + 1.0 / 12.0, 0.5 + 1.0 / 12.0), (0.5, 0.75), (0.75 + 1.0 / 12.0, 1.0 + 1.0 / 12.0), (1.125, 1.25), (1.25 + 1.0 / 12.0, 1.75 + 1.0 / 12.0)];
+        for (got, want) in swung.iter().zip(&expected) {
+            assert!((got.0 - want.0).abs() < 1e-9 && (got.1 - want.1).abs() < 1e-9, "{:?} != {:?}", swung, expected);
+        }
+    }
+
+    #[test]
+    fn test_get_notes_swing_time_range() {
+        // Off-beat eighths at 120 BPM, the first held past the end of the range
+        let track = vec![
+            note_on(48, 0, 60, 100),
+            note_off(96, 0, 60),
+            note_on(0, 0, 62, 100),
+            note_off(24, 0, 62)
+        ];
+        let tempo_map = get_tempo_map(&[&track], Some(120.0)).unwrap();
+        let get_times = |time_range| -> Vec<(f64, f64)> {
+            let options = NoteOptions { swing: Some(0.75), time_range: Some(time_range), ..Default::default() };
+            get_notes(&track, &tempo_map, 96, &options)
+                .unwrap()
+                .iter()
+                .map(|note| (note.time_start.as_f64(), note.time_end.as_f64()))
+                .collect()
+        };
+
+        // The swung end is still cut off at the end of the range
+        assert_eq!(get_times((0, 96)), vec![(0.375, 0.5)]);
+        assert_eq!(get_times((0, 192)), vec![(0.375, 0.875), (0.875, 1.0)]);
+        // Swinging the start to the end of the range or past it leaves the note out
+        assert_eq!(get_times((0, 72)), vec![]);
+        assert_eq!(get_times((0, 60)), vec![]);
+    }
+
+    #[test]
+    fn test_get_notes_tempo_change() {
+        // A note every beat, with the tempo doubling from 120 to 240 BPM halfway through
+        let mut track = vec![tempo(0, 500_000)];
+        for beat in 0..8 {
+            if beat == 4 {
+                track.push(tempo(0, 250_000));
+            }
+            track.push(note_on(0, 0, 60 + beat, 100));
+            track.push(note_off(96, 0, 60 + beat));
+        }
+        let tempo_map = get_tempo_map(&[&track], None).unwrap();
+        let starts: Vec<f64> = get_notes(&track, &tempo_map, 96, &Default::default())
+            .unwrap()
+            .iter()
+            .map(|note| note.time_start.as_f64())
+            .collect();
+        assert_eq!(starts, vec![0.0, 0.5, 1.0, 1.5, 2.0, 2.25, 2.5, 2.75]);
+
+        let gaps: Vec<f64> = starts.windows(2).map(|pair| pair[1] - pair[0]).collect();
+        assert!(gaps[..4].iter().all(|&gap| gap == 0.5));
+        assert!(gaps[4..].iter().all(|&gap| gap == 0.25));
+    }
+
+    #[test]
+    fn test_get_notes_overlapping() {
+        let track = vec![
+            note_on(0, 0, 60, 100),
+            note_on(48, 0, 67, 100),
+            note_off(48, 0, 60),
+            note_off(96, 0, 67)
+        ];
+        assert_eq!(get_note_times(&track), vec![
+            (0.0, 1.0, 60),
+            (0.5, 2.0, 67)
+        ]);
+    }
+
+    #[test]
+    fn test_get_notes_repeated_pitch() {
+        let track = vec![
+            note_on(0, 0, 60, 100),
+            note_off(24, 0, 60),
+            note_on(0, 0, 60, 100),
+            note_off(24, 0, 60),
+            note_on(0, 0, 60, 100),
+            note_on(24, 0, 60, 100),
+            note_off(24, 0, 60)
+        ];
+        assert_eq!(get_note_times(&track), vec![
+            (0.0, 0.25, 60),
+            (0.25, 0.5, 60),
+            (0.5, 0.75, 60),
+            (0.75, 1.0, 60)
+        ]);
+    }
+
+    #[test]
+    fn test_get_notes_velocity_zero_note_off() {
+        let track = vec![
+            note_on(0, 0, 60, 100),
+            note_on(48, 0, 60, 0),
+            note_on(0, 0, 62, 100),
+            note_on(48, 0, 62, 0),
+            note_on(0, 0, 64, 0)
+        ];
+        assert_eq!(get_note_times(&track), vec![
+            (0.0, 0.5, 60),
+            (0.5, 1.0, 62)
+        ]);
+    }
+
+    #[test]
+    fn test_get_notes_channels() {
+        let track = vec![
+            note_on(0, 0, 60, 100),
+            note_on(0, 9, 36, 100),
+            note_on(0, 15, 60, 100),
+            note_off(96, 15, 60),
+            note_off(0, 9, 36),
+            note_off(0, 0, 60)
+        ];
+        let tempo_map = get_tempo_map(&[&track], Some(60.0)).unwrap();
+        let channels: Vec<(u8, u32)> = get_notes(&track, &tempo_map, 96, &Default::default())
+            .unwrap()
+            .iter()
+            .map(|note| (note.channel, note.pitch_value))
+            .collect();
+        assert_eq!(channels, vec![(0, 60), (9, 36), (15, 60)]);
+    }
+
+    #[test]
+    fn test_get_notes_velocity() {
+        let track = vec![
+            note_on(0, 0, 60, 64),
+            note_on(0, 0, 62, 127),
+            note_on(0, 0, 64, 0),
+            note_off_velocity(96, 0, 60, 20),
+            note_on(0, 0, 62, 0)
+        ];
+        let tempo_map = get_tempo_map(&[&track], Some(60.0)).unwrap();
+        let options = NoteOptions {
+            velocity_normalized: true,
+            ..Default::default()
+        };
+        let velocities: Vec<(u32, u8, Option<u8>, Option<f64>)> = get_notes(&track, &tempo_map, 96, &options)
+            .unwrap()
+            .iter()
+            .map(|note| (note.pitch_value, note.velocity, note.release_velocity, note.velocity_f))
+            .collect();
+        assert_eq!(velocities, vec![
+            (60, 64, Some(20), Some(64.0 / 127.0)),
+            (62, 127, None, Some(1.0))
+        ]);
+    }
+
+    #[test]
+    fn test_get_notes_velocity_not_normalized() {
+        let track = vec![
+            note_on(0, 0, 60, 64),
+            note_off(96, 0, 60)
+        ];
+        let tempo_map = get_tempo_map(&[&track], Some(60.0)).unwrap();
+        let notes = get_notes(&track, &tempo_map, 96, &Default::default()).unwrap();
+        assert_eq!(notes[0].velocity, 64);
+        assert_eq!(notes[0].velocity_f, None);
+    }
+
+    #[test]
+    fn test_get_notes_filter_channel() {
+        let track = vec![
+            note_on(0, 0, 60, 100),
+            note_on(0, 9, 36, 100),
+            note_on(0, 10, 38, 100),
+            note_off(48, 9, 36),
+            note_on(0, 9, 42, 100),
+            note_off(48, 0, 60),
+            note_off(0, 9, 42),
+            note_off(0, 10, 38)
+        ];
+        let tempo_map = get_tempo_map(&[&track], Some(60.0)).unwrap();
+        let options = NoteOptions {
+            channel: Some(9),
+            ..Default::default()
+        };
+        let notes: Vec<(u8, u32)> = get_notes(&track, &tempo_map, 96, &options)
+            .unwrap()
+            .iter()
+            .map(|note| (note.channel, note.pitch_value))
+            .collect();
+        assert_eq!(notes, vec![(9, 36), (9, 42)]);
+    }
+
+    #[test]
+    fn test_merge_notes_two_tracks() {
+        let melody_track = vec![
+            note_on(0, 0, 72, 100),
+            note_off(48, 0, 72),
+            note_on(48, 0, 74, 100),
+            note_off(48, 0, 74)
+        ];
+        let bass_track = vec![
+            note_on(24, 1, 48, 100),
+            note_off(48, 1, 48),
+            note_on(48, 1, 50, 100),
+            note_off(48, 1, 50)
+        ];
+        let tracks = vec![melody_track, bass_track];
+        let tempo_map = get_tempo_map(&tracks, Some(60.0)).unwrap();
+        let track_notes: Vec<Vec<Note>> = tracks.iter()
+            .map(|track| get_notes(track, &tempo_map, 96, &Default::default()).unwrap())
+            .collect();
+
+        let notes: Vec<(f64, u32)> = merge_notes(track_notes)
+            .iter()
+            .map(|note| (note.time_start.as_f64(), note.pitch_value))
+            .collect();
+        assert_eq!(notes, vec![
+            (0.0, 72),
+            (0.25, 48),
+            (1.0, 74),
+            (1.25, 50)
+        ]);
+    }
+
+    #[test]
+    fn test_get_notes_duration() {
+        let track = vec![
+            note_on(0, 0, 60, 100),
+            note_on(24, 0, 64, 100),
+            note_off(24, 0, 60),
+            note_off(120, 0, 64)
+        ];
+        let tempo_map = get_tempo_map(&[&track], Some(60.0)).unwrap();
+        let durations: Vec<Option<TimeValue>> = get_notes(&track, &tempo_map, 96, &Default::default())
+            .unwrap()
+            .iter()
+            .map(|note| note.duration)
+            .collect();
+        assert_eq!(durations, vec![Some(TimeValue::Seconds(0.5)), Some(TimeValue::Seconds(1.5))]);
+
+        let options = NoteOptions {
+            time_unit: TimeUnit::Ticks,
+            ..Default::default()
+        };
+        let notes = get_notes(&track, &tempo_map, 96, &options).unwrap();
+        assert_eq!(notes[1].time_start, TimeValue::Ticks(24));
+        assert_eq!(notes[1].time_end, TimeValue::Ticks(168));
+        assert_eq!(notes[1].duration, Some(TimeValue::Ticks(144)));
+    }
+
+    #[test]
+    fn test_note_duration_json() {
+        let note = Note {
+            time_start: 1.0.into(),
+            time_end: 1.5.into(),
+            pitch_value: 60,
+            ..Default::default()
+        };
+        let json = serde_json::to_value(note.clone().ended_at(1.5.into())).unwrap();
+        assert_eq!(json["duration"], 0.5);
+
+        let json = serde_json::to_value(note).unwrap();
+        assert!(json["duration"].is_null());
+    }
+
+    #[test]
+    fn test_get_notes_pitch_names() {
+        let track = vec![
+            note_on(0, 0, 60, 100),
+            note_on(0, 0, 63, 100),
+            note_off(96, 0, 60),
+            note_off(0, 0, 63)
+        ];
+        let tempo_map = get_tempo_map(&[&track], Some(60.0)).unwrap();
+        let get_pitch_names = |options: &NoteOptions| -> Vec<Option<String>> {
+            get_notes(&track, &tempo_map, 96, options)
+                .unwrap()
+                .into_iter()
+                .map(|note| note.pitch_name)
+                .collect()
+        };
+
+        assert_eq!(get_pitch_names(&Default::default()), vec![None, None]);
+        assert_eq!(get_pitch_names(&NoteOptions {
+            pitch_names: true,
+            ..Default::default()
+        }), vec![Some("C4".to_string()), Some("D#4".to_string())]);
+        assert_eq!(get_pitch_names(&NoteOptions {
+            pitch_names: true,
+            flat_names: true,
+            ..Default::default()
+        }), vec![Some("C4".to_string()), Some("Eb4".to_string())]);
+    }
+
+    #[test]
+    fn test_get_notes_missing_note_off() {
+        let track = vec![
+            note_on(0, 0, 60, 100),
+            note_on(0, 3, 64, 100),
+            note_on(0, 3, 67, 100),
+            note_off(96, 0, 60)
+        ];
+        let tempo_map = get_tempo_map(&[&track], Some(60.0)).unwrap();
+        match get_notes(&track, &tempo_map, 96, &Default::default()) {
+            Err(Midi2JsonError::MissingNoteOff { pitch, channel }) => assert_eq!((pitch, channel), (64, 3)),
+            other => panic!("Expected a missing NoteOff error, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn test_get_notes_truncated_notes() {
+        let track = vec![
+            note_on(0, 0, 60, 100),
+            note_off(96, 0, 60),
+            note_on(0, 0, 64, 100),
+            note_on(96, 0, 67, 100),
+            note_off(96, 0, 67)
+        ];
+        let tempo_map = get_tempo_map(&[&track], Some(60.0)).unwrap();
+        let get_with = |truncated_notes| {
+            let options = NoteOptions {
+                truncated_notes,
+                ..Default::default()
+            };
+            get_notes(&track, &tempo_map, 96, &options)
+        };
+
+        assert!(matches!(
+            get_with(TruncatedNotes::Error),
+            Err(Midi2JsonError::MissingNoteOff { pitch: 64, channel: 0 })
+        ));
+
+        let included: Vec<(f64, f64, u32, bool)> = get_with(TruncatedNotes::Include)
+            .unwrap()
+            .iter()
+            .map(|note| (note.time_start.as_f64(), note.time_end.as_f64(), note.pitch_value, note.truncated))
+            .collect();
+        assert_eq!(included, vec![
+            (0.0, 1.0, 60, false),
+            (1.0, 3.0, 64, true),
+            (2.0, 3.0, 67, false)
+        ]);
+
+        let discarded: Vec<u32> = get_with(TruncatedNotes::Discard)
+            .unwrap()
+            .iter()
+            .map(|note| note.pitch_value)
+            .collect();
+        assert_eq!(discarded, vec![60, 67]);
+    }
+
+    #[test]
+    fn test_truncated_json() {
+        let note = Note { truncated: true, ..Default::default() };
+        assert!(serde_json::to_string(&note).unwrap().contains("\"truncated\":true"));
+        assert!(!serde_json::to_string(&Note::default()).unwrap().contains("truncated"));
+    }
+
+    fn sustain_pedal(delta: u32, channel: u8, value: u8) -> Event<'static> {
+        Event {
+            delta: VarlenInt::from(delta),
+            kind: EventKind::Midi {
+                channel: u4::from(channel),
+                message: MidiMessage::Controller(u7::from(64), u7::from(value))
+            }
+        }
+    }
+
+    fn get_sustained_note_times(track: &[Event]) -> Vec<(f64, f64, u32)> {
+        let tempo_map = get_tempo_map(&[track], Some(60.0)).unwrap();
+        let options = NoteOptions {
+            sustain_pedal: true,
+            ..Default::default()
+        };
+        get_notes(track, &tempo_map, 96, &options)
+            .unwrap()
+            .iter()
+            .map(|note| (note.time_start.as_f64(), note.time_end.as_f64(), note.pitch_value))
+            .collect()
+    }
+
+    #[test]
+    fn test_get_notes_sustain_pedal() {
+        let track = vec![
+            note_on(0, 0, 60, 100),
+            sustain_pedal(48, 0, 127),
+            note_off(48, 0, 60),
+            note_on(0, 0, 64, 100),
+            note_off(96, 0, 64),
+            sustain_pedal(96, 0, 0),
+            note_on(0, 0, 67, 100),
+            note_off(96, 0, 67)
+        ];
+        assert_eq!(get_sustained_note_times(&track), vec![
+            (0.0, 3.0, 60),
+            (1.0, 3.0, 64),
+            (3.0, 4.0, 67)
+        ]);
+
+        // Without --sustain-pedal, the pedal is ignored
+        assert_eq!(get_note_times(&track), vec![
+            (0.0, 1.0, 60),
+            (1.0, 2.0, 64),
+            (3.0, 4.0, 67)
+        ]);
+    }
+
+    #[test]
+    fn test_get_notes_sustain_pedal_overlap() {
+        let track = vec![
+            sustain_pedal(0, 0, 127),
+            note_on(0, 0, 60, 100),
+            note_off(96, 0, 60),
+            // Striking the key again cuts off the sustained note
+            note_on(96, 0, 60, 100),
+            // The pedal comes up while the key is still held
+            sustain_pedal(48, 0, 0),
+            note_off(48, 0, 60),
+            // A pedal on another channel doesn't hold this one
+            sustain_pedal(0, 1, 127),
+            note_on(0, 0, 62, 100),
+            note_off(96, 0, 62),
+            // A pedal left down holds its notes until the end of the track
+            sustain_pedal(0, 0, 127),
+            note_on(0, 0, 64, 100),
+            note_off(96, 0, 64),
+            sustain_pedal(96, 1, 0)
+        ];
+        assert_eq!(get_sustained_note_times(&track), vec![
+            (0.0, 2.0, 60),
+            (2.0, 3.0, 60),
+            (3.0, 4.0, 62),
+            (4.0, 6.0, 64)
+        ]);
+    }
+
+    #[test]
+    fn test_get_notes_pitch_range() {
+        let track = vec![
+            note_on(0, 0, 47, 100),
+            note_on(0, 0, 48, 100),
+            note_on(0, 0, 60, 100),
+            note_on(0, 0, 72, 100),
+            note_on(0, 0, 73, 100),
+            note_off(96, 0, 47),
+            note_off(0, 0, 48),
+            note_off(0, 0, 60),
+            note_off(0, 0, 72),
+            note_off(0, 0, 73)
+        ];
+        let tempo_map = get_tempo_map(&[&track], Some(60.0)).unwrap();
+        let options = NoteOptions {
+            min_pitch: Some(48),
+            max_pitch: Some(72),
+            ..Default::default()
+        };
+        let pitches: Vec<u32> = get_notes(&track, &tempo_map, 96, &options)
+            .unwrap()
+            .iter()
+            .map(|note| note.pitch_value)
+            .collect();
+        assert_eq!(pitches, vec![48, 60, 72]);
+    }
+
+    #[test]
+    fn test_get_notes_time_range() {
+        let track = vec![
+            // Starts before the range and ends inside it
+            note_on(0, 0, 48, 100),
+            note_off(144, 0, 48),
+            // Starts on the range's first tick
+            note_on(48, 0, 60, 100),
+            note_off(96, 0, 60),
+            // Ends after the range, so it's cut short
+            note_on(0, 0, 62, 100),
+            note_off(192, 0, 62),
+            // Starts after the range
+            note_on(0, 0, 64, 100),
+            note_off(96, 0, 64)
+        ];
+        let tempo_map = get_tempo_map(&[&track], Some(60.0)).unwrap();
+        let options = NoteOptions {
+            time_range: Some((192, 384)),
+            ..Default::default()
+        };
+        let notes: Vec<(f64, f64, u32)> = get_notes(&track, &tempo_map, 96, &options)
+            .unwrap()
+            .iter()
+            .map(|note| (note.time_start.as_f64(), note.time_end.as_f64(), note.pitch_value))
+            .collect();
+        assert_eq!(notes, vec![(2.0, 3.0, 60), (3.0, 4.0, 62)]);
+    }
+
+    #[test]
+    fn test_get_notes_times_in_order() {
+        // Random tracks from a fixed seed: overlapping notes, repeated pitches,
+        // NoteOn with velocity 0, notes shorter than a tick's rounding, sustain
+        // pedal presses, and some notes left held at the end
+        let mut rng = Rng::new(98);
+        for case in 0..500 {
+            let mut track = Vec::new();
+            let mut track_ticks = 0;
+            for _ in 0..(rng.next_u64() % 100) {
+                let delta = match rng.next_u64() % 5 {
+                    0 => 0,
+                    1 => (rng.next_u64() % 1_000_000) as u32,
+                    2 => (rng.next_u64() % 12) as u32,
+                    _ => (rng.next_u64() % 200) as u32
+                };
+                track_ticks += delta;
+                let (channel, pitch) = ((rng.next_u64() % 2) as u8, (rng.next_u64() % 8) as u8 + 60);
+                track.push(match rng.next_u64() % 7 {
+                    0 | 1 => note_on(delta, channel, pitch, (rng.next_u64() % 128) as u8),
+                    2 | 3 => note_off(delta, channel, pitch),
+                    4 => sustain_pedal(delta, channel, if rng.next_u64().is_multiple_of(2) { 127 } else { 0 }),
+                    _ => note_on(delta, channel, pitch, 100)
+                });
+            }
+
+            // The options that move note starts and ends on their own
+            let quantize = match rng.next_u64() % 5 {
+                0 => Some(0.25),
+                1 => Some(1.0 / 3.0),
+                2 => Some(rng.next_f64(0.05, 2.0)),
+                _ => None
+            };
+            let time_range = if rng.next_u64().is_multiple_of(2) {
+                let (a, b) = ((rng.next_u64() % (track_ticks as u64 + 2)) as u32, (rng.next_u64() % (track_ticks as u64 + 2)) as u32);
+                Some((a.min(b), a.max(b)))
+            } else {
+                None
+            };
+            let options = NoteOptions {
+                truncated_notes: TruncatedNotes::Include,
+                quantize,
+                quantize_start_only: quantize.is_some() && rng.next_u64().is_multiple_of(2),
+                swing: if rng.next_u64().is_multiple_of(2) { Some(rng.next_f64(0.5, 1.0)) } else { None },
+                time_range,
+                sustain_pedal: rng.next_u64().is_multiple_of(2),
+                ..Default::default()
+            };
+
+            let bpm = rng.next_f64(1.0, 300.0) as f32;
+            let tempo_map = get_tempo_map(&[&track], Some(bpm)).unwrap();
+            for &time_unit in &[TimeUnit::Seconds, TimeUnit::Ticks, TimeUnit::Beats(QUARTER_NOTE)] {
+                let options = NoteOptions { time_unit, ..options.clone() };
+                for note in get_notes(&track, &tempo_map, 96, &options).unwrap() {
+                    assert!(
+                        note.time_start.as_f64() <= note.time_end.as_f64() && note.duration.unwrap().as_f64() >= 0.0,
+                        "case {} at {} BPM with {:?}: {:?}", case, bpm, options, note
+                    );
+                    if let (TimeUnit::Ticks, Some((range_start, range_end))) = (time_unit, time_range) {
+                        let in_range = note.time_start.as_f64() >= range_start as f64 && note.time_end.as_f64() <= range_end as f64;
+                        assert!(in_range, "case {} with {:?}: {:?}", case, options, note);
+                    }
+                }
+            }
+        }
+    }
+
+
+    #[test]
+    fn test_get_notes_max_notes() {
+        let track = vec![
+            note_on(0, 0, 60, 100),
+            // Held past the others
+            note_on(0, 0, 72, 100),
+            note_off(48, 0, 60),
+            note_on(0, 0, 62, 100),
+            note_off(48, 0, 62),
+            note_on(0, 0, 64, 100),
+            note_off(48, 0, 64),
+            note_off(48, 0, 72),
+            // Never ends, which would be an error if it were reached
+            note_on(0, 0, 67, 100)
+        ];
+        let tempo_map = get_tempo_map(&[&track], Some(60.0)).unwrap();
+        let pitches = |max_notes| -> Vec<u32> {
+            let options = NoteOptions { max_notes: Some(max_notes), ..Default::default() };
+            get_notes(&track, &tempo_map, 96, &options)
+                .unwrap()
+                .iter()
+                .map(|note| note.pitch_value)
+                .collect()
+        };
+        assert_eq!(pitches(2), vec![60, 62]);
+        assert_eq!(pitches(3), vec![60, 62, 64]);
+        // The held note ends fourth, but started second
+        assert_eq!(pitches(4), vec![60, 72, 62, 64]);
+    }
+
+    #[test]
+    fn test_get_notes_key_filter() {
+        let track: Vec<Event> = (36..=50)
+            .flat_map(|pitch| vec![note_on(0, 0, pitch, 100), note_off(24, 0, pitch)])
+            .collect();
+        let tempo_map = get_tempo_map(&[&track], Some(60.0)).unwrap();
+        let options = NoteOptions {
+            key_filter: names::key_pitch_classes("C major"),
+            ..Default::default()
+        };
+        let pitches: Vec<u32> = get_notes(&track, &tempo_map, 96, &options)
+            .unwrap()
+            .iter()
+            .map(|note| note.pitch_value)
+            .collect();
+        // 37, 39, 42, 44, 46, and 49 are the black keys; 50 is D again
+        assert_eq!(pitches, vec![36, 38, 40, 41, 43, 45, 47, 48, 50]);
+    }
+
+    #[test]
+    fn test_get_notes_min_duration() {
+        let track = vec![
+            // Exactly the minimum
+            note_on(0, 0, 60, 100),
+            note_off(24, 0, 60),
+            // A tick shorter
+            note_on(0, 0, 62, 100),
+            note_off(23, 0, 62),
+            note_on(0, 0, 64, 100),
+            note_off(96, 0, 64)
+        ];
+        let tempo_map = get_tempo_map(&[&track], Some(60.0)).unwrap();
+        let options = NoteOptions {
+            min_duration: Some(0.25),
+            ..Default::default()
+        };
+        let pitches: Vec<u32> = get_notes(&track, &tempo_map, 96, &options)
+            .unwrap()
+            .iter()
+            .map(|note| note.pitch_value)
+            .collect();
+        assert_eq!(pitches, vec![60, 64]);
+
+        // Quantizing to eighth notes snaps both ends of the first note to tick 48,
+        // so it's too short even though it was played for longer than the minimum
+        let track = vec![
+            note_on(26, 0, 60, 100),
+            note_off(34, 0, 60),
+            note_on(36, 0, 62, 100),
+            note_off(48, 0, 62)
+        ];
+        let options = NoteOptions {
+            quantize: Some(0.5),
+            ..options
+        };
+        let notes: Vec<(f64, f64, u32)> = get_notes(&track, &tempo_map, 96, &options)
+            .unwrap()
+            .iter()
+            .map(|note| (note.time_start.as_f64(), note.time_end.as_f64(), note.pitch_value))
+            .collect();
+        assert_eq!(notes, vec![(1.0, 1.5, 62)]);
+    }
+
+    #[test]
+    fn test_get_notes_velocity_range() {
+        let track = vec![
+            note_on(0, 0, 60, 5),
+            note_on(0, 0, 62, 11),
+            note_on(0, 0, 64, 100),
+            note_on(0, 0, 65, 120),
+            // Velocity 0 still ends the note, even though it's below the minimum
+            note_on(96, 0, 60, 0),
+            note_on(0, 0, 62, 0),
+            note_on(0, 0, 64, 0),
+            note_off(0, 0, 65)
+        ];
+        let tempo_map = get_tempo_map(&[&track], Some(60.0)).unwrap();
+        let options = NoteOptions {
+            min_velocity: Some(11),
+            max_velocity: Some(110),
+            ..Default::default()
+        };
+        let notes: Vec<(u32, u8, f64)> = get_notes(&track, &tempo_map, 96, &options)
+            .unwrap()
+            .iter()
+            .map(|note| (note.pitch_value, note.velocity, note.time_end.as_f64()))
+            .collect();
+        assert_eq!(notes, vec![(62, 11, 1.0), (64, 100, 1.0)]);
+    }
+
+    #[test]
+    fn test_transpose_notes() {
+        let mut notes: Vec<Note> = [0, 60, 127].iter()
+            .map(|&pitch_value| Note { pitch_value, pitch_name: Some(names::pitch_name(pitch_value, false)), ..Default::default() })
+            .collect();
+        assert_eq!(transpose_notes(&mut notes, 7, TransposeMode::Clamp, &Default::default()), 1);
+        let pitches: Vec<(u32, Option<String>)> = notes.iter()
+            .map(|note| (note.pitch_value, note.pitch_name.clone()))
+            .collect();
+        assert_eq!(pitches, vec![
+            (7, Some("G-1".to_string())),
+            (67, Some("G4".to_string())),
+            (127, Some("G9".to_string()))
+        ]);
+    }
+
+    #[test]
+    fn test_transpose_notes_discard() {
+        let mut notes: Vec<Note> = [0, 12, 127].iter()
+            .map(|&pitch_value| Note { pitch_value, ..Default::default() })
+            .collect();
+        assert_eq!(transpose_notes(&mut notes, -12, TransposeMode::Discard, &Default::default()), 1);
+        let pitches: Vec<u32> = notes.iter().map(|note| note.pitch_value).collect();
+        assert_eq!(pitches, vec![0, 115]);
+    }
+
+    #[test]
+    fn test_set_relative_times() {
+        let starts = [480, 0, 960, 960, 2000];
+        let mut notes: Vec<Note> = starts.iter()
+            .map(|&start| Note {
+                time_start: TimeValue::Ticks(start),
+                time_end: TimeValue::Ticks(start + 100),
+                duration: Some(TimeValue::Ticks(100)),
+                ..Default::default()
+            })
+            .collect();
+        set_relative_times(&mut notes);
+
+        let deltas: Vec<u32> = notes.iter()
+            .map(|note| note.delta_start.unwrap().as_f64() as u32)
+            .collect();
+        assert_eq!(deltas, vec![0, 480, 480, 0, 1040]);
+        assert_eq!(deltas.iter().sum::<u32>() as f64, notes.last().unwrap().time_start.as_f64());
+        assert!(notes.iter().all(|note| note.duration == Some(TimeValue::Ticks(100))));
+
+        set_relative_times(&mut []);
+    }
+
+    #[test]
+    fn test_normalize_start() {
+        // The first event is a NoteOn on another track, which starts later
+        let first_track = vec![
+            note_on(192, 0, 64, 100),
+            note_off(96, 0, 64)
+        ];
+        let second_track = vec![
+            note_on(96, 1, 60, 100),
+            note_off(192, 1, 60)
+        ];
+        let tempo_map = get_tempo_map(&[&first_track], Some(60.0)).unwrap();
+        let tracks = [first_track, second_track].iter()
+            .enumerate()
+            .map(|(index, track)| TrackNotes {
+                index,
+                name: None,
+                notes: get_notes(track, &tempo_map, 96, &Default::default()).unwrap()
+            })
+            .collect();
+
+        let mut note_info = NoteInfo {
+            tracks: Some(tracks),
+            ..Default::default()
+        };
+        note_info.normalize_start();
+
+        assert_eq!(note_info.offset_seconds, Some(TimeValue::Seconds(1.0)));
+        let times: Vec<(f64, f64, u32)> = note_info.all_notes()
+            .map(|note| (note.time_start.as_f64(), note.time_end.as_f64(), note.pitch_value))
+            .collect();
+        assert_eq!(times, vec![(1.0, 2.0, 64), (0.0, 2.0, 60)]);
+    }
+
+    #[test]
+    fn test_pitch_frequency() {
+        assert_eq!(pitch_frequency(69, 440.0), 440.0);
+        assert_eq!(pitch_frequency(57, 440.0), 220.0);
+        assert_eq!(pitch_frequency(69, 415.0), 415.0);
+        assert_eq!(pitch_frequency(57, 415.0), 207.5);
+        assert!((pitch_frequency(60, 440.0) - 261.6255653005986).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_get_notes_frequency() {
+        let track = vec![
+            note_on(0, 0, 69, 100),
+            note_off(96, 0, 69)
+        ];
+        let tempo_map = get_tempo_map(&[&track], Some(60.0)).unwrap();
+        let options = NoteOptions {
+            a4_hz: Some(415.0),
+            ..Default::default()
+        };
+        let mut notes = get_notes(&track, &tempo_map, 96, &options).unwrap();
+        assert_eq!(notes[0].frequency_hz, Some(415.0));
+
+        transpose_notes(&mut notes, -12, TransposeMode::Clamp, &Default::default());
+        assert_eq!(notes[0].frequency_hz, Some(207.5));
+
+        let notes = get_notes(&track, &tempo_map, 96, &Default::default()).unwrap();
+        assert_eq!(notes[0].frequency_hz, None);
+    }
+
+    #[test]
+    fn test_get_notes_exclude_drums() {
+        let track = vec![
+            note_on(0, 0, 60, 100),
+            note_on(0, 9, 36, 100),
+            note_on(0, 10, 64, 100),
+            note_on(0, 15, 67, 100),
+            note_off(96, 9, 36),
+            note_off(0, 0, 60),
+            note_off(0, 10, 64),
+            note_off(0, 15, 67)
+        ];
+        let tempo_map = get_tempo_map(&[&track], Some(60.0)).unwrap();
+        let options = NoteOptions {
+            exclude_drums: true,
+            ..Default::default()
+        };
+        let channels: Vec<(u8, u32)> = get_notes(&track, &tempo_map, 96, &options)
+            .unwrap()
+            .iter()
+            .map(|note| (note.channel, note.pitch_value))
+            .collect();
+        assert_eq!(channels, vec![(0, 60), (10, 64), (15, 67)]);
+    }
+
+    #[test]
+    fn test_get_notes_gm_drum_names() {
+        let track = vec![
+            note_on(0, 9, 36, 100),
+            note_on(0, 9, 42, 100),
+            note_on(0, 9, 20, 100),
+            note_on(0, 0, 36, 100),
+            note_off(96, 9, 36),
+            note_off(0, 9, 42),
+            note_off(0, 9, 20),
+            note_off(0, 0, 36)
+        ];
+        let tempo_map = get_tempo_map(&[&track], Some(60.0)).unwrap();
+        let options = NoteOptions {
+            gm_drum_names: true,
+            ..Default::default()
+        };
+        let drum_names: Vec<Option<String>> = get_notes(&track, &tempo_map, 96, &options)
+            .unwrap()
+            .into_iter()
+            .map(|note| note.drum_name)
+            .collect();
+        assert_eq!(drum_names, vec![
+            Some("Bass Drum 1".to_string()),
+            Some("Closed Hi-Hat".to_string()),
+            None,
+            None
+        ]);
+
+        let notes = get_notes(&track, &tempo_map, 96, &Default::default()).unwrap();
+        assert!(notes.iter().all(|note| note.drum_name.is_none()));
+    }
+
+    #[test]
+    fn test_get_notes_pitch_class() {
+        // Every pitch class, spread across several octaves
+        let mut track = Vec::new();
+        for pitch in [0, 13, 26, 39, 52, 65, 78, 91, 104, 117, 106, 119] {
+            track.push(note_on(0, 0, pitch, 100));
+            track.push(note_off(96, 0, pitch));
+        }
+        let tempo_map = get_tempo_map(&[&track], Some(60.0)).unwrap();
+        let options = NoteOptions {
+            pitch_class: true,
+            pitch_class_names: true,
+            flat_names: true,
+            ..Default::default()
+        };
+        let pitch_classes: Vec<(u32, u8, String)> = get_notes(&track, &tempo_map, 96, &options)
+            .unwrap()
+            .into_iter()
+            .map(|note| (note.pitch_value, note.pitch_class.unwrap(), note.pitch_class_name.unwrap()))
+            .collect();
+        assert_eq!(pitch_classes, vec![
+            (0, 0, "C".to_string()),
+            (13, 1, "C#".to_string()),
+            (26, 2, "D".to_string()),
+            (39, 3, "D#".to_string()),
+            (52, 4, "E".to_string()),
+            (65, 5, "F".to_string()),
+            (78, 6, "F#".to_string()),
+            (91, 7, "G".to_string()),
+            (104, 8, "G#".to_string()),
+            (117, 9, "A".to_string()),
+            (106, 10, "A#".to_string()),
+            (119, 11, "B".to_string())
+        ]);
+    }
+}
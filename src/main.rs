@@ -1,137 +1,1836 @@
-use std::fs;
+use std::env;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::ops::{Bound, Range, RangeBounds};
+use std::path::{Component, Path, PathBuf};
+use std::process;
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use flate2::Compression;
+use flate2::write::GzEncoder;
 use clap::{
     Arg,
-    App
+    ArgGroup,
+    App,
+    AppSettings,
+    ArgMatches,
+    SubCommand
 };
-use serde::{Serialize};
-use serde_json;
-use midly::{
-    SmfBuffer,
-    Event,
-    EventKind,
-    MidiMessage
+use midi2json::{
+    Midi2JsonError,
+    NoteInfo,
+    NoteOptions,
+    Options,
+    Result,
+    SortDirection,
+    SortKey,
+    TrackNotes,
+    TransposeMode,
+    TruncatedNotes,
+    TimeUnit,
+    QUARTER_NOTE,
+    get_measure_starts,
+    process_bytes_with_log,
+    process_files_with_log,
+    read_event_log,
+    stream_bytes
 };
+use midi2json::gzip;
+use midi2json::header::get_chunks;
+use midi2json::names::{key_pitch_classes, PitchSpeller};
+use midi2json::json2midi::{json_to_midi, Json2MidiOptions};
+use midi2json::piano_roll::render_piano_roll;
+use midi2json::typescript::write_typescript;
+use midi2json::output::{
+    write_event_log,
+    write_jsonl_note,
+    write_output,
+    write_schema,
+    OutputFormat,
+    CsvOptions
+};
+
+const DEFAULT_OUTPUT_FILENAME: &str = "output/notes.json";
+/// The name of the output file for input from stdin, or from several merged files
+const DEFAULT_OUTPUT_NAME: &str = "notes.json";
+const DEFAULT_MIDI_OUTPUT_FILENAME: &str = "output/notes.mid";
+/// How often --watch checks whether the input has changed
+const WATCH_INTERVAL: Duration = Duration::from_millis(250);
+/// How many more times --watch tries to convert the input if it can't be read
+const WATCH_RETRIES: u32 = 3;
+
+/// Everything the command line asked for
+struct Settings {
+    /// Whether the input is gzip-compressed, or `None` to detect it
+    compressed: Option<bool>,
+    options: Options,
+    output_format: OutputFormat,
+    /// How many decimal places to round floats in the output to, if set
+    precision: Option<u32>,
+    /// Whether to gzip the output
+    gzip: bool,
+    /// How many columns to draw a piano roll with, if one is wanted
+    visualize_columns: Option<usize>,
+    /// Whether to draw the piano roll to stdout instead of writing any output
+    visualize_only: bool,
+    /// What to write a file for each of, next to the output file
+    split: Option<Split>,
+    /// Whether to write a file for each track even if it has no notes
+    include_empty_tracks: bool,
+    /// Whether to copy the other fields into each split file instead of
+    /// writing the output file
+    merge_into: bool,
+    /// Where to write every event in the input, if anywhere
+    event_log: Option<String>,
+    /// Whether to only convert the input to see if it can be, without writing it
+    check: bool,
+    /// Whether to write the warnings from checking the input to stderr
+    check_report: bool
+}
+
+/// How much progress output to write
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum LogLevel {
+    /// Nothing, since errors are written to stderr separately
+    Error,
+    /// What's being done, and any warnings
+    Info,
+    /// Each note that was converted too
+    Debug,
+    /// Where each chunk of the file is too
+    Trace
+}
+
+/// Writes progress output, leaving out anything more detailed than its level
+struct Logger {
+    level: LogLevel,
+    writer: Box<dyn Write>,
+    sink: io::Sink
+}
+
+impl Logger {
+    /// Where to write a message at this level
+    fn at(&mut self, level: LogLevel) -> &mut dyn Write {
+        if level <= self.level {
+            &mut self.writer
+        } else {
+            &mut self.sink
+        }
+    }
+
+    fn info(&mut self) -> &mut dyn Write {
+        self.at(LogLevel::Info)
+    }
+
+    fn debug(&mut self) -> &mut dyn Write {
+        self.at(LogLevel::Debug)
+    }
 
-#[derive(Clone, Copy, Debug, Serialize)]
-struct Note {
-    time_start: f64,
-    time_end: f64,
-    pitch_value: u32
+    fn trace(&mut self) -> &mut dyn Write {
+        self.at(LogLevel::Trace)
+    }
 }
 
-#[derive(Serialize)]
-struct NoteInfo {
-    notes: Vec<Note>
+/// How to split the output up into several files
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Split {
+    Channel,
+    Track
 }
 
 fn main() {
-    let matches = App::new("midi2json")
+    // Config file values go before the real arguments, so the real ones override them
+    let mut args: Vec<OsString> = env::args_os().collect();
+    if let Some(config_filename) = find_config_arg(&args) {
+        match read_config(&config_filename) {
+            Ok(config_args) => {
+                args.splice(1..1, config_args.into_iter().map(OsString::from));
+            },
+            Err(error) => {
+                eprintln!("Error: {}", error);
+                process::exit(1);
+            }
+        }
+    }
+
+    let matches = build_app().get_matches_from(args);
+
+    if let Err(error) = run(&matches) {
+        eprintln!("Error: {}", error);
+        process::exit(1);
+    }
+}
+
+fn build_app() -> App<'static, 'static> {
+    App::new("midi2json")
         .author("Andrew Jensen <andrewjensen90@gmail.com>")
         .about("Converts MIDI files into note information in JSON")
+        // A later value for an argument replaces an earlier one, for --config
+        .setting(AppSettings::AllArgsOverrideSelf)
+        // json2midi doesn't take a MIDI file
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .subcommand(SubCommand::with_name("json2midi")
+            .about("Converts note information in JSON back into a MIDI file")
+            .arg(Arg::with_name("input")
+                .short("i")
+                .long("input")
+                .value_name("INPUT")
+                .help("Sets the JSON file to read, or - for stdin")
+                .required(true)
+                .takes_value(true))
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .value_name("OUTPUT")
+                .help("Sets the MIDI file to write, or - for stdout (default output/notes.mid)")
+                .takes_value(true))
+            .arg(Arg::with_name("bpm")
+                .short("b")
+                .long("bpm")
+                .value_name("BPM")
+                .help("Sets the tempo, in beats per minute, if the JSON has no tempo_map (default 120)")
+                .takes_value(true))
+            .arg(Arg::with_name("input-ticks")
+                .long("input-ticks")
+                .help("Reads the note times as ticks, like --output-ticks writes them"))
+            .arg(Arg::with_name("input-beats")
+                .long("input-beats")
+                .help("Reads the note times as quarter-note beats, like --output-beats writes them")
+                .conflicts_with("input-ticks"))
+            .arg(Arg::with_name("beat-resolution")
+                .long("beat-resolution")
+                .value_name("SUBDIVISION")
+                .help("Reads beats in this fraction of a whole note with --input-beats (default 4)")
+                .requires("input-beats")
+                .takes_value(true))
+            .arg(Arg::with_name("ticks-per-beat")
+                .long("ticks-per-beat")
+                .value_name("TICKS")
+                .help("Sets the MIDI file's resolution, instead of the JSON's midi_header or 480")
+                .takes_value(true)))
+        .arg(Arg::with_name("schema")
+            .long("schema")
+            .help("Prints a JSON Schema describing the JSON output, then exits"))
+        .arg(Arg::with_name("emit-typescript")
+            .long("emit-typescript")
+            .help("Prints TypeScript interfaces for the JSON output, then exits"))
+        .arg(Arg::with_name("config")
+            .long("config")
+            .value_name("CONFIG")
+            .help("Reads default arguments from a TOML file, e.g. bpm = 120")
+            .takes_value(true))
         .arg(Arg::with_name("input")
             .short("i")
             .long("input")
             .value_name("INPUT")
-            .help("Sets the input MIDI file to read")
-            .required(true)
+            .help("Sets the input MIDI file to read, or - for stdin. Repeat to merge several files")
+            .required_unless_one(&["input-glob", "schema", "emit-typescript"])
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1))
+        .arg(Arg::with_name("input-glob")
+            .long("input-glob")
+            .value_name("PATTERN")
+            .help("Converts every .mid or .midi file matching this glob pattern, like \"music/**/*\", as with --batch")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1))
+        .arg(Arg::with_name("compressed")
+            .long("compressed")
+            .help("Decompresses the input with gzip, instead of detecting whether it's compressed")
+            .conflicts_with("no-compressed"))
+        .arg(Arg::with_name("no-compressed")
+            .long("no-compressed")
+            .help("Reads the input as-is, instead of detecting whether it's compressed with gzip"))
+        .arg(Arg::with_name("batch")
+            .short("B")
+            .long("batch")
+            .help("Converts each input file separately, writing the output next to it (or to --output-dir)")
+            .conflicts_with_all(&["output", "stdout"]))
+        .arg(Arg::with_name("verbose")
+            .short("v")
+            .long("verbose")
+            .help("Writes each note as it's converted, and with -vv where each chunk of the file is")
+            .multiple(true))
+        .arg(Arg::with_name("quiet")
+            .short("q")
+            .long("quiet")
+            .help("Doesn't write anything but the output and any errors")
+            .conflicts_with("verbose"))
+        .arg(Arg::with_name("include-running-stats")
+            .long("include-running-stats")
+            .value_name("INTERVAL")
+            .help("Writes how many notes have been processed, and how long it's taken, every this many notes")
             .takes_value(true))
+        .arg(Arg::with_name("event-log")
+            .long("event-log")
+            .value_name("PATH")
+            .help("Also writes every event in the file, of any kind, to this file as JSON, for debugging")
+            .takes_value(true)
+            .conflicts_with_all(&["batch", "input-glob"]))
+        .arg(Arg::with_name("check")
+            .long("check")
+            .help("Converts the notes to make sure the file can be, without writing any output, failing if it can't")
+            .conflicts_with_all(&["output", "stdout", "output-dir", "watch", "visualize", "visualize-only", "split-by-channel", "split-by-track"]))
+        .arg(Arg::with_name("check-report")
+            .long("check-report")
+            .help("Checks the file like --check, and writes its warnings to stderr, counting notes with no NoteOff instead of failing")
+            .conflicts_with_all(&["output", "stdout", "output-dir", "watch", "visualize", "visualize-only", "split-by-channel", "split-by-track"]))
+        .arg(Arg::with_name("watch")
+            .long("watch")
+            .help("Converts the input again each time it changes, until stopped")
+            .conflicts_with_all(&["batch", "visualize-only"]))
+        .arg(Arg::with_name("output-dir")
+            .long("output-dir")
+            .value_name("DIR")
+            .help("Writes the output into this directory, named after the input, or next to the input for .")
+            .takes_value(true))
+        .arg(Arg::with_name("split-by-channel")
+            .long("split-by-channel")
+            .help("Writes a file for each channel with notes, like notes_ch9.json, as well as the whole output")
+            .conflicts_with_all(&["stdout", "split-by-track"]))
+        .arg(Arg::with_name("split-by-track")
+            .long("split-by-track")
+            .help("Writes a file for each track with notes, like notes_Piano.json or notes_track1.json, as well as the whole output")
+            .conflicts_with_all(&["stdout", "all-tracks"]))
+        .arg(Arg::with_name("include-empty-tracks")
+            .long("include-empty-tracks")
+            .help("Also writes a file for each track without any notes, for --split-by-track")
+            .requires("split-by-track"))
+        .arg(Arg::with_name("merge-into")
+            .long("merge-into")
+            .help("Copies the other fields into each split file, instead of writing the whole output separately"))
         .arg(Arg::with_name("bpm")
             .short("b")
             .long("bpm")
             .value_name("BPM")
-            .help("Sets the tempo, in beats per minute")
-            .required(true)
+            .help("Sets the tempo, in beats per minute, if the file has no tempo events")
+            .takes_value(true))
+        .arg(Arg::with_name("no-bpm-required")
+            .long("no-bpm-required")
+            .help("Writes the note times in ticks, as time_start_ticks and time_end_ticks, if the file has no tempo events and there's no --bpm")
+            .conflicts_with_all(&["bpm", "visualize", "visualize-only"]))
+        .arg(Arg::with_name("output")
+            .short("o")
+            .long("output")
+            .value_name("OUTPUT")
+            .help("Sets the output JSON file to write, or - for stdout")
+            .default_value(DEFAULT_OUTPUT_FILENAME)
+            .takes_value(true))
+        .arg(Arg::with_name("stdout")
+            .long("stdout")
+            .help("Writes the JSON to stdout, same as --output -")
+            .conflicts_with("output"))
+        .arg(Arg::with_name("format")
+            .short("f")
+            .long("format")
+            .value_name("FORMAT")
+            .help("Sets the output format")
+            .possible_values(&["json", "jsonl", "yaml", "csv"])
+            .default_value("json")
+            .takes_value(true))
+        .arg(Arg::with_name("gzip")
+            .long("gzip")
+            .help("Compresses the output with gzip, adding .gz to the output filename"))
+        .arg(Arg::with_name("visualize")
+            .long("visualize")
+            .help("Draws the notes as a piano roll on stderr"))
+        .arg(Arg::with_name("visualize-only")
+            .long("visualize-only")
+            .help("Draws the notes as a piano roll on stdout, without writing any output"))
+        .arg(Arg::with_name("visualize-columns")
+            .long("visualize-columns")
+            .value_name("COLUMNS")
+            .help("Sets how many columns of time the piano roll has (default 80)")
+            .takes_value(true))
+        .arg(Arg::with_name("compact")
+            .long("compact")
+            .help("Writes JSON output on a single line, without pretty-printing"))
+        .arg(Arg::with_name("precision")
+            .long("precision")
+            .value_name("DECIMAL_PLACES")
+            .help("Rounds every float in the output to this many decimal places")
+            .takes_value(true))
+        .arg(Arg::with_name("csv-separator")
+            .long("csv-separator")
+            .value_name("SEPARATOR")
+            .help("Sets the column separator for CSV output")
+            .default_value(",")
+            .takes_value(true))
+        .arg(Arg::with_name("csv-header")
+            .long("csv-header")
+            .help("Writes a header row in CSV output (the default)")
+            .overrides_with("no-csv-header"))
+        .arg(Arg::with_name("no-csv-header")
+            .long("no-csv-header")
+            .help("Leaves out the header row in CSV output")
+            .overrides_with("csv-header"))
+        .arg(Arg::with_name("velocity-normalized")
+            .long("velocity-normalized")
+            .help("Adds each note's velocity scaled to [0.0, 1.0] as velocity_f"))
+        .arg(Arg::with_name("min-pitch")
+            .long("min-pitch")
+            .value_name("PITCH")
+            .help("Only includes notes at or above this MIDI pitch (0-127)")
+            .takes_value(true))
+        .arg(Arg::with_name("max-pitch")
+            .long("max-pitch")
+            .value_name("PITCH")
+            .help("Only includes notes at or below this MIDI pitch (0-127)")
+            .takes_value(true))
+        .arg(Arg::with_name("key-filter")
+            .long("key-filter")
+            .value_name("KEY")
+            .help("Only includes notes in this key's scale, like \"C major\", \"G# minor\", or \"Bb dorian\", or in --key's without one")
+            .takes_value(true)
+            .min_values(0))
+        .arg(Arg::with_name("key")
+            .long("key")
+            .value_name("KEY")
+            .help("Spells pitch names the way they're spelled in this key, like F# in \"G major\" and Bb in \"F major\"")
+            .conflicts_with("flat-names")
+            .takes_value(true))
+        .arg(Arg::with_name("min-velocity")
+            .long("min-velocity")
+            .value_name("VELOCITY")
+            .help("Only includes notes played at or above this velocity (0-127)")
+            .takes_value(true))
+        .arg(Arg::with_name("max-velocity")
+            .long("max-velocity")
+            .value_name("VELOCITY")
+            .help("Only includes notes played at or below this velocity (0-127)")
+            .takes_value(true))
+        .arg(Arg::with_name("time-start")
+            .long("time-start")
+            .value_name("SECONDS")
+            .help("Only includes notes that start at or after this time")
+            .takes_value(true))
+        .arg(Arg::with_name("time-end")
+            .long("time-end")
+            .value_name("SECONDS")
+            .help("Only includes notes that start before this time, cutting short any still held then")
+            .takes_value(true))
+        .arg(Arg::with_name("time-range-beats")
+            .long("time-range-beats")
+            .help("Reads --time-start and --time-end as quarter-note beats instead of seconds"))
+        .arg(Arg::with_name("transpose")
+            .long("transpose")
+            .value_name("SEMITONES")
+            .help("Shifts every note's pitch by this many semitones (e.g. -12 or +7)")
+            .takes_value(true)
+            .allow_hyphen_values(true))
+        .arg(Arg::with_name("transpose-clamp")
+            .long("transpose-clamp")
+            .help("Moves notes transposed outside 0-127 to the nearest valid pitch (the default)")
+            .overrides_with("transpose-discard"))
+        .arg(Arg::with_name("transpose-discard")
+            .long("transpose-discard")
+            .help("Leaves out notes transposed outside 0-127")
+            .overrides_with("transpose-clamp"))
+        .arg(Arg::with_name("pitch-names")
+            .short("n")
+            .long("pitch-names")
+            .help("Adds each note's pitch name (e.g. C4) as pitch_name"))
+        .arg(Arg::with_name("flat-names")
+            .long("flat-names")
+            .help("Spells pitch names with flats instead of sharps")
+            .requires("pitch-names"))
+        .arg(Arg::with_name("sustain-pedal")
+            .long("sustain-pedal")
+            .help("Extends notes released while the sustain pedal is down until the pedal comes up"))
+        .arg(Arg::with_name("include-pitch-class")
+            .long("include-pitch-class")
+            .help("Adds each note's pitch class (0-11, where 0 is C) as pitch_class"))
+        .arg(Arg::with_name("pitch-class-names")
+            .long("pitch-class-names")
+            .help("Adds each note's pitch class name (e.g. C#) as pitch_class_name"))
+        .arg(Arg::with_name("include-frequency")
+            .long("include-frequency")
+            .help("Adds each note's equal-tempered frequency as frequency_hz"))
+        .arg(Arg::with_name("a4-hz")
+            .long("a4-hz")
+            .value_name("FREQUENCY")
+            .help("Sets the frequency of A4 for --include-frequency")
+            .default_value("440")
+            .takes_value(true))
+        .arg(Arg::with_name("exclude-drums")
+            .long("exclude-drums")
+            .help("Leaves out notes on channel 10, which General MIDI reserves for percussion"))
+        .arg(Arg::with_name("gm-drum-names")
+            .long("gm-drum-names")
+            .help("Adds the General MIDI percussion sound of each note on channel 10 as drum_name"))
+        .arg(Arg::with_name("quantize")
+            .long("quantize")
+            .value_name("BEATS")
+            .help("Snaps note times to the nearest multiple of this many beats, e.g. 0.25 for sixteenth notes")
+            .takes_value(true))
+        .arg(Arg::with_name("quantize-start-only")
+            .long("quantize-start-only")
+            .requires("quantize")
+            .help("Only snaps note start times with --quantize, keeping each note's end as played"))
+        .arg(Arg::with_name("swing")
+            .long("swing")
+            .value_name("RATIO")
+            .help("Delays the notes on off-beat eighths to this fraction of the beat, from 0.5 (straight) to 1, e.g. 0.67 for triplet swing")
+            .takes_value(true))
+        .arg(Arg::with_name("truncated-notes")
+            .long("truncated-notes")
+            .value_name("MODE")
+            .help("Sets what to do with notes still held when their track ends")
+            .possible_values(&["error", "include", "discard"])
+            .default_value("error")
+            .takes_value(true))
+        .arg(Arg::with_name("output-ticks")
+            .long("output-ticks")
+            .help("Writes note times as MIDI ticks instead of seconds"))
+        .arg(Arg::with_name("output-beats")
+            .long("output-beats")
+            .help("Writes note times as quarter-note beats instead of seconds")
+            .conflicts_with("output-ticks"))
+        .arg(Arg::with_name("beat-resolution")
+            .long("beat-resolution")
+            .value_name("SUBDIVISION")
+            .help("Counts beats in this fraction of a whole note with --output-beats, e.g. 8 for eighth notes (default 4)")
+            .requires("output-beats")
+            .takes_value(true))
+        .arg(Arg::with_name("all-tracks")
+            .long("all-tracks")
+            .help("Merges the notes from every track into a single list, or splits a Type 0 file's notes by channel"))
+        .arg(Arg::with_name("normalize-start")
+            .long("normalize-start")
+            .help("Shifts note times so the first note starts at 0, recording the shift as offset_seconds"))
+        .arg(Arg::with_name("merge-legato")
+            .long("merge-legato")
+            .help("Merges notes of the same pitch and channel that overlap or touch into one"))
+        .arg(Arg::with_name("legato-classification")
+            .long("legato-classification")
+            .help("Tags each note as legato or staccato, by how soon the next note of its pitch starts"))
+        .arg(Arg::with_name("legato-threshold")
+            .long("legato-threshold")
+            .value_name("SECONDS")
+            .help("Sets the longest gap before the next note that is still legato, in seconds (or ticks or beats) (default 0.05)")
+            .takes_value(true)
+            .requires("legato-classification"))
+        .arg(Arg::with_name("legato-gap")
+            .long("legato-gap")
+            .value_name("GAP")
+            .help("Also merges notes up to this many seconds (or ticks or beats) apart with --merge-legato")
+            .requires("merge-legato")
+            .takes_value(true))
+        .arg(Arg::with_name("min-duration")
+            .long("min-duration")
+            .value_name("DURATION")
+            .help("Leaves out notes shorter than this many seconds (or ticks or beats)")
+            .takes_value(true))
+        .arg(Arg::with_name("max-duration")
+            .long("max-duration")
+            .value_name("DURATION")
+            .help("Leaves out notes longer than this many seconds (or ticks or beats), after --split-long-notes")
+            .takes_value(true))
+        .arg(Arg::with_name("humanize")
+            .long("humanize")
+            .value_name("MAX_MS")
+            .help("Moves each note's start and end randomly by up to half this many milliseconds either way")
+            .conflicts_with_all(&["output-ticks", "output-beats"])
+            .takes_value(true))
+        .arg(Arg::with_name("seed")
+            .long("seed")
+            .value_name("SEED")
+            .help("Seeds --humanize, so it moves the notes the same way every time")
+            .requires("humanize")
+            .takes_value(true))
+        .arg(Arg::with_name("max-notes")
+            .long("max-notes")
+            .value_name("N")
+            .help("Only keeps the first N notes to end, and stops reading each track once it has them")
+            .takes_value(true))
+        .arg(Arg::with_name("max-notes-from-end")
+            .long("max-notes-from-end")
+            .value_name("N")
+            .help("Only keeps the last N notes to end")
+            .conflicts_with("max-notes")
+            .takes_value(true))
+        .arg(Arg::with_name("split-long-notes")
+            .long("split-long-notes")
+            .value_name("MAX_DURATION")
+            .help("Splits notes longer than this many seconds (or ticks or beats) into equal segments")
+            .takes_value(true))
+        .arg(Arg::with_name("check-overlaps")
+            .long("check-overlaps")
+            .help("Lists notes of the same pitch and channel that overlap as overlaps"))
+        .arg(Arg::with_name("resolve-overlaps")
+            .long("resolve-overlaps")
+            .help("Like --check-overlaps, and also ends each overlapping note when the next one starts"))
+        .arg(Arg::with_name("chord-window")
+            .long("chord-window")
+            .value_name("WINDOW")
+            .help("Groups notes that start within this many seconds (or ticks or beats) of each other into chords")
+            .takes_value(true))
+        .arg(Arg::with_name("sort-by-pitch")
+            .long("sort-by-pitch")
+            .help("Sorts the notes by pitch, then by start time"))
+        .arg(Arg::with_name("sort-by-duration")
+            .long("sort-by-duration")
+            .help("Sorts the notes by duration, longest first, then by start time"))
+        .group(ArgGroup::with_name("sort")
+            .args(&["sort-by-pitch", "sort-by-duration"]))
+        .arg(Arg::with_name("sort-direction")
+            .long("sort-direction")
+            .value_name("DIRECTION")
+            .help("Sets the direction to sort the notes in, instead of the sort's default")
+            .possible_values(&["asc", "desc"])
+            .requires("sort")
+            .takes_value(true))
+        .arg(Arg::with_name("deduplicate")
+            .long("deduplicate")
+            .help("Removes notes with the same pitch, channel, start, and end as an earlier one"))
+        .arg(Arg::with_name("deduplicate-report")
+            .long("deduplicate-report")
+            .help("Removes duplicate notes like --deduplicate, and adds duplicates_removed to statistics"))
+        .arg(Arg::with_name("relative-time")
+            .long("relative-time")
+            .help("Adds each note's time since the previous note started as delta_start"))
+        .arg(Arg::with_name("include-header")
+            .long("include-header")
+            .help("Adds the file's format, track count, and timing as midi_header"))
+        .arg(Arg::with_name("include-stats")
+            .long("include-stats")
+            .help("Adds summary statistics about the notes as statistics"))
+        .arg(Arg::with_name("include-entropy")
+            .long("include-entropy")
+            .help("Adds how evenly the notes are spread over the 12 pitch classes, in bits, to statistics as pitch_entropy"))
+        .arg(Arg::with_name("include-tempo-map")
+            .long("include-tempo-map")
+            .help("Adds the file's tempo changes as tempo_map"))
+        .arg(Arg::with_name("include-time-signatures")
+            .long("include-time-signatures")
+            .help("Adds the file's time signature changes as time_signatures"))
+        .arg(Arg::with_name("include-key-signatures")
+            .long("include-key-signatures")
+            .help("Adds the file's key signature changes as key_signatures"))
+        .arg(Arg::with_name("include-text-events")
+            .long("include-text-events")
+            .help("Adds the file's lyrics, markers, and other text events as text_events"))
+        .arg(Arg::with_name("include-controllers")
+            .long("include-controllers")
+            .help("Adds the file's control change events as control_changes"))
+        .arg(Arg::with_name("controller")
+            .long("controller")
+            .value_name("CONTROLLER")
+            .help("Only includes these controller numbers (0-127) in control_changes")
+            .takes_value(true)
+            .multiple(true)
+            .requires("include-controllers"))
+        .arg(Arg::with_name("include-program-changes")
+            .long("include-program-changes")
+            .help("Adds the file's program change events as program_changes"))
+        .arg(Arg::with_name("gm-names")
+            .long("gm-names")
+            .help("Adds the General MIDI instrument name to each program change")
+            .requires("include-program-changes"))
+        .arg(Arg::with_name("include-pitch-bend")
+            .long("include-pitch-bend")
+            .help("Adds the file's pitch bend events as pitch_bends"))
+        .arg(Arg::with_name("pitch-bend-semitones")
+            .long("pitch-bend-semitones")
+            .help("Adds each pitch bend in semitones")
+            .requires("include-pitch-bend"))
+        .arg(Arg::with_name("pitch-bend-range")
+            .long("pitch-bend-range")
+            .value_name("SEMITONES")
+            .help("Sets how far the pitch wheel bends each way, for --pitch-bend-semitones")
+            .default_value("2")
+            .takes_value(true))
+        .arg(Arg::with_name("annotate-measures")
+            .long("annotate-measures")
+            .help("Adds the measure and beat each note starts on"))
+        .arg(Arg::with_name("annotate-source")
+            .long("annotate-source")
+            .help("Adds the index of the track each note came from as source_track"))
+        .arg(Arg::with_name("note-density")
+            .long("note-density")
+            .help("Adds the number of notes that start in each measure as note_density"))
+        .arg(Arg::with_name("note-density-window")
+            .long("note-density-window")
+            .value_name("SECONDS")
+            .help("Adds how many notes a second start in each window of this many seconds as density_timeline")
+            .conflicts_with_all(&["output-ticks", "output-beats"])
             .takes_value(true))
-        .get_matches();
+        .arg(Arg::with_name("pitch-histogram")
+            .long("pitch-histogram")
+            .help("Adds the number of notes of each pitch as pitch_histogram"))
+        .arg(Arg::with_name("include-ioi")
+            .long("include-ioi")
+            .help("Adds the time between each note's start and the next one's as inter_onset_intervals"))
+        .arg(Arg::with_name("ioi-per-pitch")
+            .long("ioi-per-pitch")
+            .help("Lists the inter-onset intervals separately for each pitch")
+            .requires("include-ioi"))
+        .arg(Arg::with_name("filter-channel")
+            .short("c")
+            .long("filter-channel")
+            .value_name("CHANNEL")
+            .help("Only includes notes on this MIDI channel (0-15)")
+            .takes_value(true))
+}
 
-    println!("Got matches!");
+/// Finds the --config argument before the arguments are parsed, since the
+/// config file adds to them
+fn find_config_arg(args: &[OsString]) -> Option<String> {
+    let mut args = args.iter().map(|arg| arg.to_string_lossy());
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            return args.next().map(String::from);
+        }
+        if let Some(config_filename) = arg.strip_prefix("--config=") {
+            return Some(config_filename.to_string());
+        }
+    }
 
-    let input_filename = matches.value_of("input").unwrap();
-    let bpm_raw = matches.value_of("bpm").unwrap();
-    let bpm = bpm_raw.parse::<f32>()
-        .expect("Cannot parse BPM");
-
-    process(input_filename, bpm);
-}
-
-fn process(input_filename: &str, bpm: f32) {
-
-    println!("Loading MIDI file...");
-
-    let smf_buffer = SmfBuffer::open(input_filename)
-        .expect("Could not read input file");
-    let smf = smf_buffer.parse_collect()
-        .expect("Could not parse MIDI file contents");
-
-    let track = &smf.tracks[0];
-
-    println!("Handling contents...");
-    let notes = get_notes(track, bpm);
-    println!("Notes:");
-    for note in &notes {
-        println!("  {} to {}: pitch {}", note.time_start, note.time_end, note.pitch_value);
-    }
-
-    println!("Saving output JSON file...");
-    create_json(&notes);
-
-    println!("Done.");
-}
-
-fn get_notes(track: &Vec<Event>, bpm: f32) -> Vec<Note> {
-    let mut notes = Vec::<Note>::new();
-    let mut cur_time: u32 = 0;
-    let mut cur_note: Option<Note> = None;
-    for event in track {
-        let delta = event.delta.as_int();
-        let kind = event.kind;
-        cur_time = cur_time + delta;
-
-        if let EventKind::Midi{ message, channel: _ } = kind {
-            match message {
-                MidiMessage::NoteOn(pitch, _) => {
-                    cur_note = Some(Note {
-                        pitch_value: pitch.as_int() as u32,
-                        time_start: get_time_seconds(cur_time, bpm),
-                        time_end: 0.0
-                    });
-                },
-                MidiMessage::NoteOff(_, _) => {
-                    let partial_note = cur_note.unwrap();
-                    let updated_note = Note {
-                        pitch_value: partial_note.pitch_value,
-                        time_start: partial_note.time_start,
-                        time_end: get_time_seconds(cur_time, bpm)
-                    };
-
-                    notes.push(updated_note);
-                },
-                _ => {}
+    None
+}
+
+fn read_config(config_filename: &str) -> Result<Vec<String>> {
+    let contents = std::fs::read_to_string(config_filename).map_err(|error| {
+        Midi2JsonError::IoError(io::Error::new(error.kind(), format!("Could not read {}: {}", config_filename, error)))
+    })?;
+
+    parse_config(&contents).map_err(|error| {
+        Midi2JsonError::InvalidArgument(format!("Invalid config file {}: {}", config_filename, error))
+    })
+}
+
+/// Turns a TOML config into the equivalent arguments. Each key is an argument's
+/// long name, with underscores or hyphens: `true` passes a flag, other values
+/// are passed as the argument's value, and lists pass it once per item.
+fn parse_config(contents: &str) -> std::result::Result<Vec<String>, String> {
+    let table: toml::Table = contents.parse().map_err(|error: toml::de::Error| error.message().to_string())?;
+
+    let mut args = Vec::new();
+    for (key, value) in table {
+        let name = key.replace('_', "-");
+        if name == "config" {
+            return Err("config can't be set from a config file".to_string());
+        }
+
+        let values = match value {
+            toml::Value::Boolean(true) => {
+                args.push(format!("--{}", name));
+                continue;
+            },
+            toml::Value::Boolean(false) => continue,
+            toml::Value::Array(values) => values,
+            value => vec![value]
+        };
+        for value in values {
+            let value = match value {
+                toml::Value::String(value) => value,
+                toml::Value::Integer(value) => value.to_string(),
+                toml::Value::Float(value) => value.to_string(),
+                _ => return Err(format!("{} must be a string, number, or boolean", key))
+            };
+            // With = so that negative numbers aren't taken for arguments
+            args.push(format!("--{}={}", name, value));
+        }
+    }
+
+    Ok(args)
+}
+
+fn run(matches: &ArgMatches) -> Result<()> {
+    if let Some(matches) = matches.subcommand_matches("json2midi") {
+        return run_json2midi(matches);
+    }
+    if matches.is_present("schema") {
+        write_schema(&mut io::stdout().lock())?;
+        return Ok(());
+    }
+    if matches.is_present("emit-typescript") {
+        write_typescript(&mut io::stdout().lock())?;
+        return Ok(());
+    }
+
+    let mut input_filenames: Vec<String> = matches.values_of("input")
+        .map(|values| values.map(String::from).collect())
+        .unwrap_or_default();
+    // Files from a glob keep their place under the pattern's base directory in --output-dir
+    let mut output_subdirs = vec![PathBuf::new(); input_filenames.len()];
+    for pattern in matches.values_of("input-glob").into_iter().flatten() {
+        for (input_filename, output_subdir) in expand_glob(pattern)? {
+            input_filenames.push(input_filename);
+            output_subdirs.push(output_subdir);
+        }
+    }
+
+    let batch = matches.is_present("batch") || matches.is_present("input-glob");
+    // Without --batch, several inputs are merged into one output
+    let merge_inputs = !batch && input_filenames.len() > 1;
+
+    let bpm = matches.value_of("bpm")
+        .map(parse_bpm)
+        .transpose()?;
+
+    let split = if matches.is_present("split-by-channel") {
+        Some(Split::Channel)
+    } else if matches.is_present("split-by-track") {
+        Some(Split::Track)
+    } else {
+        None
+    };
+    let output_dir = matches.value_of("output-dir").map(Path::new);
+    if let Some(output_dir) = output_dir {
+        if matches.occurrences_of("output") > 0 {
+            return Err(Midi2JsonError::InvalidArgument(
+                "--output and --output-dir can't be used together".to_string()
+            ));
+        }
+        if !output_dir.is_dir() {
+            return Err(Midi2JsonError::IoError(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Output directory does not exist: {}", output_dir.display())
+            )));
+        }
+    }
+    if matches.is_present("merge-into") && split.is_none() {
+        return Err(Midi2JsonError::InvalidArgument(
+            "--merge-into can only be used with --split-by-channel or --split-by-track".to_string()
+        ));
+    }
+
+    let output_format = match matches.value_of("format").unwrap() {
+        "csv" => {
+            let separator_raw = matches.value_of("csv-separator").unwrap();
+            let mut separator_chars = separator_raw.chars();
+            let separator = match (separator_chars.next(), separator_chars.next()) {
+                (Some(separator), None) => separator,
+                _ => return Err(Midi2JsonError::InvalidArgument(
+                    "CSV separator must be a single character".to_string()
+                ))
+            };
+
+            OutputFormat::Csv(CsvOptions {
+                separator,
+                header: !matches.is_present("no-csv-header")
+            })
+        },
+        "jsonl" => OutputFormat::Jsonl,
+        "yaml" => OutputFormat::Yaml,
+        _ if matches.is_present("compact") => OutputFormat::CompactJson,
+        _ => OutputFormat::Json
+    };
+
+    let output_filename = if matches.is_present("stdout") {
+        "-".to_string()
+    } else if batch {
+        // Each input gets its own output path instead
+        String::new()
+    } else if split.is_some() || output_dir.is_some() {
+        // When splitting, --output names the directory to split into, rather than a file
+        let output_dir = output_dir
+            .or_else(|| matches.value_of("output").filter(|_| matches.occurrences_of("output") > 0).map(Path::new))
+            .unwrap_or_else(|| Path::new(DEFAULT_OUTPUT_FILENAME).parent().unwrap());
+        // Without an input file to name the output after, it gets the default name
+        let input_filename = match input_filenames[0].as_str() {
+            _ if merge_inputs => DEFAULT_OUTPUT_NAME,
+            "-" => DEFAULT_OUTPUT_NAME,
+            input_filename => input_filename
+        };
+        batch_output_path(input_filename, Some(output_dir), &output_format).to_string_lossy().into_owned()
+    } else {
+        matches.value_of("output").unwrap().to_string()
+    };
+
+    let channel = matches.value_of("filter-channel")
+        .map(parse_channel)
+        .transpose()?;
+
+    let (min_pitch, max_pitch) = parse_range(matches, "min-pitch", "max-pitch", "Pitch")?;
+    let key = matches.value_of("key");
+    let key_filter = if matches.is_present("key-filter") {
+        match matches.value_of("key-filter").or(key) {
+            Some(key_raw) => Some(parse_key_filter(key_raw)?),
+            None => return Err(Midi2JsonError::InvalidArgument(
+                "--key-filter needs a key, or --key to take it from".to_string()
+            ))
+        }
+    } else {
+        None
+    };
+    let (min_velocity, max_velocity) = parse_range(matches, "min-velocity", "max-velocity", "Velocity")?;
+
+    let a4_hz = if matches.is_present("include-frequency") {
+        Some(parse_a4_hz(matches.value_of("a4-hz").unwrap())?)
+    } else {
+        None
+    };
+
+    let quantize = matches.value_of("quantize")
+        .map(parse_quantize)
+        .transpose()?;
+    let swing = matches.value_of("swing")
+        .map(parse_swing)
+        .transpose()?;
+
+    let beat_resolution = matches.value_of("beat-resolution")
+        .map(parse_beat_resolution)
+        .transpose()?
+        .unwrap_or(QUARTER_NOTE);
+
+    let min_duration = matches.value_of("min-duration")
+        .map(parse_min_duration)
+        .transpose()?;
+
+    let note_options = NoteOptions {
+        velocity_normalized: matches.is_present("velocity-normalized"),
+        channel,
+        exclude_drums: matches.is_present("exclude-drums"),
+        min_pitch,
+        max_pitch,
+        key_filter,
+        min_velocity,
+        max_velocity,
+        pitch_names: matches.is_present("pitch-names"),
+        flat_names: matches.is_present("flat-names"),
+        key: key.map(parse_key).transpose()?,
+        pitch_class: matches.is_present("include-pitch-class"),
+        pitch_class_names: matches.is_present("pitch-class-names"),
+        a4_hz,
+        gm_drum_names: matches.is_present("gm-drum-names"),
+        quantize,
+        quantize_start_only: matches.is_present("quantize-start-only"),
+        swing,
+        min_duration,
+        sustain_pedal: matches.is_present("sustain-pedal"),
+        truncated_notes: match matches.value_of("truncated-notes").unwrap() {
+            "include" => TruncatedNotes::Include,
+            "discard" => TruncatedNotes::Discard,
+            _ => TruncatedNotes::Error
+        },
+        time_unit: if matches.is_present("output-ticks") {
+            TimeUnit::Ticks
+        } else if matches.is_present("output-beats") {
+            TimeUnit::Beats(beat_resolution)
+        } else {
+            TimeUnit::Seconds
+        },
+        ..Default::default()
+    };
+
+    // Keep stdout clean for the JSON (or the piano roll) when it's being piped
+    // somewhere. The running stats go to stderr even when writing a file, and
+    // they're written to the log along with everything else.
+    let visualize_only = matches.is_present("visualize-only");
+    let mut log = Logger {
+        level: match matches.occurrences_of("verbose") {
+            _ if matches.is_present("quiet") => LogLevel::Error,
+            0 => LogLevel::Info,
+            1 => LogLevel::Debug,
+            _ => LogLevel::Trace
+        },
+        writer: if output_filename == "-" || visualize_only || matches.is_present("include-running-stats") {
+            Box::new(io::stderr())
+        } else {
+            Box::new(io::stdout())
+        },
+        sink: io::sink()
+    };
+
+    writeln!(log.info(), "Got matches!")?;
+
+    let check = matches.is_present("check") || matches.is_present("check-report");
+    if !batch && !visualize_only && !check {
+        check_output_path(&output_filename)?;
+    }
+    if let Some(event_log_filename) = matches.value_of("event-log") {
+        check_output_path(event_log_filename)?;
+    }
+
+    let controllers = matches.values_of("controller")
+        .map(|values| {
+            values.map(|controller_raw| parse_midi_value(controller_raw, "Controller"))
+                .collect::<Result<Vec<u8>>>()
+        })
+        .transpose()?;
+
+    let pitch_bend_range = if matches.is_present("pitch-bend-semitones") {
+        Some(parse_pitch_bend_range(matches.value_of("pitch-bend-range").unwrap())?)
+    } else {
+        None
+    };
+
+    let transpose = matches.value_of("transpose")
+        .map(parse_transpose)
+        .transpose()?
+        .unwrap_or(0);
+
+    let time_start = matches.value_of("time-start").map(parse_time).transpose()?;
+    let time_end = matches.value_of("time-end").map(parse_time).transpose()?;
+    if let (Some(time_start), Some(time_end)) = (time_start, time_end) {
+        if time_start >= time_end {
+            return Err(Midi2JsonError::InvalidArgument(
+                format!("--time-start {} is not before --time-end {}", time_start, time_end)
+            ));
+        }
+    }
+
+    // These need every note before writing any
+    if output_format == OutputFormat::Jsonl {
+        for flag in ["split-by-channel", "split-by-track", "deduplicate", "deduplicate-report", "merge-legato", "split-long-notes", "max-duration", "max-notes-from-end", "humanize", "legato-classification", "normalize-start", "relative-time", "check-overlaps", "resolve-overlaps", "chord-window", "sort-by-pitch", "sort-by-duration", "note-density", "note-density-window", "include-ioi", "pitch-histogram", "include-stats", "include-entropy"] {
+            if matches.is_present(flag) {
+                return Err(Midi2JsonError::InvalidArgument(
+                    format!("--{} needs every note before writing any, so it can't be used with --format jsonl", flag)
+                ));
+            }
+        }
+        // Each line is one note, so there's nowhere to put these
+        for flag in ["include-tempo-map"] {
+            if matches.is_present(flag) {
+                return Err(Midi2JsonError::InvalidArgument(
+                    format!("--{} is written beside the notes, so it can't be used with --format jsonl", flag)
+                ));
+            }
+        }
+    }
+
+    if matches.is_present("no-bpm-required") && matches!(output_format, OutputFormat::Csv(_)) {
+        return Err(Midi2JsonError::InvalidArgument(
+            "--no-bpm-required can't be used with --format csv, which has no columns for the times in ticks".to_string()
+        ));
+    }
+
+    // These describe a whole file, so there's nothing to merge them into
+    if merge_inputs {
+        if output_format == OutputFormat::Jsonl {
+            return Err(Midi2JsonError::InvalidArgument(
+                "--format jsonl can't be used when merging several input files; pass --batch to convert each one".to_string()
+            ));
+        }
+        for flag in ["include-header", "include-tempo-map", "include-time-signatures", "include-key-signatures", "include-text-events", "include-controllers", "include-program-changes", "include-pitch-bend", "note-density", "split-by-track", "event-log", "no-bpm-required", "check", "check-report", "watch"] {
+            if matches.is_present(flag) {
+                return Err(Midi2JsonError::InvalidArgument(
+                    format!("--{} can't be used when merging several input files; pass --batch to convert each one", flag)
+                ));
             }
         }
     }
 
-    notes
+    let options = Options {
+        bpm,
+        no_bpm_required: matches.is_present("no-bpm-required"),
+        note_options,
+        merge_tracks: matches.is_present("all-tracks"),
+        keep_tracks: matches.is_present("split-by-track"),
+        // Progress is only written to the log, which --quiet leaves out anyway
+        progress_interval: matches.value_of("include-running-stats")
+            .filter(|_| !matches.is_present("quiet"))
+            .map(parse_progress_interval)
+            .transpose()?,
+        time_start,
+        time_end,
+        time_range_beats: matches.is_present("time-range-beats"),
+        annotate_source: matches.is_present("annotate-source"),
+        transpose,
+        transpose_mode: if matches.is_present("transpose-discard") {
+            TransposeMode::Discard
+        } else {
+            TransposeMode::Clamp
+        },
+        normalize_start: matches.is_present("normalize-start"),
+        relative_time: matches.is_present("relative-time"),
+        deduplicate: matches.is_present("deduplicate"),
+        deduplicate_report: matches.is_present("deduplicate-report"),
+        merge_legato: if matches.is_present("merge-legato") {
+            Some(matches.value_of("legato-gap").map(parse_legato_gap).transpose()?.unwrap_or(0.0))
+        } else {
+            None
+        },
+        split_long_notes: matches.value_of("split-long-notes")
+            .map(parse_max_duration)
+            .transpose()?,
+        max_duration: matches.value_of("max-duration")
+            .map(parse_max_duration)
+            .transpose()?,
+        max_notes: matches.value_of("max-notes")
+            .or(matches.value_of("max-notes-from-end"))
+            .map(parse_max_notes)
+            .transpose()?,
+        max_notes_from_end: matches.is_present("max-notes-from-end"),
+        humanize: matches.value_of("humanize")
+            .map(parse_humanize)
+            .transpose()?,
+        seed: matches.value_of("seed")
+            .map(parse_seed)
+            .transpose()?,
+        legato_classification: if matches.is_present("legato-classification") {
+            Some(matches.value_of("legato-threshold").map(parse_legato_threshold).transpose()?.unwrap_or(0.05))
+        } else {
+            None
+        },
+        check_overlaps: matches.is_present("check-overlaps"),
+        resolve_overlaps: matches.is_present("resolve-overlaps"),
+        chord_window: matches.value_of("chord-window")
+            .map(parse_chord_window)
+            .transpose()?,
+        sort_by: if matches.is_present("sort-by-pitch") {
+            Some(SortKey::Pitch)
+        } else if matches.is_present("sort-by-duration") {
+            Some(SortKey::Duration)
+        } else {
+            None
+        },
+        sort_direction: matches.value_of("sort-direction").map(|direction| match direction {
+            "desc" => SortDirection::Descending,
+            _ => SortDirection::Ascending
+        }),
+        include_header: matches.is_present("include-header"),
+        include_stats: matches.is_present("include-stats"),
+        include_entropy: matches.is_present("include-entropy"),
+        include_tempo_map: matches.is_present("include-tempo-map"),
+        include_time_signatures: matches.is_present("include-time-signatures"),
+        include_key_signatures: matches.is_present("include-key-signatures"),
+        include_text_events: matches.is_present("include-text-events"),
+        annotate_measures: matches.is_present("annotate-measures"),
+        note_density: matches.is_present("note-density"),
+        note_density_window: matches.value_of("note-density-window")
+            .map(parse_density_window)
+            .transpose()?,
+        pitch_histogram: matches.is_present("pitch-histogram"),
+        include_ioi: matches.is_present("include-ioi"),
+        ioi_per_pitch: matches.is_present("ioi-per-pitch"),
+        include_controllers: matches.is_present("include-controllers"),
+        controllers,
+        include_program_changes: matches.is_present("include-program-changes"),
+        gm_names: matches.is_present("gm-names"),
+        include_pitch_bend: matches.is_present("include-pitch-bend"),
+        pitch_bend_range
+    };
+    let compressed = if matches.is_present("compressed") {
+        Some(true)
+    } else if matches.is_present("no-compressed") {
+        Some(false)
+    } else {
+        None
+    };
+    let settings = Settings {
+        compressed,
+        options,
+        output_format,
+        precision: matches.value_of("precision")
+            .map(parse_precision)
+            .transpose()?,
+        gzip: matches.is_present("gzip"),
+        visualize_columns: if matches.is_present("visualize") || matches.is_present("visualize-only") {
+            Some(matches.value_of("visualize-columns").map(parse_visualize_columns).transpose()?.unwrap_or(80))
+        } else {
+            None
+        },
+        visualize_only,
+        split,
+        include_empty_tracks: matches.is_present("include-empty-tracks"),
+        merge_into: matches.is_present("merge-into"),
+        event_log: matches.value_of("event-log").map(str::to_string),
+        check,
+        check_report: matches.is_present("check-report")
+    };
+
+    if batch {
+        process_batch(&input_filenames, &output_subdirs, output_dir, &settings, &mut log)
+    } else if merge_inputs {
+        process_merged(&input_filenames, &output_filename, &settings, &mut log)
+    } else if matches.is_present("watch") {
+        if input_filenames[0] == "-" {
+            return Err(Midi2JsonError::InvalidArgument("stdin can't be used with --watch".to_string()));
+        }
+        watch(&input_filenames[0], &output_filename, &settings, &mut log)
+    } else {
+        process(&input_filenames[0], &output_filename, &settings, &mut log)
+    }
 }
 
-fn create_json(notes: &Vec<Note>) {
-    let note_info = NoteInfo {
-        notes: notes.clone()
+/// Converts the input, then converts it again each time it changes, forever
+fn watch(input_filename: &str, output_filename: &str, settings: &Settings, log: &mut Logger) -> Result<()> {
+    let mut last_version = get_file_version(input_filename)?;
+    process_with_retries(input_filename, output_filename, settings, log)?;
+    writeln!(log.info(), "[{}] Wrote {}, watching {} for changes", format_timestamp(SystemTime::now()), output_filename, input_filename)?;
+
+    loop {
+        thread::sleep(WATCH_INTERVAL);
+        // The file can be missing for a moment while it's being saved
+        let version = match get_file_version(input_filename) {
+            Ok(version) if version != last_version => version,
+            _ => continue
+        };
+        last_version = version;
+
+        match process_with_retries(input_filename, output_filename, settings, log) {
+            Ok(()) => writeln!(log.info(), "[{}] Updated {}", format_timestamp(SystemTime::now()), output_filename)?,
+            // Keep watching, since the next save might fix it
+            Err(error) => eprintln!("[{}] Error: {}", format_timestamp(SystemTime::now()), error)
+        }
+    }
+}
+
+/// Converts the input, trying again a few times if it fails. Programs saving
+/// the file can lock it or leave it half-written for a moment.
+fn process_with_retries(input_filename: &str, output_filename: &str, settings: &Settings, log: &mut Logger) -> Result<()> {
+    let mut retries = 0;
+    loop {
+        match process(input_filename, output_filename, settings, log) {
+            Err(Midi2JsonError::IoError(_)) | Err(Midi2JsonError::ParseError(_)) if retries < WATCH_RETRIES => {
+                retries += 1;
+                thread::sleep(WATCH_INTERVAL);
+            },
+            result => return result
+        }
+    }
+}
+
+/// When the file was last modified and how long it is, to tell when it changes
+fn get_file_version(filename: &str) -> Result<(SystemTime, u64)> {
+    let metadata = fs::metadata(filename).map_err(|error| {
+        Midi2JsonError::IoError(io::Error::new(error.kind(), format!("Could not read {}: {}", filename, error)))
+    })?;
+
+    Ok((metadata.modified()?, metadata.len()))
+}
+
+/// The time of day in UTC, as HH:MM:SS
+fn format_timestamp(time: SystemTime) -> String {
+    let seconds = time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() % 86_400;
+    format!("{:02}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+/// Expands a glob pattern into the paths of the MIDI files it matches, each
+/// with its directory relative to the pattern's base directory. The pattern is
+/// expanded here rather than by the shell, which doesn't on Windows.
+fn expand_glob(pattern: &str) -> Result<Vec<(String, PathBuf)>> {
+    let paths = glob::glob(pattern)
+        .map_err(|error| Midi2JsonError::InvalidArgument(format!("Invalid glob pattern {}: {}", pattern, error)))?;
+
+    let base = glob_base(pattern);
+    let mut files = Vec::new();
+    for path in paths {
+        let path = path.map_err(|error| Midi2JsonError::IoError(error.into()))?;
+        if path.is_file() && is_midi_file(&path) {
+            let subdir = path.strip_prefix(&base).ok()
+                .and_then(Path::parent)
+                .map(Path::to_path_buf)
+                .unwrap_or_default();
+            files.push((path.to_string_lossy().into_owned(), subdir));
+        }
+    }
+
+    if files.is_empty() {
+        return Err(Midi2JsonError::InvalidArgument(format!("No MIDI files match {}", pattern)));
+    }
+    Ok(files)
+}
+
+/// The directories at the start of a glob pattern, before any wildcards
+fn glob_base(pattern: &str) -> PathBuf {
+    let mut components: Vec<Component> = Path::new(pattern).components()
+        .take_while(|component| !component.as_os_str().to_string_lossy().contains(['*', '?', '[']))
+        .collect();
+    // A pattern without wildcards names a file, not a directory
+    if components.len() == Path::new(pattern).components().count() {
+        components.pop();
+    }
+    components.into_iter().collect()
+}
+
+/// Whether a path has a `.mid` or `.midi` extension, before any `.gz`
+fn is_midi_file(path: &Path) -> bool {
+    let path = match path.extension() {
+        Some(extension) if extension.eq_ignore_ascii_case("gz") => path.with_extension(""),
+        _ => path.to_path_buf()
     };
+    path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("mid") || extension.eq_ignore_ascii_case("midi"))
+}
 
-    let json_str = serde_json::to_string_pretty(&note_info).unwrap();
-    fs::write("output/notes.json", json_str)
-        .expect("Failed to save event frames");
+/// Converts each file separately, carrying on past any that fail
+fn process_batch(
+    input_filenames: &[String],
+    output_subdirs: &[PathBuf],
+    output_dir: Option<&Path>,
+    settings: &Settings,
+    log: &mut Logger
+) -> Result<()> {
+    let mut failures = Vec::<(&str, Midi2JsonError)>::new();
+    for (input_filename, output_subdir) in input_filenames.iter().zip(output_subdirs) {
+        let file_output_dir = match output_dir {
+            Some(output_dir) if output_dir != Path::new(".") => Some(output_dir.join(output_subdir)),
+            output_dir => output_dir.map(Path::to_path_buf)
+        };
+        let output_path = batch_output_path(input_filename, file_output_dir.as_deref(), &settings.output_format);
+        let output_filename = output_path.to_string_lossy();
+        if settings.check {
+            writeln!(log.info(), "Checking {}", input_filename)?;
+        } else {
+            writeln!(log.info(), "Converting {} to {}", input_filename, output_filename)?;
+        }
+
+        let result = if input_filename == "-" {
+            Err(Midi2JsonError::InvalidArgument("stdin can't be used with --batch".to_string()))
+        } else {
+            file_output_dir.as_ref()
+                .filter(|_| !settings.check)
+                .map_or(Ok(()), fs::create_dir_all)
+                .map_err(Midi2JsonError::from)
+                .and_then(|_| process(input_filename, &output_filename, settings, log))
+        };
+        if let Err(error) = result {
+            eprintln!("Error: {}: {}", input_filename, error);
+            failures.push((input_filename, error));
+        }
+    }
+
+    let verb = if settings.check { "Checked" } else { "Converted" };
+    writeln!(log.info(), "{} {} of {} files", verb, input_filenames.len() - failures.len(), input_filenames.len())?;
+    if failures.is_empty() {
+        return Ok(());
+    }
+
+    eprintln!("Failed to convert:");
+    for (input_filename, error) in &failures {
+        eprintln!("  {}: {}", input_filename, error);
+    }
+    Err(Midi2JsonError::BatchFailed {
+        failed: failures.len(),
+        total: input_filenames.len()
+    })
+}
+
+/// Where to write the output for an input file: the same name with the
+/// format's extension, either in `output_dir` or next to the input if there's
+/// no output directory or it's `.`
+fn batch_output_path(input_filename: &str, output_dir: Option<&Path>, output_format: &OutputFormat) -> PathBuf {
+    // song.mid.gz becomes song.json, not song.mid.json
+    let input_path = Path::new(input_filename);
+    let input_path = match input_path.extension() {
+        Some(extension) if extension == "gz" => input_path.with_extension(""),
+        _ => input_path.to_path_buf()
+    };
+    // An output directory of "." means next to the input, wherever that is
+    let output_path = match output_dir {
+        Some(output_dir) if output_dir != Path::new(".") => output_dir.join(input_path.file_name().unwrap_or_default()),
+        _ => input_path
+    };
+
+    output_path.with_extension(output_format.extension())
+}
+
+fn parse_bpm(bpm_raw: &str) -> Result<f32> {
+    match bpm_raw.parse::<f32>() {
+        Ok(bpm) if bpm > 0.0 && bpm.is_finite() => Ok(bpm),
+        _ => Err(Midi2JsonError::InvalidBpm(bpm_raw.to_string()))
+    }
+}
+
+/// Bounds for `parse_in_range`. Both leave out NaN and infinity.
+const POSITIVE: (Bound<f64>, Bound<f64>) = (Bound::Excluded(0.0), Bound::Excluded(f64::INFINITY));
+const AT_LEAST_ZERO: Range<f64> = 0.0..f64::INFINITY;
+
+/// Parses a number in `range`, or fails with "{name} must be {expected}, got ..."
+fn parse_in_range<T: FromStr + PartialOrd>(value_raw: &str, range: impl RangeBounds<T>, name: &str, expected: &str) -> Result<T> {
+    match value_raw.parse::<T>() {
+        Ok(value) if range.contains(&value) => Ok(value),
+        _ => Err(Midi2JsonError::InvalidArgument(
+            format!("{} must be {}, got {}", name, expected, value_raw)
+        ))
+    }
+}
+
+fn parse_ticks_per_beat(ticks_raw: &str) -> Result<u16> {
+    // The top bit of the header's division marks SMPTE timing
+    parse_in_range(ticks_raw, 1..0x8000, "Ticks per beat", "a number from 1 to 32767")
+}
+
+fn parse_channel(channel_raw: &str) -> Result<u8> {
+    parse_in_range(channel_raw, 0..=15, "Channel", "a number from 0 to 15")
+}
+
+fn parse_transpose(semitones_raw: &str) -> Result<i32> {
+    parse_in_range(semitones_raw, -127..=127, "Transpose", "a number of semitones from -127 to 127")
+}
+
+/// Parses a 7-bit MIDI value, like a pitch or controller number
+fn parse_midi_value(value_raw: &str, name: &str) -> Result<u8> {
+    parse_in_range(value_raw, 0..=127, name, "a number from 0 to 127")
+}
+
+fn parse_time(time_raw: &str) -> Result<f64> {
+    parse_in_range(time_raw, AT_LEAST_ZERO, "Time", "a number of at least 0")
+}
+
+fn parse_a4_hz(a4_hz_raw: &str) -> Result<f64> {
+    parse_in_range(a4_hz_raw, POSITIVE, "A4 frequency", "a positive number of Hz")
+}
+
+fn parse_quantize(grid_raw: &str) -> Result<f64> {
+    parse_in_range(grid_raw, POSITIVE, "Quantize grid", "a positive number of beats")
+}
+
+fn parse_swing(ratio_raw: &str) -> Result<f64> {
+    parse_in_range(ratio_raw, 0.5..=1.0, "Swing ratio", "a number from 0.5 to 1")
+}
+
+fn parse_chord_window(window_raw: &str) -> Result<f64> {
+    parse_in_range(window_raw, POSITIVE, "Chord window", "a positive number")
+}
+
+fn parse_visualize_columns(columns_raw: &str) -> Result<usize> {
+    parse_in_range(columns_raw, 1.., "Piano roll columns", "a positive whole number")
 }
 
-fn get_time_seconds(ticks: u32, bpm: f32) -> f64 {
-    // TODO:
-    // This magic number equals 96 / 60,
-    // and 96 is the metrical unit in the file header,
-    // so maybe we should calculate it that way, with 60 (60 bpm) as a constant.
-    let ticks_per_sec = (bpm as f64) * 1.6;
+fn parse_legato_gap(gap_raw: &str) -> Result<f64> {
+    parse_in_range(gap_raw, AT_LEAST_ZERO, "Legato gap", "a number of at least 0")
+}
+
+fn parse_legato_threshold(threshold_raw: &str) -> Result<f64> {
+    parse_in_range(threshold_raw, AT_LEAST_ZERO, "Legato threshold", "a number of at least 0")
+}
+
+fn parse_max_notes(count_raw: &str) -> Result<usize> {
+    parse_in_range(count_raw, 1.., "Maximum number of notes", "a positive whole number")
+}
+
+fn parse_progress_interval(interval_raw: &str) -> Result<usize> {
+    parse_in_range(interval_raw, 1.., "Running stats interval", "a positive whole number of notes")
+}
 
-    (ticks as f64) / ticks_per_sec
+fn parse_beat_resolution(subdivision_raw: &str) -> Result<u32> {
+    parse_in_range(subdivision_raw, 1.., "Beat resolution", "a positive whole number of beats per whole note")
+}
+
+fn parse_precision(precision_raw: &str) -> Result<u32> {
+    // An f64 only has about 15 significant digits anyway
+    parse_in_range(precision_raw, 0..=15, "Precision", "a whole number of decimal places from 0 to 15")
+}
+
+fn parse_min_duration(duration_raw: &str) -> Result<f64> {
+    parse_in_range(duration_raw, POSITIVE, "Minimum duration", "a positive number")
+}
+
+fn parse_max_duration(duration_raw: &str) -> Result<f64> {
+    parse_in_range(duration_raw, POSITIVE, "Maximum duration", "a positive number")
+}
+
+fn parse_key_filter(key_raw: &str) -> Result<u16> {
+    key_pitch_classes(key_raw).ok_or_else(|| invalid_key(key_raw))
+}
+
+fn parse_key(key_raw: &str) -> Result<PitchSpeller> {
+    PitchSpeller::for_key(key_raw).ok_or_else(|| invalid_key(key_raw))
+}
+
+fn invalid_key(key_raw: &str) -> Midi2JsonError {
+    Midi2JsonError::InvalidArgument(
+        format!("Key must be a tonic and a mode, like \"C major\" or \"Bb dorian\", got {}", key_raw)
+    )
+}
+
+/// Parses a number of milliseconds, as seconds
+fn parse_humanize(max_ms_raw: &str) -> Result<f64> {
+    parse_in_range(max_ms_raw, POSITIVE, "Humanize amount", "a positive number of milliseconds")
+        .map(|max_ms| max_ms / 1000.0)
+}
+
+fn parse_seed(seed_raw: &str) -> Result<u64> {
+    parse_in_range(seed_raw, .., "Seed", &format!("a whole number from 0 to {}", u64::MAX))
+}
+
+fn parse_density_window(window_raw: &str) -> Result<f64> {
+    parse_in_range(window_raw, POSITIVE, "Note density window", "a positive number of seconds")
+}
+
+fn parse_pitch_bend_range(range_raw: &str) -> Result<f64> {
+    parse_in_range(range_raw, POSITIVE, "Pitch bend range", "a positive number of semitones")
+}
+
+/// Parses an optional pair of MIDI values, making sure the minimum isn't above the maximum
+fn parse_range(matches: &ArgMatches, min_arg: &str, max_arg: &str, name: &str) -> Result<(Option<u8>, Option<u8>)> {
+    let min = matches.value_of(min_arg)
+        .map(|value_raw| parse_midi_value(value_raw, name))
+        .transpose()?;
+    let max = matches.value_of(max_arg)
+        .map(|value_raw| parse_midi_value(value_raw, name))
+        .transpose()?;
+
+    match (min, max) {
+        (Some(min), Some(max)) if min > max => Err(Midi2JsonError::InvalidArgument(
+            format!("--{} {} is above --{} {}", min_arg, min, max_arg, max)
+        )),
+        _ => Ok((min, max))
+    }
+}
+
+fn process(input_filename: &str, output_filename: &str, settings: &Settings, log: &mut Logger) -> Result<()> {
+    writeln!(log.info(), "Loading MIDI file...")?;
+    let bytes = read_midi(input_filename, settings, log)?;
+    if let Some(event_log_filename) = &settings.event_log {
+        let mut writer = open_output(event_log_filename, false)?;
+        write_event_log(&mut writer, &read_event_log(&bytes)?)?;
+        writer.finish()?;
+    }
+    if settings.check {
+        return check_notes(input_filename, &bytes, settings, log);
+    }
+
+    let output_filename = if settings.gzip {
+        gzip_output_filename(output_filename)
+    } else {
+        output_filename.to_string()
+    };
+
+    if settings.output_format == OutputFormat::Jsonl {
+        return stream_output(&bytes, &output_filename, settings, log);
+    }
+
+    let note_info = process_bytes_with_log(&bytes, &settings.options, log.info())?;
+    let measure_starts = if settings.visualize_columns.is_some() {
+        get_measure_starts(&bytes, &settings.options)?
+    } else {
+        Vec::new()
+    };
+
+    write_note_info(&note_info, &measure_starts, &output_filename, settings, log)
+}
+
+/// Converts the notes without writing them anywhere, to see whether the file
+/// can be converted. With `check_report`, the warnings are written to stderr,
+/// and notes with no NoteOff are counted among them instead of failing.
+fn check_notes(input_filename: &str, bytes: &[u8], settings: &Settings, log: &mut Logger) -> Result<()> {
+    if !settings.check_report {
+        process_bytes_with_log(bytes, &settings.options, log.info())?;
+        writeln!(log.info(), "{} can be converted", input_filename)?;
+        return Ok(());
+    }
+
+    let mut options = settings.options.clone();
+    let count_missing_note_offs = options.note_options.truncated_notes == TruncatedNotes::Error;
+    if count_missing_note_offs {
+        options.note_options.truncated_notes = TruncatedNotes::Include;
+    }
+    // The warnings are collected from the log to be written together
+    let mut check_log = Vec::new();
+    let note_info = process_bytes_with_log(bytes, &options, &mut check_log)?;
+    let mut warnings: Vec<String> = String::from_utf8_lossy(&check_log)
+        .lines()
+        .filter_map(|line| line.strip_prefix("Warning: "))
+        .map(str::to_string)
+        .collect();
+    let missing_note_offs = note_info.all_notes().filter(|note| note.truncated).count();
+    if count_missing_note_offs && missing_note_offs > 0 {
+        let notes = if missing_note_offs == 1 { "note has" } else { "notes have" };
+        warnings.push(format!("{} {} no matching NoteOff", missing_note_offs, notes));
+    }
+
+    eprintln!("{}: {} notes", input_filename, note_info.all_notes().count());
+    for warning in &warnings {
+        eprintln!("  Warning: {}", warning);
+    }
+    if warnings.is_empty() {
+        eprintln!("  No warnings");
+    }
+
+    Ok(())
+}
+
+/// Converts several input files, merging their notes into one output
+fn process_merged(input_filenames: &[String], output_filename: &str, settings: &Settings, log: &mut Logger) -> Result<()> {
+    writeln!(log.info(), "Loading {} MIDI files...", input_filenames.len())?;
+    let files = input_filenames.iter()
+        .map(|input_filename| read_midi(input_filename, settings, log))
+        .collect::<Result<Vec<Vec<u8>>>>()?;
+    let files: Vec<(&str, &[u8])> = input_filenames.iter()
+        .map(String::as_str)
+        .zip(files.iter().map(Vec::as_slice))
+        .collect();
+
+    let output_filename = if settings.gzip {
+        gzip_output_filename(output_filename)
+    } else {
+        output_filename.to_string()
+    };
+
+    // The files' measures needn't line up, so none are drawn
+    let note_info = process_files_with_log(&files, &settings.options, log.info())?;
+    write_note_info(&note_info, &[], &output_filename, settings, log)
+}
+
+/// Reads an input MIDI file, decompressing it if needed
+fn read_midi(input_filename: &str, settings: &Settings, log: &mut Logger) -> Result<Vec<u8>> {
+    let mut bytes = read_input(input_filename)?;
+    if settings.compressed.unwrap_or_else(|| gzip::is_gzip(&bytes)) {
+        bytes = gzip::decompress(&bytes).map_err(|error| {
+            Midi2JsonError::IoError(io::Error::new(error.kind(), format!("Could not decompress {}: {}", input_filename, error)))
+        })?;
+    }
+    for chunk in get_chunks(&bytes) {
+        writeln!(log.trace(), "{} chunk at byte {}, {} bytes long", chunk.kind, chunk.offset, chunk.length)?;
+    }
+
+    Ok(bytes)
+}
+
+/// Draws and writes out the converted notes, as the settings ask
+fn write_note_info(
+    note_info: &NoteInfo,
+    measure_starts: &[f64],
+    output_filename: &str,
+    settings: &Settings,
+    log: &mut Logger
+) -> Result<()> {
+    writeln!(log.debug(), "Notes:")?;
+    for note in note_info.all_notes() {
+        writeln!(log.debug(), "  {} to {}: pitch {}", note.time_start, note.time_end, note.pitch_value)?;
+    }
+
+    if let Some(columns) = settings.visualize_columns {
+        let offset = note_info.offset_seconds.map_or(0.0, |offset| offset.as_f64());
+        let measure_starts: Vec<f64> = measure_starts.iter()
+            .map(|measure_start| measure_start - offset)
+            .collect();
+        let piano_roll = render_piano_roll(note_info.all_notes(), columns, &measure_starts);
+        if settings.visualize_only {
+            io::stdout().lock().write_all(piano_roll.as_bytes())?;
+            return Ok(());
+        }
+        io::stderr().lock().write_all(piano_roll.as_bytes())?;
+    }
+
+    let split_files: Vec<(String, NoteInfo)> = match settings.split {
+        Some(Split::Channel) => note_info.split_channels(settings.merge_into).into_iter()
+            .map(|(channel, channel_info)| (format!("ch{}", channel), channel_info))
+            .collect(),
+        Some(Split::Track) => {
+            let track_infos = note_info.split_tracks(settings.include_empty_tracks, settings.merge_into);
+            let tracks: Vec<&TrackNotes> = track_infos.iter()
+                .flat_map(|track_info| track_info.tracks.iter().flatten())
+                .collect();
+            track_file_suffixes(&tracks).into_iter().zip(track_infos).collect()
+        },
+        None => Vec::new()
+    };
+    for (suffix, split_info) in &split_files {
+        let split_filename = split_output_filename(output_filename, suffix);
+        writeln!(log.info(), "Saving {}...", split_filename)?;
+        create_output(split_info, &split_filename, settings)?;
+    }
+
+    if !settings.merge_into {
+        writeln!(log.info(), "Saving output file...")?;
+        create_output(note_info, output_filename, settings)?;
+    }
+
+    writeln!(log.info(), "Done.")?;
+
+    Ok(())
+}
+
+/// Reads the whole input file, or stdin for "-"
+fn read_input(input_filename: &str) -> Result<Vec<u8>> {
+    let read_result = if input_filename == "-" {
+        let mut bytes = Vec::new();
+        io::stdin().lock().read_to_end(&mut bytes).map(|_| bytes)
+    } else {
+        std::fs::read(input_filename)
+    };
+
+    read_result.map_err(|error| {
+        let name = if input_filename == "-" { "stdin" } else { input_filename };
+        Midi2JsonError::IoError(io::Error::new(error.kind(), format!("Could not read {}: {}", name, error)))
+    })
+}
+
+/// Writes each note as a line of JSON as soon as it ends, without collecting them first
+fn stream_output(bytes: &[u8], output_filename: &str, settings: &Settings, log: &mut Logger) -> Result<()> {
+    let mut writer = open_output(output_filename, settings.gzip)?;
+    stream_bytes(bytes, &settings.options, log.info(), |note| {
+        write_jsonl_note(&mut writer, &note, settings.precision)?;
+        Ok(())
+    })?;
+    writer.finish()?;
+
+    writeln!(log.info(), "Done.")?;
+
+    Ok(())
+}
+
+fn create_output(note_info: &NoteInfo, output_filename: &str, settings: &Settings) -> Result<()> {
+    let mut writer = open_output(output_filename, settings.gzip)?;
+    write_output(&mut writer, note_info, &settings.output_format, settings.precision)?;
+    writer.finish()?;
+
+    Ok(())
+}
+
+/// Where the output goes, either as-is or through a gzip encoder
+enum OutputStream {
+    Plain(Box<dyn Write>),
+    Gzip(GzEncoder<Box<dyn Write>>)
+}
+
+/// The output being written, to a temporary file unless it's stdout, so that
+/// anything reading the output file never sees it half-written
+struct OutputWriter {
+    stream: OutputStream,
+    /// The temporary file and the output file it's renamed to when it's done
+    rename: Option<(PathBuf, PathBuf)>
+}
+
+impl OutputWriter {
+    /// Flushes everything out and moves the file into place. A gzip stream
+    /// isn't complete until this writes its trailer.
+    fn finish(self) -> io::Result<()> {
+        match self.stream {
+            OutputStream::Plain(mut writer) => writer.flush()?,
+            OutputStream::Gzip(encoder) => encoder.finish()?.flush()?
+        }
+        if let Some((temp_path, output_path)) = self.rename {
+            fs::rename(temp_path, output_path)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Write for OutputWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.stream {
+            OutputStream::Plain(writer) => writer.write(buf),
+            OutputStream::Gzip(encoder) => encoder.write(buf)
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.stream {
+            OutputStream::Plain(writer) => writer.flush(),
+            OutputStream::Gzip(encoder) => encoder.flush()
+        }
+    }
+}
+
+/// Opens the output file, or stdout for "-"
+fn open_output(output_filename: &str, gzip: bool) -> Result<OutputWriter> {
+    let (writer, rename): (Box<dyn Write>, _) = if output_filename == "-" {
+        (Box::new(io::stdout().lock()), None)
+    } else {
+        let temp_filename = format!("{}.tmp", output_filename);
+        let writer = BufWriter::new(File::create(&temp_filename)?);
+        (Box::new(writer), Some((PathBuf::from(temp_filename), PathBuf::from(output_filename))))
+    };
+
+    let stream = if gzip {
+        OutputStream::Gzip(GzEncoder::new(writer, Compression::default()))
+    } else {
+        OutputStream::Plain(writer)
+    };
+    Ok(OutputWriter { stream, rename })
+}
+
+/// Where to write one of the split files: the output filename with `_` and
+/// the suffix added to its name, before the extension
+fn split_output_filename(output_filename: &str, suffix: &str) -> String {
+    // notes.json.gz becomes notes_ch0.json.gz, not notes.json_ch0.gz
+    let (output_filename, gzip_extension) = match output_filename.strip_suffix(".gz") {
+        Some(output_filename) => (output_filename, ".gz"),
+        None => (output_filename, "")
+    };
+    let output_path = Path::new(output_filename);
+    let stem = output_path.file_stem().unwrap_or_default().to_string_lossy();
+    let file_name = match output_path.extension() {
+        Some(extension) => format!("{}_{}.{}{}", stem, suffix, extension.to_string_lossy(), gzip_extension),
+        None => format!("{}_{}{}", stem, suffix, gzip_extension)
+    };
+
+    output_path.with_file_name(file_name).to_string_lossy().into_owned()
+}
+
+/// What to add to the output filename for each track with --split-by-track:
+/// the track's name, made safe for a filename, or `track` and its index if it
+/// has no name or another track has the same one
+fn track_file_suffixes(tracks: &[&TrackNotes]) -> Vec<String> {
+    let names: Vec<Option<String>> = tracks.iter()
+        .map(|track| track.name.as_deref().map(sanitize_file_name).filter(|name| !name.is_empty()))
+        .collect();
+
+    tracks.iter().zip(&names)
+        .map(|(track, name)| match name {
+            Some(name) if names.iter().filter(|other| other.as_ref() == Some(name)).count() == 1 => name.clone(),
+            _ => format!("track{}", track.index)
+        })
+        .collect()
+}
+
+/// Replaces each run of characters other than ASCII letters, digits, `-`,
+/// and `_` with a single `_`, trimming any from the ends
+fn sanitize_file_name(name: &str) -> String {
+    let mut sanitized = String::new();
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() || c == '-' {
+            sanitized.push(c);
+        } else if !sanitized.ends_with('_') {
+            sanitized.push('_');
+        }
+    }
+
+    sanitized.trim_matches('_').to_string()
+}
+
+/// Adds .gz to an output filename, unless it's stdout or already has it
+fn gzip_output_filename(output_filename: &str) -> String {
+    if output_filename == "-" || output_filename.ends_with(".gz") {
+        output_filename.to_string()
+    } else {
+        format!("{}.gz", output_filename)
+    }
+}
+
+fn run_json2midi(matches: &ArgMatches) -> Result<()> {
+    let input_filename = matches.value_of("input").unwrap();
+    let output_filename = matches.value_of("output").unwrap_or(DEFAULT_MIDI_OUTPUT_FILENAME);
+    check_output_path(output_filename)?;
+
+    let options = Json2MidiOptions {
+        time_unit: if matches.is_present("input-ticks") {
+            TimeUnit::Ticks
+        } else if matches.is_present("input-beats") {
+            TimeUnit::Beats(matches.value_of("beat-resolution").map(parse_beat_resolution).transpose()?.unwrap_or(QUARTER_NOTE))
+        } else {
+            TimeUnit::Seconds
+        },
+        bpm: matches.value_of("bpm").map(parse_bpm).transpose()?,
+        ticks_per_beat: matches.value_of("ticks-per-beat").map(parse_ticks_per_beat).transpose()?
+    };
+
+    let json = String::from_utf8(read_input(input_filename)?)
+        .map_err(|error| Midi2JsonError::JsonError(error.to_string()))?;
+    let bytes = json_to_midi(&json, &options)?;
+    let mut writer = open_output(output_filename, false)?;
+    writer.write_all(&bytes)?;
+    writer.finish()?;
+
+    Ok(())
+}
+
+/// Makes sure the output file can be created before doing any work
+fn check_output_path(output_filename: &str) -> Result<()> {
+    if output_filename == "-" {
+        return Ok(());
+    }
+
+    match Path::new(output_filename).parent() {
+        Some(parent) if !parent.as_os_str().is_empty() && !parent.is_dir() => {
+            Err(Midi2JsonError::IoError(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Output directory does not exist: {}", parent.display())
+            )))
+        },
+        _ => Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -139,16 +1838,339 @@ mod test {
     use super::*;
 
     #[test]
-    fn test_get_time_seconds_120() {
-        assert_eq!(get_time_seconds(0, 120.0), 0.0);
-        assert_eq!(get_time_seconds(48, 120.0), 0.25);
-        assert_eq!(get_time_seconds(192, 120.0), 1.0);
+    fn test_check_output_path() {
+        assert!(check_output_path("-").is_ok());
+        assert!(check_output_path("notes.json").is_ok());
+        assert!(check_output_path("src/notes.json").is_ok());
+        assert_eq!(
+            check_output_path("missing-dir/notes.json").unwrap_err().to_string(),
+            "Output directory does not exist: missing-dir"
+        );
+    }
+
+    #[test]
+    fn test_glob_base() {
+        assert_eq!(glob_base("music/**/*.mid"), PathBuf::from("music"));
+        assert_eq!(glob_base("music/rock/song?.mid"), PathBuf::from("music/rock"));
+        assert_eq!(glob_base("*.mid"), PathBuf::new());
+        assert_eq!(glob_base("music/song.mid"), PathBuf::from("music"));
+    }
+
+    #[test]
+    fn test_is_midi_file() {
+        assert!(is_midi_file(Path::new("music/song.mid")));
+        assert!(is_midi_file(Path::new("music/song.MIDI")));
+        assert!(is_midi_file(Path::new("music/song.mid.gz")));
+        assert!(!is_midi_file(Path::new("music/notes.txt")));
+        assert!(!is_midi_file(Path::new("music/notes.gz")));
+        assert!(!is_midi_file(Path::new("music/mid")));
+    }
+
+    #[test]
+    fn test_batch_output_path() {
+        assert_eq!(
+            batch_output_path("music/song.mid", None, &OutputFormat::Json),
+            PathBuf::from("music/song.json")
+        );
+        assert_eq!(
+            batch_output_path("music/song.midi", Some(Path::new("out")), &OutputFormat::Yaml),
+            PathBuf::from("out/song.yaml")
+        );
+        assert_eq!(
+            batch_output_path("music/song.mid.gz", None, &OutputFormat::Json),
+            PathBuf::from("music/song.json")
+        );
+        assert_eq!(
+            batch_output_path("music/song.mid", Some(Path::new(".")), &OutputFormat::Json),
+            PathBuf::from("music/song.json")
+        );
+        assert_eq!(
+            batch_output_path("notes.json", Some(Path::new(".")), &OutputFormat::Json),
+            PathBuf::from("notes.json")
+        );
+        assert_eq!(
+            batch_output_path("/home/me/music/song.mid", Some(Path::new("/tmp/out")), &OutputFormat::Json),
+            PathBuf::from("/tmp/out/song.json")
+        );
+    }
+
+    #[test]
+    fn test_find_config_arg() {
+        let args = |args: &[&str]| args.iter().map(OsString::from).collect::<Vec<OsString>>();
+        assert_eq!(find_config_arg(&args(&["midi2json", "--config", "a.toml", "-i", "song.mid"])), Some("a.toml".to_string()));
+        assert_eq!(find_config_arg(&args(&["midi2json", "--config=b.toml"])), Some("b.toml".to_string()));
+        assert_eq!(find_config_arg(&args(&["midi2json", "-i", "song.mid"])), None);
+    }
+
+    #[test]
+    fn test_parse_config() {
+        let config = r#"
+            bpm = 120.0
+            format = "csv"
+            transpose = -12
+            include_stats = true
+            pitch-names = true
+            compact = false
+            controller = [1, 64]
+        "#;
+        assert_eq!(parse_config(config).unwrap(), vec![
+            "--bpm=120",
+            "--controller=1",
+            "--controller=64",
+            "--format=csv",
+            "--include-stats",
+            "--pitch-names",
+            "--transpose=-12"
+        ]);
+
+        assert!(parse_config("bpm = ").is_err());
+        assert!(parse_config("config = \"other.toml\"").is_err());
+        assert!(parse_config("[section]\nbpm = 120").is_err());
+    }
+
+    #[test]
+    fn test_split_output_filename() {
+        assert_eq!(split_output_filename("output/song.json", "ch9"), "output/song_ch9.json");
+        assert_eq!(split_output_filename("output/song.json.gz", "ch0"), "output/song_ch0.json.gz");
+        assert_eq!(split_output_filename("song", "Piano"), "song_Piano");
+    }
+
+    #[test]
+    fn test_track_file_suffixes() {
+        let track = |index, name: Option<&str>| TrackNotes { index, name: name.map(String::from), notes: vec![] };
+        let tracks = [
+            track(0, Some("Piano (Left Hand)")),
+            track(1, None),
+            track(2, Some("Strings")),
+            track(3, Some("Strings")),
+            track(4, Some("???"))
+        ];
+        assert_eq!(
+            track_file_suffixes(&tracks.iter().collect::<Vec<_>>()),
+            ["Piano_Left_Hand", "track1", "track2", "track3", "track4"]
+        );
+    }
+
+    #[test]
+    fn test_sanitize_file_name() {
+        assert_eq!(sanitize_file_name("Lead Vox"), "Lead_Vox");
+        assert_eq!(sanitize_file_name("  drums/perc: 2 "), "drums_perc_2");
+        assert_eq!(sanitize_file_name("Bass-1_low"), "Bass-1_low");
+        assert_eq!(sanitize_file_name("Violín"), "Viol_n");
+    }
+
+    #[test]
+    fn test_gzip_output_filename() {
+        assert_eq!(gzip_output_filename("output/notes.json"), "output/notes.json.gz");
+        assert_eq!(gzip_output_filename("notes.json.gz"), "notes.json.gz");
+        assert_eq!(gzip_output_filename("-"), "-");
+    }
+
+    #[test]
+    fn test_parse_ticks_per_beat() {
+        assert_eq!(parse_ticks_per_beat("480").unwrap(), 480);
+        assert!(parse_ticks_per_beat("0").is_err());
+        assert!(parse_ticks_per_beat("32768").is_err());
+        assert!(parse_ticks_per_beat("fine").is_err());
+    }
+
+    #[test]
+    fn test_parse_bpm() {
+        assert_eq!(parse_bpm("120").unwrap(), 120.0);
+        assert_eq!(parse_bpm("92.5").unwrap(), 92.5);
+        assert!(matches!(parse_bpm("fast"), Err(Midi2JsonError::InvalidBpm(_))));
+        assert!(matches!(parse_bpm("0"), Err(Midi2JsonError::InvalidBpm(_))));
+        assert!(matches!(parse_bpm("-60"), Err(Midi2JsonError::InvalidBpm(_))));
+    }
+
+    #[test]
+    fn test_parse_channel() {
+        assert_eq!(parse_channel("0").unwrap(), 0);
+        assert_eq!(parse_channel("15").unwrap(), 15);
+        assert!(parse_channel("16").is_err());
+        assert!(parse_channel("drums").is_err());
+    }
+
+    #[test]
+    fn test_parse_transpose() {
+        assert_eq!(parse_transpose("-12").unwrap(), -12);
+        assert_eq!(parse_transpose("+7").unwrap(), 7);
+        assert!(parse_transpose("128").is_err());
+        assert!(parse_transpose("up").is_err());
+    }
+
+    #[test]
+    fn test_parse_midi_value() {
+        assert_eq!(parse_midi_value("64", "Controller").unwrap(), 64);
+        assert_eq!(parse_midi_value("127", "Pitch").unwrap(), 127);
+        assert!(parse_midi_value("sustain", "Controller").is_err());
+        assert_eq!(
+            parse_midi_value("128", "Pitch").unwrap_err().to_string(),
+            "Pitch must be a number from 0 to 127, got 128"
+        );
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(UNIX_EPOCH), "00:00:00");
+        assert_eq!(format_timestamp(UNIX_EPOCH + Duration::from_secs(3 * 86_400 + 13 * 3600 + 5 * 60 + 9)), "13:05:09");
+    }
+
+    #[test]
+    fn test_parse_time() {
+        assert_eq!(parse_time("0").unwrap(), 0.0);
+        assert_eq!(parse_time("32.5").unwrap(), 32.5);
+        assert!(parse_time("-1").is_err());
+        assert!(parse_time("soon").is_err());
+    }
+
+    #[test]
+    fn test_parse_a4_hz() {
+        assert_eq!(parse_a4_hz("440").unwrap(), 440.0);
+        assert_eq!(parse_a4_hz("415.3").unwrap(), 415.3);
+        assert!(parse_a4_hz("0").is_err());
+        assert!(parse_a4_hz("concert").is_err());
+    }
+
+    #[test]
+    fn test_parse_quantize() {
+        assert_eq!(parse_quantize("0.25").unwrap(), 0.25);
+        assert_eq!(parse_quantize("1").unwrap(), 1.0);
+        assert!(parse_quantize("0").is_err());
+        assert!(parse_quantize("-0.5").is_err());
+        assert!(parse_quantize("sixteenth").is_err());
+    }
+
+    #[test]
+    fn test_parse_swing() {
+        assert_eq!(parse_swing("0.5").unwrap(), 0.5);
+        assert_eq!(parse_swing("0.67").unwrap(), 0.67);
+        assert_eq!(parse_swing("1").unwrap(), 1.0);
+        assert!(parse_swing("0.4").is_err());
+        assert!(parse_swing("1.5").is_err());
+        assert!(parse_swing("triplet").is_err());
+    }
+
+    #[test]
+    fn test_parse_chord_window() {
+        assert_eq!(parse_chord_window("0.05").unwrap(), 0.05);
+        assert_eq!(parse_chord_window("10").unwrap(), 10.0);
+        assert!(parse_chord_window("0").is_err());
+        assert!(parse_chord_window("short").is_err());
+    }
+
+    #[test]
+    fn test_parse_visualize_columns() {
+        assert_eq!(parse_visualize_columns("80").unwrap(), 80);
+        assert!(parse_visualize_columns("0").is_err());
+        assert!(parse_visualize_columns("wide").is_err());
+    }
+
+    #[test]
+    fn test_parse_legato_gap() {
+        assert_eq!(parse_legato_gap("0").unwrap(), 0.0);
+        assert_eq!(parse_legato_gap("0.02").unwrap(), 0.02);
+        assert!(parse_legato_gap("-0.1").is_err());
+        assert!(parse_legato_gap("none").is_err());
+    }
+
+    #[test]
+    fn test_parse_legato_threshold() {
+        assert_eq!(parse_legato_threshold("0.05").unwrap(), 0.05);
+        assert_eq!(parse_legato_threshold("24").unwrap(), 24.0);
+        assert!(parse_legato_threshold("-0.05").is_err());
+        assert!(parse_legato_threshold("short").is_err());
+    }
+
+    #[test]
+    fn test_parse_max_notes() {
+        assert_eq!(parse_max_notes("100").unwrap(), 100);
+        assert!(parse_max_notes("0").is_err());
+        assert!(parse_max_notes("-3").is_err());
+    }
+
+    #[test]
+    fn test_parse_in_range() {
+        assert_eq!(parse_in_range::<f64>("0.5", POSITIVE, "Window", "a positive number").unwrap(), 0.5);
+        for value_raw in ["0", "inf", "NaN", "-1"] {
+            assert!(parse_in_range::<f64>(value_raw, POSITIVE, "Window", "a positive number").is_err(), "{}", value_raw);
+        }
+        assert_eq!(parse_in_range::<f64>("0", AT_LEAST_ZERO, "Gap", "a number of at least 0").unwrap(), 0.0);
+        assert!(parse_in_range::<f64>("inf", AT_LEAST_ZERO, "Gap", "a number of at least 0").is_err());
+        match parse_in_range::<u8>("16", 0..=15, "Channel", "a number from 0 to 15") {
+            Err(Midi2JsonError::InvalidArgument(message)) => assert_eq!(message, "Channel must be a number from 0 to 15, got 16"),
+            other => panic!("Expected an invalid argument, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn test_parse_progress_interval() {
+        assert_eq!(parse_progress_interval("1000").unwrap(), 1000);
+        assert!(parse_progress_interval("0").is_err());
+        assert!(parse_progress_interval("ten").is_err());
+    }
+
+    #[test]
+    fn test_parse_beat_resolution() {
+        assert_eq!(parse_beat_resolution("8").unwrap(), 8);
+        assert_eq!(parse_beat_resolution("3").unwrap(), 3);
+        assert!(parse_beat_resolution("0").is_err());
+        assert!(parse_beat_resolution("1.5").is_err());
+    }
+
+    #[test]
+    fn test_parse_precision() {
+        assert_eq!(parse_precision("0").unwrap(), 0);
+        assert_eq!(parse_precision("4").unwrap(), 4);
+        assert!(parse_precision("16").is_err());
+        assert!(parse_precision("-1").is_err());
+        assert!(parse_precision("2.5").is_err());
+    }
+
+    #[test]
+    fn test_parse_min_duration() {
+        assert_eq!(parse_min_duration("0.01").unwrap(), 0.01);
+        assert!(parse_min_duration("0").is_err());
+        assert!(parse_min_duration("-1").is_err());
+    }
+
+    #[test]
+    fn test_parse_key_filter() {
+        assert_eq!(parse_key_filter("C major").unwrap(), 0b1010_1011_0101);
+        assert!(parse_key_filter("C").is_err());
+        assert_eq!(parse_key("F major").unwrap().pitch_class_name(10), "Bb");
+        assert!(parse_key("F").is_err());
+    }
+
+    #[test]
+    fn test_parse_density_window() {
+        assert_eq!(parse_density_window("0.5").unwrap(), 0.5);
+        assert!(parse_density_window("0").is_err());
+        assert!(parse_density_window("inf").is_err());
+    }
+
+    #[test]
+    fn test_parse_humanize() {
+        assert_eq!(parse_humanize("20").unwrap(), 0.02);
+        assert!(parse_humanize("0").is_err());
+        assert!(parse_humanize("-5").is_err());
+        assert_eq!(parse_seed("42").unwrap(), 42);
+        assert!(parse_seed("-1").is_err());
+    }
+
+    #[test]
+    fn test_parse_max_duration() {
+        assert_eq!(parse_max_duration("0.25").unwrap(), 0.25);
+        assert_eq!(parse_max_duration("96").unwrap(), 96.0);
+        assert!(parse_max_duration("0").is_err());
+        assert!(parse_max_duration("long").is_err());
     }
 
     #[test]
-    fn test_get_time_seconds_60() {
-        assert_eq!(get_time_seconds(0, 60.0), 0.0);
-        assert_eq!(get_time_seconds(48, 60.0), 0.5);
-        assert_eq!(get_time_seconds(96, 60.0), 1.0);
+    fn test_parse_pitch_bend_range() {
+        assert_eq!(parse_pitch_bend_range("2").unwrap(), 2.0);
+        assert_eq!(parse_pitch_bend_range("0.5").unwrap(), 0.5);
+        assert!(parse_pitch_bend_range("0").is_err());
+        assert!(parse_pitch_bend_range("wide").is_err());
     }
 }
@@ -0,0 +1,279 @@
+//! Time signatures, and where they put each tick in the bar
+
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use midly::{
+    Event,
+    EventKind,
+    MetaMessage
+};
+use crate::time::{TempoChange, get_time_seconds_tempo_map};
+
+/// A time signature change, as written to the output
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct TimeSignature {
+    pub tick: u32,
+    pub time_seconds: f64,
+    pub numerator: u8,
+    /// The actual note value of a beat (e.g. 4 for quarter notes)
+    pub denominator: u8,
+    /// MIDI clocks per metronome click
+    pub clocks_per_click: u8,
+    pub thirty_second_notes_per_quarter: u8
+}
+
+/// Collects the time signature changes from every track, in tick order.
+///
+/// MIDI stores the denominator as a power of two, so it is converted to the
+/// note value here (e.g. 2 becomes 4).
+///
+/// # Examples
+///
+/// ```
+/// use midly::{Smf, Event};
+/// use midi2json::{get_tempo_map, get_time_signatures};
+///
+/// let bytes = include_bytes!("../input/sample.mid");
+/// let smf: Smf<Vec<Event>> = Smf::read(bytes).unwrap();
+///
+/// let tempo_map = get_tempo_map(&smf.tracks, Some(120.0)).unwrap();
+/// for time_signature in get_time_signatures(&smf.tracks, &tempo_map, 96) {
+///     println!("{}/{}", time_signature.numerator, time_signature.denominator);
+/// }
+/// ```
+pub fn get_time_signatures<'a, T: AsRef<[Event<'a>]>>(
+    tracks: &[T],
+    tempo_map: &[TempoChange],
+    ticks_per_beat: u16
+) -> Vec<TimeSignature> {
+    let mut time_signatures = Vec::<TimeSignature>::new();
+    for track in tracks {
+        let mut cur_time: u32 = 0;
+        for event in track.as_ref() {
+            cur_time += event.delta.as_int();
+
+            if let EventKind::Meta(MetaMessage::TimeSignature(numerator, denominator_power, clocks_per_click, thirty_seconds)) = event.kind {
+                time_signatures.push(TimeSignature {
+                    tick: cur_time,
+                    time_seconds: get_time_seconds_tempo_map(cur_time, tempo_map, ticks_per_beat),
+                    numerator,
+                    denominator: 2u8.saturating_pow(denominator_power as u32),
+                    clocks_per_click,
+                    thirty_second_notes_per_quarter: thirty_seconds
+                });
+            }
+        }
+    }
+    time_signatures.sort_by_key(|time_signature| time_signature.tick);
+
+    time_signatures
+}
+
+/// A stretch of the piece in one meter
+#[derive(Clone, Debug, PartialEq)]
+struct MeterSegment {
+    tick: u32,
+    /// The number of whole measures before this segment
+    measures_before: u32,
+    beats_per_measure: u32,
+    ticks_per_beat: f64
+}
+
+impl MeterSegment {
+    fn new(tick: u32, measures_before: u32, numerator: u8, denominator: u8, ticks_per_quarter: u16) -> MeterSegment {
+        MeterSegment {
+            tick,
+            measures_before,
+            beats_per_measure: numerator.max(1) as u32,
+            // The beat is whatever note value the denominator names
+            ticks_per_beat: (ticks_per_quarter as f64) * 4.0 / (denominator.max(1) as f64)
+        }
+    }
+
+    fn ticks_per_measure(&self) -> f64 {
+        self.ticks_per_beat * (self.beats_per_measure as f64)
+    }
+}
+
+/// Finds the measure and beat of a tick position, from the time signature changes.
+///
+/// The piece is in 4/4 until the first time signature. A time signature that
+/// lands partway through a measure starts a new measure.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MeasureMap {
+    segments: Vec<MeterSegment>
+}
+
+impl MeasureMap {
+    /// Builds the map from time signatures sorted by tick, like `get_time_signatures` returns
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use midi2json::meter::MeasureMap;
+    ///
+    /// // 4/4 throughout, at 96 ticks per beat
+    /// let measure_map = MeasureMap::new(&[], 96);
+    /// assert_eq!(measure_map.get_position(0), (1, 1.0));
+    /// assert_eq!(measure_map.get_position(432), (2, 1.5));
+    /// ```
+    pub fn new(time_signatures: &[TimeSignature], ticks_per_beat: u16) -> MeasureMap {
+        let mut segments = vec![MeterSegment::new(0, 0, 4, 4, ticks_per_beat)];
+        for time_signature in time_signatures {
+            let previous = segments.last().unwrap();
+            let elapsed_ticks = (time_signature.tick - previous.tick) as f64;
+            let measures_before = previous.measures_before
+                + (elapsed_ticks / previous.ticks_per_measure()).ceil() as u32;
+            let segment = MeterSegment::new(
+                time_signature.tick,
+                measures_before,
+                time_signature.numerator,
+                time_signature.denominator,
+                ticks_per_beat
+            );
+
+            // A later time signature at the same tick replaces the earlier one
+            if previous.tick == time_signature.tick {
+                segments.pop();
+            }
+            segments.push(segment);
+        }
+
+        MeasureMap { segments }
+    }
+
+    /// The 1-indexed measure of a tick position, and the beat within it.
+    /// Beats are counted from 1.0 on the downbeat.
+    pub fn get_position(&self, ticks: u32) -> (u32, f64) {
+        let segment = self.segments.iter()
+            .rev()
+            .find(|segment| segment.tick <= ticks)
+            .unwrap();
+
+        let offset = (ticks - segment.tick) as f64;
+        let measure_index = (offset / segment.ticks_per_measure()).floor();
+        let beat = (offset - measure_index * segment.ticks_per_measure()) / segment.ticks_per_beat;
+
+        (segment.measures_before + measure_index as u32 + 1, beat + 1.0)
+    }
+
+    /// The tick a 1-indexed measure starts on
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use midi2json::meter::MeasureMap;
+    ///
+    /// let measure_map = MeasureMap::new(&[], 96);
+    /// assert_eq!(measure_map.get_measure_start(1), 0);
+    /// assert_eq!(measure_map.get_measure_start(3), 768);
+    /// ```
+    pub fn get_measure_start(&self, measure: u32) -> u32 {
+        let segment = self.segments.iter()
+            .rev()
+            .find(|segment| segment.measures_before < measure)
+            .unwrap_or(&self.segments[0]);
+
+        let measure_index = measure.saturating_sub(segment.measures_before + 1);
+        segment.tick + (measure_index as f64 * segment.ticks_per_measure()).round() as u32
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use midly::number::VarlenInt;
+
+    fn time_signature_event(delta: u32, numerator: u8, denominator_power: u8) -> Event<'static> {
+        Event {
+            delta: VarlenInt::from(delta),
+            kind: EventKind::Meta(MetaMessage::TimeSignature(numerator, denominator_power, 24, 8))
+        }
+    }
+
+    #[test]
+    fn test_get_time_signatures() {
+        let track = vec![
+            time_signature_event(0, 3, 2),
+            time_signature_event(288, 6, 3)
+        ];
+        let tempo_map = vec![TempoChange { tick: 0, micros_per_beat: 500_000 }];
+        assert_eq!(get_time_signatures(&[&track], &tempo_map, 96), vec![
+            TimeSignature {
+                tick: 0,
+                time_seconds: 0.0,
+                numerator: 3,
+                denominator: 4,
+                clocks_per_click: 24,
+                thirty_second_notes_per_quarter: 8
+            },
+            TimeSignature {
+                tick: 288,
+                time_seconds: 1.5,
+                numerator: 6,
+                denominator: 8,
+                clocks_per_click: 24,
+                thirty_second_notes_per_quarter: 8
+            }
+        ]);
+    }
+
+    fn time_signature(tick: u32, numerator: u8, denominator: u8) -> TimeSignature {
+        TimeSignature {
+            tick,
+            time_seconds: 0.0,
+            numerator,
+            denominator,
+            clocks_per_click: 24,
+            thirty_second_notes_per_quarter: 8
+        }
+    }
+
+    #[test]
+    fn test_measure_map_default_meter() {
+        let measure_map = MeasureMap::new(&[], 96);
+        assert_eq!(measure_map.get_position(0), (1, 1.0));
+        assert_eq!(measure_map.get_position(96), (1, 2.0));
+        assert_eq!(measure_map.get_position(384), (2, 1.0));
+        assert_eq!(measure_map.get_position(400), (2, 1.1666666666666667));
+    }
+
+    #[test]
+    fn test_measure_map_meter_change() {
+        // Two bars of 3/4, then 6/8
+        let measure_map = MeasureMap::new(&[
+            time_signature(0, 3, 4),
+            time_signature(576, 6, 8)
+        ], 96);
+        assert_eq!(measure_map.get_position(0), (1, 1.0));
+        assert_eq!(measure_map.get_position(288), (2, 1.0));
+        assert_eq!(measure_map.get_position(480), (2, 3.0));
+        assert_eq!(measure_map.get_position(576), (3, 1.0));
+        assert_eq!(measure_map.get_position(624), (3, 2.0));
+        assert_eq!(measure_map.get_position(864), (4, 1.0));
+    }
+
+    #[test]
+    fn test_measure_map_change_mid_measure() {
+        // A 2/4 bar cuts the first 4/4 bar short
+        let measure_map = MeasureMap::new(&[time_signature(192, 2, 4)], 96);
+        assert_eq!(measure_map.get_position(96), (1, 2.0));
+        assert_eq!(measure_map.get_position(192), (2, 1.0));
+        assert_eq!(measure_map.get_position(384), (3, 1.0));
+    }
+
+    #[test]
+    fn test_measure_map_get_measure_start() {
+        // Two bars of 3/4, then 6/8, then a 2/4 bar that cuts a 6/8 bar short
+        let measure_map = MeasureMap::new(&[
+            time_signature(0, 3, 4),
+            time_signature(576, 6, 8),
+            time_signature(1008, 2, 4)
+        ], 96);
+        let starts: Vec<u32> = (1..=6).map(|measure| measure_map.get_measure_start(measure)).collect();
+        assert_eq!(starts, vec![0, 288, 576, 864, 1008, 1200]);
+        for (measure, start) in (1..=6).zip(starts) {
+            assert_eq!(measure_map.get_position(start), (measure, 1.0));
+        }
+    }
+}
@@ -0,0 +1,406 @@
+//! Human-readable names for MIDI values
+
+/// Pitch class names, spelled with sharps
+pub const SHARP_NAMES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+
+/// Pitch class names, spelled with flats
+pub const FLAT_NAMES: [&str; 12] = ["C", "Db", "D", "Eb", "E", "F", "Gb", "G", "Ab", "A", "Bb", "B"];
+
+/// Names a MIDI pitch in scientific pitch notation, where 60 is middle C ("C4")
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::names::pitch_name;
+///
+/// assert_eq!(pitch_name(61, false), "C#4");
+/// assert_eq!(pitch_name(61, true), "Db4");
+/// ```
+pub fn pitch_name(pitch: u32, flats: bool) -> String {
+    let names = if flats { &FLAT_NAMES } else { &SHARP_NAMES };
+    let octave = (pitch / 12) as i32 - 1;
+    let semitone = (pitch % 12) as usize;
+
+    format!("{}{}", names[semitone], octave)
+}
+
+/// Names a pitch class (0-11), always spelled with sharps
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::names::pitch_class_name;
+///
+/// assert_eq!(pitch_class_name(1), "C#");
+/// ```
+pub fn pitch_class_name(pitch_class: u8) -> &'static str {
+    SHARP_NAMES[(pitch_class % 12) as usize]
+}
+
+/// General MIDI instrument names, indexed by program number
+pub const GM_PROGRAM_NAMES: [&str; 128] = [
+    "Acoustic Grand Piano", "Bright Acoustic Piano", "Electric Grand Piano", "Honky-tonk Piano",
+    "Electric Piano 1", "Electric Piano 2", "Harpsichord", "Clavinet",
+    "Celesta", "Glockenspiel", "Music Box", "Vibraphone",
+    "Marimba", "Xylophone", "Tubular Bells", "Dulcimer",
+    "Drawbar Organ", "Percussive Organ", "Rock Organ", "Church Organ",
+    "Reed Organ", "Accordion", "Harmonica", "Tango Accordion",
+    "Acoustic Guitar (nylon)", "Acoustic Guitar (steel)", "Electric Guitar (jazz)", "Electric Guitar (clean)",
+    "Electric Guitar (muted)", "Overdriven Guitar", "Distortion Guitar", "Guitar Harmonics",
+    "Acoustic Bass", "Electric Bass (finger)", "Electric Bass (pick)", "Fretless Bass",
+    "Slap Bass 1", "Slap Bass 2", "Synth Bass 1", "Synth Bass 2",
+    "Violin", "Viola", "Cello", "Contrabass",
+    "Tremolo Strings", "Pizzicato Strings", "Orchestral Harp", "Timpani",
+    "String Ensemble 1", "String Ensemble 2", "Synth Strings 1", "Synth Strings 2",
+    "Choir Aahs", "Voice Oohs", "Synth Voice", "Orchestra Hit",
+    "Trumpet", "Trombone", "Tuba", "Muted Trumpet",
+    "French Horn", "Brass Section", "Synth Brass 1", "Synth Brass 2",
+    "Soprano Sax", "Alto Sax", "Tenor Sax", "Baritone Sax",
+    "Oboe", "English Horn", "Bassoon", "Clarinet",
+    "Piccolo", "Flute", "Recorder", "Pan Flute",
+    "Blown Bottle", "Shakuhachi", "Whistle", "Ocarina",
+    "Lead 1 (square)", "Lead 2 (sawtooth)", "Lead 3 (calliope)", "Lead 4 (chiff)",
+    "Lead 5 (charang)", "Lead 6 (voice)", "Lead 7 (fifths)", "Lead 8 (bass + lead)",
+    "Pad 1 (new age)", "Pad 2 (warm)", "Pad 3 (polysynth)", "Pad 4 (choir)",
+    "Pad 5 (bowed)", "Pad 6 (metallic)", "Pad 7 (halo)", "Pad 8 (sweep)",
+    "FX 1 (rain)", "FX 2 (soundtrack)", "FX 3 (crystal)", "FX 4 (atmosphere)",
+    "FX 5 (brightness)", "FX 6 (goblins)", "FX 7 (echoes)", "FX 8 (sci-fi)",
+    "Sitar", "Banjo", "Shamisen", "Koto",
+    "Kalimba", "Bagpipe", "Fiddle", "Shanai",
+    "Tinkle Bell", "Agogo", "Steel Drums", "Woodblock",
+    "Taiko Drum", "Melodic Tom", "Synth Drum", "Reverse Cymbal",
+    "Guitar Fret Noise", "Breath Noise", "Seashore", "Bird Tweet",
+    "Telephone Ring", "Helicopter", "Applause", "Gunshot"
+];
+
+/// Names a program number with the General MIDI instrument it selects
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::names::program_name;
+///
+/// assert_eq!(program_name(0), "Acoustic Grand Piano");
+/// ```
+pub fn program_name(program: u8) -> &'static str {
+    GM_PROGRAM_NAMES[(program & 0x7f) as usize]
+}
+
+/// The channel General MIDI reserves for percussion: channel 10, counting from 1
+pub const DRUM_CHANNEL: u8 = 9;
+
+/// The lowest pitch in the General MIDI percussion map
+const FIRST_DRUM_PITCH: u8 = 35;
+
+/// General MIDI percussion sounds on the drum channel, from pitch 35 to 81
+const GM_DRUM_NAMES: [&str; 47] = [
+    "Acoustic Bass Drum", "Bass Drum 1", "Side Stick", "Acoustic Snare",
+    "Hand Clap", "Electric Snare", "Low Floor Tom", "Closed Hi-Hat",
+    "High Floor Tom", "Pedal Hi-Hat", "Low Tom", "Open Hi-Hat",
+    "Low-Mid Tom", "Hi-Mid Tom", "Crash Cymbal 1", "High Tom",
+    "Ride Cymbal 1", "Chinese Cymbal", "Ride Bell", "Tambourine",
+    "Splash Cymbal", "Cowbell", "Crash Cymbal 2", "Vibraslap",
+    "Ride Cymbal 2", "Hi Bongo", "Low Bongo", "Mute Hi Conga",
+    "Open Hi Conga", "Low Conga", "High Timbale", "Low Timbale",
+    "High Agogo", "Low Agogo", "Cabasa", "Maracas",
+    "Short Whistle", "Long Whistle", "Short Guiro", "Long Guiro",
+    "Claves", "Hi Wood Block", "Low Wood Block", "Mute Cuica",
+    "Open Cuica", "Mute Triangle", "Open Triangle"
+];
+
+/// Names a pitch on the drum channel with the General MIDI percussion sound it
+/// plays, or `None` if the pitch is outside the percussion map
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::names::drum_name;
+///
+/// assert_eq!(drum_name(38), Some("Acoustic Snare"));
+/// assert_eq!(drum_name(20), None);
+/// ```
+pub fn drum_name(pitch: u8) -> Option<&'static str> {
+    pitch.checked_sub(FIRST_DRUM_PITCH)
+        .and_then(|index| GM_DRUM_NAMES.get(index as usize))
+        .copied()
+}
+
+/// Major keys around the circle of fifths, from 7 flats to 7 sharps
+const MAJOR_KEYS: [&str; 15] = ["Cb", "Gb", "Db", "Ab", "Eb", "Bb", "F", "C", "G", "D", "A", "E", "B", "F#", "C#"];
+
+/// Minor keys around the circle of fifths, from 7 flats to 7 sharps
+const MINOR_KEYS: [&str; 15] = ["Ab", "Eb", "Bb", "F", "C", "G", "D", "A", "E", "B", "F#", "C#", "G#", "D#", "A#"];
+
+/// Names a key from its number of sharps (positive) or flats (negative), like a MIDI key signature.
+///
+/// Returns `None` for more than 7 sharps or flats.
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::names::key_name;
+///
+/// assert_eq!(key_name(2, true).unwrap(), "D major");
+/// assert_eq!(key_name(-3, false).unwrap(), "C minor");
+/// ```
+pub fn key_name(sharps_flats: i8, major: bool) -> Option<String> {
+    if !(-7..=7).contains(&sharps_flats) {
+        return None;
+    }
+
+    let index = (sharps_flats + 7) as usize;
+    Some(if major {
+        format!("{} major", MAJOR_KEYS[index])
+    } else {
+        format!("{} minor", MINOR_KEYS[index])
+    })
+}
+
+/// The semitones above the tonic of the notes in each mode's scale
+const MODES: [(&str, [u8; 7]); 9] = [
+    ("major", [0, 2, 4, 5, 7, 9, 11]),
+    ("minor", [0, 2, 3, 5, 7, 8, 10]),
+    ("ionian", [0, 2, 4, 5, 7, 9, 11]),
+    ("dorian", [0, 2, 3, 5, 7, 9, 10]),
+    ("phrygian", [0, 1, 3, 5, 7, 8, 10]),
+    ("lydian", [0, 2, 4, 6, 7, 9, 11]),
+    ("mixolydian", [0, 2, 4, 5, 7, 9, 10]),
+    ("aeolian", [0, 2, 3, 5, 7, 8, 10]),
+    ("locrian", [0, 1, 3, 5, 6, 8, 10])
+];
+
+/// Parses a key like "C major", "G# minor", or "Bb dorian" into a mask of the
+/// pitch classes in its scale, with bit 0 for C up to bit 11 for B.
+///
+/// The tonic is a letter with an optional `#` or `b`, and the mode is major,
+/// minor, or one of the seven church modes, in any case. Returns `None` for
+/// anything else.
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::names::key_pitch_classes;
+///
+/// // C, D, E, F, G, A, and B
+/// assert_eq!(key_pitch_classes("C major"), Some(0b1010_1011_0101));
+/// assert_eq!(key_pitch_classes("A minor"), key_pitch_classes("C major"));
+/// assert_eq!(key_pitch_classes("H major"), None);
+/// ```
+pub fn key_pitch_classes(key: &str) -> Option<u16> {
+    let (_, tonic, intervals) = parse_key(key)?;
+    Some(intervals.iter().fold(0, |mask, interval| mask | 1 << ((tonic + *interval as i32).rem_euclid(12))))
+}
+
+/// The letters of the note names, and the pitch class of each one
+const LETTERS: [(char, i32); 7] = [('C', 0), ('D', 2), ('E', 4), ('F', 5), ('G', 7), ('A', 9), ('B', 11)];
+
+/// Splits a key into the index of its tonic's letter in `LETTERS`, the
+/// tonic's pitch class (which can be -1 for Cb), and its mode's intervals
+fn parse_key(key: &str) -> Option<(usize, i32, &'static [u8; 7])> {
+    let (tonic_name, mode_name) = key.trim().split_once(char::is_whitespace)?;
+    let mut tonic_chars = tonic_name.chars();
+    let letter = tonic_chars.next()?.to_ascii_uppercase();
+    let letter_index = LETTERS.iter().position(|(name, _)| *name == letter)?;
+    let natural = LETTERS[letter_index].1;
+    let tonic = match tonic_chars.as_str() {
+        "" => natural,
+        "#" => natural + 1,
+        "b" => natural - 1,
+        _ => return None
+    };
+
+    let mode_name = mode_name.trim();
+    let (_, intervals) = MODES.iter().find(|(name, _)| name.eq_ignore_ascii_case(mode_name))?;
+    Some((letter_index, tonic, intervals))
+}
+
+/// Names pitches the way they're conventionally spelled in a key, like F# in
+/// G major and Bb in F major.
+///
+/// The seven notes of the key's scale each get their own letter, so keys with
+/// many flats or sharps spell some with double flats or sharps, like Bbb in
+/// Fb major. The other five pitch classes are spelled with flats in keys whose
+/// scale has flats, and with sharps otherwise.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PitchSpeller {
+    /// The name of each pitch class, from C up
+    names: [String; 12],
+    /// How many semitones each pitch class's name is above its letter's natural
+    alterations: [i32; 12]
+}
+
+impl PitchSpeller {
+    /// A speller for a key like "G major" or "Bb dorian", which are parsed like
+    /// `key_pitch_classes`. Returns `None` if the key can't be parsed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use midi2json::names::PitchSpeller;
+    ///
+    /// let speller = PitchSpeller::for_key("F major").unwrap();
+    /// assert_eq!(speller.pitch_name(70), "Bb4");
+    /// assert_eq!(PitchSpeller::for_key("G major").unwrap().pitch_class_name(6), "F#");
+    /// ```
+    pub fn for_key(key: &str) -> Option<PitchSpeller> {
+        let (letter_index, tonic, intervals) = parse_key(key)?;
+
+        let mut spellings: [Option<(char, i32)>; 12] = [None; 12];
+        for (degree, interval) in intervals.iter().enumerate() {
+            let (letter, natural) = LETTERS[(letter_index + degree) % 7];
+            let pitch_class = (tonic + *interval as i32).rem_euclid(12);
+            // Between a double flat and a double sharp
+            let alteration = (pitch_class - natural + 6).rem_euclid(12) - 6;
+            spellings[pitch_class as usize] = Some((letter, alteration));
+        }
+
+        let flats = spellings.iter().flatten().any(|(_, alteration)| *alteration < 0);
+        let mut names: [String; 12] = Default::default();
+        let mut alterations = [0; 12];
+        for (pitch_class, spelling) in spellings.iter().enumerate() {
+            let (letter, alteration) = spelling.unwrap_or_else(|| {
+                let name = if flats { FLAT_NAMES[pitch_class] } else { SHARP_NAMES[pitch_class] };
+                (name.chars().next().unwrap(), if name.len() == 1 { 0 } else if flats { -1 } else { 1 })
+            });
+            let accidental = match alteration {
+                -2 => "bb",
+                -1 => "b",
+                1 => "#",
+                2 => "##",
+                _ => ""
+            };
+            names[pitch_class] = format!("{}{}", letter, accidental);
+            alterations[pitch_class] = alteration;
+        }
+
+        Some(PitchSpeller { names, alterations })
+    }
+
+    /// Names a MIDI pitch in scientific pitch notation, like `pitch_name`. The
+    /// octave goes with the letter, so 59 is Cb4 where it's spelled as Cb.
+    pub fn pitch_name(&self, pitch: u32) -> String {
+        let pitch_class = (pitch % 12) as usize;
+        let octave = (pitch as i32 - self.alterations[pitch_class]).div_euclid(12) - 1;
+        format!("{}{}", self.names[pitch_class], octave)
+    }
+
+    /// Names a pitch class (0-11)
+    pub fn pitch_class_name(&self, pitch_class: u8) -> &str {
+        &self.names[(pitch_class % 12) as usize]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pitch_name_range() {
+        assert_eq!(pitch_name(0, false), "C-1");
+        assert_eq!(pitch_name(60, false), "C4");
+        assert_eq!(pitch_name(69, false), "A4");
+        assert_eq!(pitch_name(127, false), "G9");
+    }
+
+    #[test]
+    fn test_key_pitch_classes() {
+        let pitch_classes = |key| {
+            let mask = key_pitch_classes(key).unwrap();
+            (0..12).filter(|pitch_class| mask & (1 << pitch_class) != 0).collect::<Vec<u8>>()
+        };
+        assert_eq!(pitch_classes("C major"), [0, 2, 4, 5, 7, 9, 11]);
+        // G#, A#, B, C#, D#, E, and F#
+        assert_eq!(pitch_classes("G# minor"), [1, 3, 4, 6, 8, 10, 11]);
+        // Bb, C, Db, Eb, F, G, and Ab
+        assert_eq!(pitch_classes("Bb dorian"), [0, 1, 3, 5, 7, 8, 10]);
+        // Wraps below C
+        assert_eq!(pitch_classes("Cb major"), pitch_classes("B major"));
+        assert_eq!(pitch_classes("f# MIXOLYDIAN"), pitch_classes("B major"));
+
+        assert_eq!(key_pitch_classes("C"), None);
+        assert_eq!(key_pitch_classes("C## major"), None);
+        assert_eq!(key_pitch_classes("C blues"), None);
+    }
+
+    #[test]
+    fn test_pitch_speller() {
+        let spell = |key| {
+            let speller = PitchSpeller::for_key(key).unwrap();
+            (0..12).map(|pitch_class| speller.pitch_class_name(pitch_class).to_string()).collect::<Vec<String>>()
+        };
+        assert_eq!(spell("G major"), ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"]);
+        assert_eq!(spell("F major"), ["C", "Db", "D", "Eb", "E", "F", "Gb", "G", "Ab", "A", "Bb", "B"]);
+        assert_eq!(spell("Eb major"), ["C", "Db", "D", "Eb", "E", "F", "Gb", "G", "Ab", "A", "Bb", "B"]);
+        assert_eq!(spell("C# major"), ["B#", "C#", "D", "D#", "E", "E#", "F#", "G", "G#", "A", "A#", "B"]);
+        // Bbb is the fourth of Fb major, and Cb its fifth
+        assert_eq!(spell("Fb major"), ["C", "Db", "D", "Eb", "Fb", "F", "Gb", "G", "Ab", "Bbb", "Bb", "Cb"]);
+
+        let speller = PitchSpeller::for_key("Fb major").unwrap();
+        assert_eq!(speller.pitch_name(59), "Cb4");
+        assert_eq!(speller.pitch_name(57), "Bbb3");
+        assert_eq!(PitchSpeller::for_key("C# major").unwrap().pitch_name(60), "B#3");
+        assert_eq!(PitchSpeller::for_key("C major").unwrap().pitch_name(61), "C#4");
+        assert_eq!(PitchSpeller::for_key("C"), None);
+    }
+
+    #[test]
+    fn test_pitch_name_accidentals() {
+        assert_eq!(pitch_name(54, false), "F#3");
+        assert_eq!(pitch_name(54, true), "Gb3");
+        assert_eq!(pitch_name(70, false), "A#4");
+        assert_eq!(pitch_name(70, true), "Bb4");
+        assert_eq!(pitch_name(64, true), "E4");
+    }
+
+    #[test]
+    fn test_program_name() {
+        assert_eq!(program_name(0), "Acoustic Grand Piano");
+        assert_eq!(program_name(56), "Trumpet");
+        assert_eq!(program_name(127), "Gunshot");
+    }
+
+    #[test]
+    fn test_drum_name() {
+        assert_eq!(drum_name(35), Some("Acoustic Bass Drum"));
+        assert_eq!(drum_name(36), Some("Bass Drum 1"));
+        assert_eq!(drum_name(38), Some("Acoustic Snare"));
+        assert_eq!(drum_name(42), Some("Closed Hi-Hat"));
+        assert_eq!(drum_name(46), Some("Open Hi-Hat"));
+        assert_eq!(drum_name(49), Some("Crash Cymbal 1"));
+        assert_eq!(drum_name(51), Some("Ride Cymbal 1"));
+        assert_eq!(drum_name(54), Some("Tambourine"));
+        assert_eq!(drum_name(56), Some("Cowbell"));
+        assert_eq!(drum_name(70), Some("Maracas"));
+        assert_eq!(drum_name(81), Some("Open Triangle"));
+        assert_eq!(drum_name(34), None);
+        assert_eq!(drum_name(82), None);
+        assert_eq!(drum_name(0), None);
+    }
+
+    #[test]
+    fn test_key_name_major() {
+        let names: Vec<String> = (-7..=7).map(|sharps_flats| key_name(sharps_flats, true).unwrap()).collect();
+        assert_eq!(names, vec![
+            "Cb major", "Gb major", "Db major", "Ab major", "Eb major", "Bb major", "F major", "C major",
+            "G major", "D major", "A major", "E major", "B major", "F# major", "C# major"
+        ]);
+    }
+
+    #[test]
+    fn test_key_name_minor() {
+        let names: Vec<String> = (-7..=7).map(|sharps_flats| key_name(sharps_flats, false).unwrap()).collect();
+        assert_eq!(names, vec![
+            "Ab minor", "Eb minor", "Bb minor", "F minor", "C minor", "G minor", "D minor", "A minor",
+            "E minor", "B minor", "F# minor", "C# minor", "G# minor", "D# minor", "A# minor"
+        ]);
+    }
+
+    #[test]
+    fn test_key_name_edge_cases() {
+        assert_eq!(key_name(-7, true).unwrap(), "Cb major");
+        assert_eq!(key_name(5, false).unwrap(), "G# minor");
+        assert_eq!(key_name(0, true).unwrap(), "C major");
+        assert_eq!(key_name(8, true), None);
+        assert_eq!(key_name(-8, false), None);
+    }
+}
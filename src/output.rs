@@ -0,0 +1,276 @@
+//! Writing notes out in the supported formats
+
+use std::io::{self, Write};
+use crate::{LoggedEvent, Note, NoteInfo, TimeValue};
+use crate::precision::{Rounded, round_half_even};
+
+/// The format used to write the notes
+#[derive(Clone, Debug, PartialEq)]
+pub enum OutputFormat {
+    Json,
+    /// JSON without the indentation and newlines
+    CompactJson,
+    /// JSON Lines, with one note object per line
+    Jsonl,
+    /// The same structure as the JSON, in YAML syntax
+    Yaml,
+    Csv(CsvOptions)
+}
+
+impl OutputFormat {
+    /// The usual file extension for this format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Json | OutputFormat::CompactJson => "json",
+            OutputFormat::Jsonl => "jsonl",
+            OutputFormat::Yaml => "yaml",
+            OutputFormat::Csv(_) => "csv"
+        }
+    }
+}
+
+/// Settings for CSV output
+#[derive(Clone, Debug, PartialEq)]
+pub struct CsvOptions {
+    pub separator: char,
+    pub header: bool
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions {
+            separator: ',',
+            header: true
+        }
+    }
+}
+
+/// Writes the note info to the writer in the given format, with every float
+/// rounded to `precision` decimal places if it's set
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::{Note, NoteInfo};
+/// use midi2json::output::{write_output, OutputFormat, CsvOptions};
+///
+/// let note_info = NoteInfo {
+///     notes: Some(vec![Note { time_start: 0.0.into(), time_end: 0.5.into(), pitch_value: 60, channel: 1, velocity: 100, ..Default::default() }]),
+///     ..Default::default()
+/// };
+///
+/// let mut csv = Vec::new();
+/// write_output(&mut csv, &note_info, &OutputFormat::Csv(CsvOptions::default()), None).unwrap();
+/// assert_eq!(String::from_utf8(csv).unwrap(), "time_start,time_end,pitch_value,channel,velocity\n0,0.5,60,1,100\n");
+/// ```
+pub fn write_output<W: Write>(
+    writer: &mut W,
+    note_info: &NoteInfo,
+    format: &OutputFormat,
+    precision: Option<u32>
+) -> io::Result<()> {
+    match format {
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(&mut *writer, &Rounded::new(note_info, precision))?;
+            writeln!(writer)
+        },
+        OutputFormat::CompactJson => {
+            serde_json::to_writer(&mut *writer, &Rounded::new(note_info, precision))?;
+            writeln!(writer)
+        },
+        OutputFormat::Jsonl => {
+            for note in note_info.all_notes() {
+                write_jsonl_note(writer, note, precision)?;
+            }
+            Ok(())
+        },
+        OutputFormat::Yaml => {
+            serde_yaml::to_writer(&mut *writer, &Rounded::new(note_info, precision))
+                .map_err(io::Error::other)
+        },
+        OutputFormat::Csv(options) => write_csv(writer, note_info.all_notes(), options, precision)
+    }
+}
+
+/// Writes a single note as one line of JSON Lines, rounding its floats to
+/// `precision` decimal places if it's set
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::Note;
+/// use midi2json::output::write_jsonl_note;
+///
+/// let mut output = Vec::new();
+/// write_jsonl_note(&mut output, &Note { pitch_value: 60, ..Default::default() }, None).unwrap();
+/// assert!(output.ends_with(b"}\n"));
+/// ```
+pub fn write_jsonl_note<W: Write>(writer: &mut W, note: &Note, precision: Option<u32>) -> io::Result<()> {
+    serde_json::to_writer(&mut *writer, &Rounded::new(note, precision))?;
+    writeln!(writer)
+}
+
+/// Writes a JSON Schema (draft-07) describing the JSON output, including every
+/// field that is only written when asked for
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::output::write_schema;
+///
+/// let mut output = Vec::new();
+/// write_schema(&mut output).unwrap();
+/// let schema: serde_json::Value = serde_json::from_slice(&output).unwrap();
+/// assert_eq!(schema["title"], "NoteInfo");
+/// ```
+pub fn write_schema<W: Write>(writer: &mut W) -> io::Result<()> {
+    let schema = schemars::schema_for!(NoteInfo);
+    serde_json::to_writer_pretty(&mut *writer, &schema)?;
+    writeln!(writer)
+}
+
+/// Writes an event log, from `get_event_log`, as a JSON array
+pub fn write_event_log<W: Write>(writer: &mut W, event_log: &[LoggedEvent]) -> io::Result<()> {
+    serde_json::to_writer_pretty(&mut *writer, event_log)?;
+    writeln!(writer)
+}
+
+fn write_csv<'a, W: Write>(
+    writer: &mut W,
+    notes: impl Iterator<Item = &'a Note>,
+    options: &CsvOptions,
+    precision: Option<u32>
+) -> io::Result<()> {
+    let sep = options.separator;
+    // Ticks are whole numbers already
+    let format_time = |time: TimeValue| match (time, precision) {
+        (TimeValue::Ticks(_), _) | (_, None) => time.to_string(),
+        (time, Some(precision)) => round_half_even(time.as_f64(), precision).to_string()
+    };
+    if options.header {
+        writeln!(writer, "time_start{0}time_end{0}pitch_value{0}channel{0}velocity", sep)?;
+    }
+
+    for note in notes {
+        writeln!(
+            writer,
+            "{1}{0}{2}{0}{3}{0}{4}{0}{5}",
+            sep, format_time(note.time_start), format_time(note.time_end), note.pitch_value, note.channel, note.velocity
+        )?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn get_note_info() -> NoteInfo {
+        NoteInfo {
+            notes: Some(vec![
+                Note { time_start: 0.0.into(), time_end: 0.25.into(), pitch_value: 60, channel: 0, velocity: 64, ..Default::default() },
+                Note { time_start: 0.25.into(), time_end: 1.125.into(), pitch_value: 72, channel: 9, velocity: 127, ..Default::default() }
+            ]),
+            ..Default::default()
+        }
+    }
+
+    fn write_to_string(format: &OutputFormat) -> String {
+        let mut output = Vec::new();
+        write_output(&mut output, &get_note_info(), format, None).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_write_csv_round_trip() {
+        let output = write_to_string(&OutputFormat::Csv(CsvOptions::default()));
+
+        let mut reader = csv::Reader::from_reader(output.as_bytes());
+        let headers = reader.headers().unwrap().clone();
+        assert_eq!(headers, vec!["time_start", "time_end", "pitch_value", "channel", "velocity"]);
+
+        let rows: Vec<(f64, f64, u32, u8, u8)> = reader.deserialize()
+            .map(|row| row.unwrap())
+            .collect();
+        assert_eq!(rows, vec![
+            (0.0, 0.25, 60, 0, 64),
+            (0.25, 1.125, 72, 9, 127)
+        ]);
+    }
+
+    #[test]
+    fn test_write_csv_options() {
+        let output = write_to_string(&OutputFormat::Csv(CsvOptions {
+            separator: ';',
+            header: false
+        }));
+        assert_eq!(output, "0;0.25;60;0;64\n0.25;1.125;72;9;127\n");
+
+        let mut reader = csv::ReaderBuilder::new()
+            .delimiter(b';')
+            .has_headers(false)
+            .from_reader(output.as_bytes());
+        assert_eq!(reader.records().count(), 2);
+    }
+
+    #[test]
+    fn test_write_compact_json() {
+        let notes = (0..1000)
+            .map(|i| Note {
+                time_start: (i as f64 * 0.5).into(),
+                time_end: (i as f64 * 0.5 + 0.25).into(),
+                pitch_value: 36 + (i % 48),
+                velocity: 100,
+                ..Default::default()
+            })
+            .collect();
+        let note_info = NoteInfo {
+            notes: Some(notes),
+            ..Default::default()
+        };
+
+        let mut pretty = Vec::new();
+        write_output(&mut pretty, &note_info, &OutputFormat::Json, None).unwrap();
+        let mut compact = Vec::new();
+        write_output(&mut compact, &note_info, &OutputFormat::CompactJson, None).unwrap();
+
+        assert!(compact.len() * 3 < pretty.len() * 2, "{} bytes compact vs {} pretty", compact.len(), pretty.len());
+        assert_eq!(compact.iter().filter(|&&byte| byte == b'\n').count(), 1);
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&compact).unwrap(),
+            serde_json::from_slice::<serde_json::Value>(&pretty).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_write_yaml_matches_json() {
+        let yaml: serde_json::Value = serde_yaml::from_str(&write_to_string(&OutputFormat::Yaml)).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&write_to_string(&OutputFormat::Json)).unwrap();
+        assert_eq!(yaml, json);
+        assert_eq!(yaml["notes"][1]["time_end"], 1.125);
+    }
+
+    #[test]
+    fn test_write_output_precision() {
+        let mut csv = Vec::new();
+        write_output(&mut csv, &get_note_info(), &OutputFormat::Csv(CsvOptions::default()), Some(2)).unwrap();
+        assert!(String::from_utf8(csv).unwrap().ends_with("0.25,1.12,72,9,127\n"));
+
+        let mut yaml = Vec::new();
+        write_output(&mut yaml, &get_note_info(), &OutputFormat::Yaml, Some(0)).unwrap();
+        let yaml: serde_json::Value = serde_yaml::from_slice(&yaml).unwrap();
+        assert_eq!(yaml["notes"][1]["time_end"], 1.0);
+    }
+
+    #[test]
+    fn test_write_jsonl() {
+        let output = write_to_string(&OutputFormat::Jsonl);
+        let lines: Vec<serde_json::Value> = output.lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["pitch_value"], 60);
+        assert_eq!(lines[1]["time_end"], 1.125);
+    }
+}
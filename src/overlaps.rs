@@ -0,0 +1,396 @@
+//! Notes of the same pitch on the same channel that sound at the same time
+
+use std::collections::{HashMap, HashSet};
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+
+use crate::{Note, TimeValue};
+
+/// Two notes of the same pitch and channel that overlap, as written to the output
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Overlap {
+    /// The index of the track (or pattern) both notes are in, when notes are
+    /// split up by track
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub track: Option<usize>,
+    pub channel: u8,
+    pub pitch_value: u32,
+    /// When the note that starts first starts and ends
+    pub first_start: TimeValue,
+    pub first_end: TimeValue,
+    /// When the note that starts second starts and ends
+    pub second_start: TimeValue,
+    pub second_end: TimeValue
+}
+
+/// Finds every pair of notes with the same channel and pitch where each one
+/// starts before the other ends. Notes that only touch, with one ending
+/// exactly when the next starts, don't overlap.
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::{find_overlaps, Note};
+///
+/// let notes = vec![
+///     Note { time_start: 0.0.into(), time_end: 1.0.into(), pitch_value: 60, ..Default::default() },
+///     Note { time_start: 0.5.into(), time_end: 1.5.into(), pitch_value: 60, ..Default::default() },
+///     Note { time_start: 0.5.into(), time_end: 1.5.into(), pitch_value: 64, ..Default::default() }
+/// ];
+/// let overlaps = find_overlaps(&notes);
+/// assert_eq!(overlaps.len(), 1);
+/// assert_eq!(overlaps[0].second_start, 0.5.into());
+/// ```
+pub fn find_overlaps(notes: &[Note]) -> Vec<Overlap> {
+    let mut overlaps = Vec::new();
+    for group in group_by_key(notes) {
+        for (i, &first_index) in group.iter().enumerate() {
+            let first = &notes[first_index];
+            // The group is in start order, so once a note starts after this one
+            // ends, every later one does too
+            for &second_index in &group[i + 1..] {
+                let second = &notes[second_index];
+                if second.time_start.as_f64() >= first.time_end.as_f64() {
+                    break;
+                }
+                overlaps.push(Overlap {
+                    track: None,
+                    channel: first.channel,
+                    pitch_value: first.pitch_value,
+                    first_start: first.time_start,
+                    first_end: first.time_end,
+                    second_start: second.time_start,
+                    second_end: second.time_end
+                });
+            }
+        }
+    }
+
+    // The groups come out of a HashMap, so their order has to be pinned down here
+    overlaps.sort_by(|a, b| {
+        a.first_start.as_f64().partial_cmp(&b.first_start.as_f64()).unwrap()
+            .then_with(|| (a.channel, a.pitch_value).cmp(&(b.channel, b.pitch_value)))
+            .then_with(|| a.second_start.as_f64().partial_cmp(&b.second_start.as_f64()).unwrap())
+    });
+    overlaps
+}
+
+/// Ends each note that overlaps a later note of the same channel and pitch
+/// when the later one starts, so no two of them sound at once.
+///
+/// Returns the number of notes that were shortened.
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::{resolve_overlaps, Note};
+///
+/// let mut notes = vec![
+///     Note { time_start: 0.0.into(), time_end: 1.0.into(), pitch_value: 60, ..Default::default() },
+///     Note { time_start: 0.5.into(), time_end: 1.5.into(), pitch_value: 60, ..Default::default() }
+/// ];
+/// assert_eq!(resolve_overlaps(&mut notes), 1);
+/// assert_eq!(notes[0].time_end, 0.5.into());
+/// ```
+pub fn resolve_overlaps(notes: &mut [Note]) -> usize {
+    let mut resolved_count = 0;
+    for group in group_by_key(notes) {
+        for pair in group.windows(2) {
+            let next_start = notes[pair[1]].time_start;
+            let note = &mut notes[pair[0]];
+            if next_start.as_f64() < note.time_end.as_f64() {
+                note.time_end = next_start;
+                note.duration = Some(next_start.since(note.time_start));
+                resolved_count += 1;
+            }
+        }
+    }
+
+    resolved_count
+}
+
+/// Merges each run of notes of the same channel and pitch that overlap, touch,
+/// or are at most `gap` apart into a single note, from the earliest start to the
+/// latest end. The gap is in the same unit as the note times.
+///
+/// The merged note keeps the first note's velocity and other details, and takes
+/// its release from the note that ends last. Returns the number of notes that
+/// were merged into an earlier one and removed.
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::{merge_legato, Note};
+///
+/// let mut notes = vec![
+///     Note { time_start: 0.0.into(), time_end: 1.0.into(), pitch_value: 60, ..Default::default() },
+///     Note { time_start: 0.9.into(), time_end: 2.0.into(), pitch_value: 60, ..Default::default() }
+/// ];
+/// assert_eq!(merge_legato(&mut notes, 0.0), 1);
+/// assert_eq!(notes.len(), 1);
+/// assert_eq!(notes[0].time_end, 2.0.into());
+/// ```
+pub fn merge_legato(notes: &mut Vec<Note>, gap: f64) -> usize {
+    let mut merged = vec![false; notes.len()];
+    for group in group_by_key(notes) {
+        let mut group = group.into_iter();
+        let mut current = match group.next() {
+            Some(index) => index,
+            None => continue
+        };
+        for next in group {
+            if notes[next].time_start.as_f64() - notes[current].time_end.as_f64() <= gap {
+                let next_note = notes[next].clone();
+                let note = &mut notes[current];
+                // A note inside the current one doesn't extend it
+                if next_note.time_end.as_f64() > note.time_end.as_f64() {
+                    note.time_end = next_note.time_end;
+                    note.release_velocity = next_note.release_velocity;
+                    note.truncated = next_note.truncated;
+                }
+                note.duration = Some(note.time_end.since(note.time_start));
+                merged[next] = true;
+            } else {
+                current = next;
+            }
+        }
+    }
+
+    let mut index = 0;
+    notes.retain(|_| {
+        index += 1;
+        !merged[index - 1]
+    });
+
+    merged.iter().filter(|&&is_merged| is_merged).count()
+}
+
+/// Sets each note's `articulation` to `"legato"` if the next note of the same
+/// channel and pitch starts less than `threshold` after it ends (or before it
+/// ends), or `"staccato"` otherwise. The last note of each pitch has nothing
+/// to lead into, so it's staccato. The threshold is in the same unit as the
+/// note times.
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::{classify_articulation, Note};
+///
+/// let mut notes = vec![
+///     Note { time_start: 0.0.into(), time_end: 0.98.into(), pitch_value: 60, ..Default::default() },
+///     Note { time_start: 1.0.into(), time_end: 1.5.into(), pitch_value: 60, ..Default::default() }
+/// ];
+/// classify_articulation(&mut notes, 0.05);
+/// assert_eq!(notes[0].articulation.as_deref(), Some("legato"));
+/// assert_eq!(notes[1].articulation.as_deref(), Some("staccato"));
+/// ```
+pub fn classify_articulation(notes: &mut [Note], threshold: f64) {
+    for group in group_by_key(notes) {
+        for (position, &index) in group.iter().enumerate() {
+            let is_legato = group.get(position + 1)
+                .is_some_and(|&next| notes[next].time_start.as_f64() - notes[index].time_end.as_f64() < threshold);
+            let articulation = if is_legato { "legato" } else { "staccato" };
+            notes[index].articulation = Some(articulation.to_string());
+        }
+    }
+}
+
+/// Removes the notes that have the same pitch, channel, start, and end as an
+/// earlier one, keeping the first of each. The rest stay in order.
+///
+/// Returns the number of notes that were removed.
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::{remove_duplicates, Note};
+///
+/// let note = Note { time_start: 0.0.into(), time_end: 1.0.into(), pitch_value: 60, ..Default::default() };
+/// let mut notes = vec![note.clone(), Note { velocity: 90, ..note }];
+/// assert_eq!(remove_duplicates(&mut notes), 1);
+/// assert_eq!(notes[0].velocity, 0);
+/// ```
+pub fn remove_duplicates(notes: &mut Vec<Note>) -> usize {
+    let mut seen = HashSet::<(u32, u8, u64, u64)>::new();
+    let original_count = notes.len();
+    notes.retain(|note| seen.insert((
+        note.pitch_value,
+        note.channel,
+        note.time_start.as_f64().to_bits(),
+        note.time_end.as_f64().to_bits()
+    )));
+
+    original_count - notes.len()
+}
+
+/// The indices of the notes for each channel and pitch, each in start order
+fn group_by_key(notes: &[Note]) -> Vec<Vec<usize>> {
+    let mut groups = HashMap::<(u8, u32), Vec<usize>>::new();
+    for (index, note) in notes.iter().enumerate() {
+        groups.entry((note.channel, note.pitch_value)).or_default().push(index);
+    }
+
+    groups.into_values()
+        .map(|mut group| {
+            group.sort_by(|&a, &b| notes[a].time_start.as_f64().partial_cmp(&notes[b].time_start.as_f64()).unwrap());
+            group
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn note(time_start: f64, time_end: f64, pitch_value: u32) -> Note {
+        Note {
+            time_start: time_start.into(),
+            time_end: time_end.into(),
+            duration: Some((time_end - time_start).into()),
+            pitch_value,
+            ..Default::default()
+        }
+    }
+
+    fn get_times(notes: &[Note]) -> Vec<(f64, f64)> {
+        notes.iter()
+            .map(|note| (note.time_start.as_f64(), note.time_end.as_f64()))
+            .collect()
+    }
+
+    #[test]
+    fn test_find_overlaps_adjacent() {
+        let notes = vec![note(0.0, 1.0, 60), note(1.0, 2.0, 60)];
+        assert!(find_overlaps(&notes).is_empty());
+    }
+
+    #[test]
+    fn test_find_overlaps_partial() {
+        let notes = vec![note(1.0, 3.0, 60), note(0.0, 2.0, 60)];
+        assert_eq!(find_overlaps(&notes), vec![Overlap {
+            track: None,
+            channel: 0,
+            pitch_value: 60,
+            first_start: 0.0.into(),
+            first_end: 2.0.into(),
+            second_start: 1.0.into(),
+            second_end: 3.0.into()
+        }]);
+    }
+
+    #[test]
+    fn test_find_overlaps_contained() {
+        let notes = vec![note(0.0, 4.0, 60), note(1.0, 2.0, 60), note(3.0, 5.0, 60)];
+        let pairs: Vec<(f64, f64)> = find_overlaps(&notes).iter()
+            .map(|overlap| (overlap.first_start.as_f64(), overlap.second_start.as_f64()))
+            .collect();
+        assert_eq!(pairs, vec![(0.0, 1.0), (0.0, 3.0)]);
+    }
+
+    #[test]
+    fn test_find_overlaps_different_keys() {
+        let mut notes = vec![note(0.0, 2.0, 60), note(1.0, 3.0, 62), note(1.0, 3.0, 60)];
+        notes[2].channel = 1;
+        assert!(find_overlaps(&notes).is_empty());
+    }
+
+    #[test]
+    fn test_merge_legato_chain() {
+        // Three overlapping notes in a row, with a separate note after a rest
+        let mut notes = vec![
+            note(0.0, 1.0, 60),
+            note(0.75, 2.0, 60),
+            note(1.5, 3.0, 60),
+            note(1.0, 1.5, 64),
+            note(4.0, 5.0, 60)
+        ];
+        notes[2].release_velocity = Some(40);
+        assert_eq!(merge_legato(&mut notes, 0.0), 2);
+        assert_eq!(get_times(&notes), vec![(0.0, 3.0), (1.0, 1.5), (4.0, 5.0)]);
+        assert_eq!(notes[0].duration, Some(3.0.into()));
+        assert_eq!(notes[0].release_velocity, Some(40));
+    }
+
+    #[test]
+    fn test_merge_legato_gap() {
+        let mut notes = vec![note(0.0, 1.0, 60), note(1.0, 2.0, 60), note(2.05, 3.0, 60), note(3.5, 4.0, 60)];
+        let mut contiguous = notes.clone();
+        assert_eq!(merge_legato(&mut contiguous, 0.0), 1);
+        assert_eq!(get_times(&contiguous), vec![(0.0, 2.0), (2.05, 3.0), (3.5, 4.0)]);
+
+        assert_eq!(merge_legato(&mut notes, 0.1), 2);
+        assert_eq!(get_times(&notes), vec![(0.0, 3.0), (3.5, 4.0)]);
+    }
+
+    #[test]
+    fn test_merge_legato_contained() {
+        let mut notes = vec![note(0.0, 4.0, 60), note(1.0, 2.0, 60), note(3.0, 5.0, 60)];
+        assert_eq!(merge_legato(&mut notes, 0.0), 2);
+        assert_eq!(get_times(&notes), vec![(0.0, 5.0)]);
+    }
+
+    #[test]
+    fn test_classify_articulation() {
+        let mut notes = vec![
+            note(0.0, 1.0, 60),
+            // Exactly at the threshold after the note before, which is staccato
+            note(1.25, 2.0, 60),
+            note(2.125, 3.0, 60),
+            // The last note of its pitch, even though another pitch follows right away
+            note(3.0, 4.0, 60),
+            note(4.0, 5.0, 64)
+        ];
+        classify_articulation(&mut notes, 0.25);
+        let articulations: Vec<&str> = notes.iter()
+            .map(|note| note.articulation.as_deref().unwrap())
+            .collect();
+        assert_eq!(articulations, vec!["staccato", "legato", "legato", "staccato", "staccato"]);
+    }
+
+    #[test]
+    fn test_remove_duplicates_three_way() {
+        let mut notes = vec![
+            note(0.0, 1.0, 60),
+            note(0.0, 1.0, 60),
+            note(0.0, 1.0, 64),
+            note(0.0, 1.0, 60)
+        ];
+        notes[1].velocity = 1;
+        notes[3].velocity = 2;
+        assert_eq!(remove_duplicates(&mut notes), 2);
+        assert_eq!(get_times(&notes), vec![(0.0, 1.0), (0.0, 1.0)]);
+        assert_eq!(notes[0].velocity, 0);
+        assert_eq!(notes[1].pitch_value, 64);
+    }
+
+    #[test]
+    fn test_remove_duplicates_near_misses() {
+        let mut notes = vec![note(0.0, 1.0, 60), note(0.0, 1.5, 60), note(0.5, 1.0, 60), note(0.0, 1.0, 61)];
+        notes.push(Note { channel: 1, ..note(0.0, 1.0, 60) });
+        assert_eq!(remove_duplicates(&mut notes), 0);
+        assert_eq!(notes.len(), 5);
+    }
+
+    #[test]
+    fn test_resolve_overlaps() {
+        let mut notes = vec![
+            note(0.0, 1.0, 60),
+            note(1.0, 2.0, 60),
+            note(3.0, 6.0, 60),
+            note(4.0, 5.0, 60),
+            note(5.5, 7.0, 60),
+            note(3.5, 4.5, 64)
+        ];
+        assert_eq!(resolve_overlaps(&mut notes), 1);
+        assert_eq!(get_times(&notes), vec![
+            (0.0, 1.0),
+            (1.0, 2.0),
+            (3.0, 4.0),
+            (4.0, 5.0),
+            (5.5, 7.0),
+            (3.5, 4.5)
+        ]);
+        assert_eq!(notes[2].duration, Some(1.0.into()));
+        assert!(find_overlaps(&notes).is_empty());
+    }
+}
@@ -0,0 +1,148 @@
+//! A text picture of the notes, for checking a conversion at a glance
+
+use crate::names;
+use crate::Note;
+
+/// The lowest key on an 88-key piano, A0
+pub const LOWEST_KEY: u32 = 21;
+/// The highest key on an 88-key piano, C8
+pub const HIGHEST_KEY: u32 = 108;
+
+const FILLED: char = '█';
+const EMPTY: char = ' ';
+
+/// Draws the notes as a piano roll, with a row for each piano key from the
+/// highest down and `columns` columns of time from 0 to the end of the last
+/// note. A cell is filled if any note of its key sounds during it.
+///
+/// If `measure_starts` has the times the measures start, in the same unit as
+/// the notes, the roll is framed above and below with a `|` over each one.
+/// Notes outside the piano's range aren't drawn.
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::Note;
+/// use midi2json::piano_roll::render_piano_roll;
+///
+/// let notes = vec![Note { time_start: 0.0.into(), time_end: 1.0.into(), pitch_value: 60, ..Default::default() }];
+/// let roll = render_piano_roll(notes.iter(), 10, &[]);
+/// assert_eq!(roll.lines().count(), 88);
+/// assert!(roll.contains(" C4 |██████████|"));
+/// ```
+pub fn render_piano_roll<'a>(notes: impl Iterator<Item = &'a Note>, columns: usize, measure_starts: &[f64]) -> String {
+    let notes: Vec<&Note> = notes
+        .filter(|note| (LOWEST_KEY..=HIGHEST_KEY).contains(&note.pitch_value))
+        .collect();
+    let end = notes.iter()
+        .map(|note| note.time_end.as_f64())
+        .fold(0.0, f64::max);
+    // Without any notes there's nothing to scale to, but the keys are still drawn
+    let column_length = if end > 0.0 { end / columns as f64 } else { 1.0 };
+
+    let mut rows = vec![vec![EMPTY; columns]; (HIGHEST_KEY - LOWEST_KEY + 1) as usize];
+    for note in notes {
+        let row = &mut rows[(HIGHEST_KEY - note.pitch_value) as usize];
+        let start = note.time_start.as_f64();
+        let end = note.time_end.as_f64();
+        for (column, cell) in row.iter_mut().enumerate() {
+            let column_start = column as f64 * column_length;
+            if start < column_start + column_length && end > column_start {
+                *cell = FILLED;
+            }
+        }
+    }
+
+    let mut roll = String::new();
+    let frame = measure_frame(columns, column_length, measure_starts);
+    if let Some(frame) = &frame {
+        roll.push_str(frame);
+    }
+    for (index, row) in rows.iter().enumerate() {
+        let pitch_name = names::pitch_name(HIGHEST_KEY - index as u32, false);
+        roll.push_str(&format!("{:>4} |{}|\n", pitch_name, row.iter().collect::<String>()));
+    }
+    if let Some(frame) = &frame {
+        roll.push_str(frame);
+    }
+
+    roll
+}
+
+/// A line with a `|` over the column each measure starts in, or `None` if
+/// there are no measures to mark
+fn measure_frame(columns: usize, column_length: f64, measure_starts: &[f64]) -> Option<String> {
+    if measure_starts.is_empty() {
+        return None;
+    }
+
+    let mut markers = vec!['-'; columns];
+    for &measure_start in measure_starts {
+        let column = (measure_start / column_length).floor();
+        if column >= 0.0 && (column as usize) < columns {
+            markers[column as usize] = '|';
+        }
+    }
+
+    Some(format!("{:>4} +{}+\n", "", markers.iter().collect::<String>()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn note(time_start: f64, time_end: f64, pitch_value: u32) -> Note {
+        Note {
+            time_start: time_start.into(),
+            time_end: time_end.into(),
+            pitch_value,
+            ..Default::default()
+        }
+    }
+
+    fn get_row<'a>(roll: &'a str, pitch_name: &str) -> &'a str {
+        roll.lines()
+            .find(|line| line.trim_start().starts_with(&format!("{} |", pitch_name)))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_render_piano_roll_two_notes() {
+        // Middle C for the first half, then the E above for the last quarter
+        let notes = [note(0.0, 2.0, 60), note(3.0, 4.0, 64)];
+        let roll = render_piano_roll(notes.iter(), 8, &[]);
+        assert_eq!(roll.lines().count(), 88);
+        assert_eq!(roll.matches(FILLED).count(), 6);
+        assert_eq!(get_row(&roll, "C4"), "  C4 |████    |");
+        assert_eq!(get_row(&roll, "E4"), "  E4 |      ██|");
+        assert_eq!(roll.lines().next().unwrap(), "  C8 |        |");
+        assert_eq!(roll.lines().last().unwrap(), "  A0 |        |");
+    }
+
+    #[test]
+    fn test_render_piano_roll_short_note() {
+        // Shorter than a column, but it's still drawn
+        let notes = [note(0.0, 8.0, 60), note(4.1, 4.2, 72)];
+        let roll = render_piano_roll(notes.iter(), 8, &[]);
+        assert_eq!(get_row(&roll, "C5"), "  C5 |    █   |");
+    }
+
+    #[test]
+    fn test_render_piano_roll_measures() {
+        let notes = [note(0.0, 4.0, 60), note(0.0, 4.0, 20), note(0.0, 4.0, 120)];
+        let roll = render_piano_roll(notes.iter(), 8, &[0.0, 2.0, 4.0]);
+        let lines: Vec<&str> = roll.lines().collect();
+        assert_eq!(lines.len(), 90);
+        assert_eq!(lines[0], "     +|---|---+");
+        assert_eq!(lines[89], lines[0]);
+        // Only the key in the piano's range is drawn
+        assert_eq!(roll.matches(FILLED).count(), 8);
+    }
+
+    #[test]
+    fn test_render_piano_roll_empty() {
+        let roll = render_piano_roll([].iter(), 4, &[]);
+        assert_eq!(roll.lines().count(), 88);
+        assert_eq!(roll.matches(FILLED).count(), 0);
+    }
+}
@@ -0,0 +1,425 @@
+//! Rounding the floats in the output to a number of decimal places
+//!
+//! Times worked out from ticks often come out a hair off, like
+//! `0.5000000000000001`. Rounding happens as the output is serialized, so the
+//! converted values themselves keep their full precision.
+
+use serde::ser::{self, Serialize, Serializer};
+
+/// Rounds to a number of decimal places, with ties going to the even digit
+/// (banker's rounding) so that rounding many values doesn't bias them upward
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::precision::round_half_even;
+///
+/// assert_eq!(round_half_even(0.5000000000000001, 4), 0.5);
+/// assert_eq!(round_half_even(0.125, 2), 0.12);
+/// assert_eq!(round_half_even(2.5, 0), 2.0);
+/// ```
+pub fn round_half_even(value: f64, decimal_places: u32) -> f64 {
+    let scale = 10f64.powi(decimal_places as i32);
+    let rounded = (value * scale).round_ties_even() / scale;
+    // Values too large to scale are already as precise as they can be
+    if rounded.is_finite() { rounded } else { value }
+}
+
+/// A float that is rounded to a number of decimal places when it's serialized
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RoundedF64 {
+    pub value: f64,
+    pub decimal_places: u32
+}
+
+impl Serialize for RoundedF64 {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(round_half_even(self.value, self.decimal_places))
+    }
+}
+
+/// Serializes a value with every float in it rounded to a number of decimal
+/// places, or as it is if `decimal_places` is `None`
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::precision::Rounded;
+///
+/// let times = vec![0.1 + 0.2, 1.0 / 3.0];
+/// let json = serde_json::to_string(&Rounded::new(&times, Some(2))).unwrap();
+/// assert_eq!(json, "[0.3,0.33]");
+/// ```
+pub struct Rounded<'a, T: ?Sized> {
+    value: &'a T,
+    decimal_places: Option<u32>
+}
+
+impl<'a, T: ?Sized> Rounded<'a, T> {
+    pub fn new(value: &'a T, decimal_places: Option<u32>) -> Self {
+        Rounded { value, decimal_places }
+    }
+}
+
+impl<T: Serialize + ?Sized> Serialize for Rounded<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.decimal_places {
+            Some(decimal_places) => self.value.serialize(RoundingSerializer { inner: serializer, decimal_places }),
+            None => self.value.serialize(serializer)
+        }
+    }
+}
+
+/// Passes everything through to another serializer, rounding the floats on the way
+struct RoundingSerializer<S> {
+    inner: S,
+    decimal_places: u32
+}
+
+impl<S: Serializer> Serializer for RoundingSerializer<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+    type SerializeSeq = Compound<S::SerializeSeq>;
+    type SerializeTuple = Compound<S::SerializeTuple>;
+    type SerializeTupleStruct = Compound<S::SerializeTupleStruct>;
+    type SerializeTupleVariant = Compound<S::SerializeTupleVariant>;
+    type SerializeMap = Compound<S::SerializeMap>;
+    type SerializeStruct = Compound<S::SerializeStruct>;
+    type SerializeStructVariant = Compound<S::SerializeStructVariant>;
+
+    fn serialize_f32(self, value: f32) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_f32(round_half_even(value as f64, self.decimal_places) as f32)
+    }
+
+    fn serialize_f64(self, value: f64) -> Result<S::Ok, S::Error> {
+        RoundedF64 { value, decimal_places: self.decimal_places }.serialize(self.inner)
+    }
+
+    fn serialize_bool(self, value: bool) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_bool(value)
+    }
+
+    fn serialize_i8(self, value: i8) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_i8(value)
+    }
+
+    fn serialize_i16(self, value: i16) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_i16(value)
+    }
+
+    fn serialize_i32(self, value: i32) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_i32(value)
+    }
+
+    fn serialize_i64(self, value: i64) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_i64(value)
+    }
+
+    fn serialize_i128(self, value: i128) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_i128(value)
+    }
+
+    fn serialize_u8(self, value: u8) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_u8(value)
+    }
+
+    fn serialize_u16(self, value: u16) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_u16(value)
+    }
+
+    fn serialize_u32(self, value: u32) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_u32(value)
+    }
+
+    fn serialize_u64(self, value: u64) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_u64(value)
+    }
+
+    fn serialize_u128(self, value: u128) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_u128(value)
+    }
+
+    fn serialize_char(self, value: char) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_char(value)
+    }
+
+    fn serialize_str(self, value: &str) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_str(value)
+    }
+
+    fn serialize_bytes(self, value: &[u8]) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_bytes(value)
+    }
+
+    fn serialize_none(self) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_none()
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<S::Ok, S::Error> {
+        let decimal_places = Some(self.decimal_places);
+        self.inner.serialize_some(&Rounded { value, decimal_places })
+    }
+
+    fn serialize_unit(self) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_unit()
+    }
+
+    fn serialize_unit_struct(self, name: &'static str) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_unit_struct(name)
+    }
+
+    fn serialize_unit_variant(self, name: &'static str, index: u32, variant: &'static str) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_unit_variant(name, index, variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, name: &'static str, value: &T) -> Result<S::Ok, S::Error> {
+        let decimal_places = Some(self.decimal_places);
+        self.inner.serialize_newtype_struct(name, &Rounded { value, decimal_places })
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        value: &T
+    ) -> Result<S::Ok, S::Error> {
+        let decimal_places = Some(self.decimal_places);
+        self.inner.serialize_newtype_variant(name, index, variant, &Rounded { value, decimal_places })
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, S::Error> {
+        let decimal_places = self.decimal_places;
+        Ok(Compound { inner: self.inner.serialize_seq(len)?, decimal_places })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, S::Error> {
+        let decimal_places = self.decimal_places;
+        Ok(Compound { inner: self.inner.serialize_tuple(len)?, decimal_places })
+    }
+
+    fn serialize_tuple_struct(self, name: &'static str, len: usize) -> Result<Self::SerializeTupleStruct, S::Error> {
+        let decimal_places = self.decimal_places;
+        Ok(Compound { inner: self.inner.serialize_tuple_struct(name, len)?, decimal_places })
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        len: usize
+    ) -> Result<Self::SerializeTupleVariant, S::Error> {
+        let decimal_places = self.decimal_places;
+        Ok(Compound { inner: self.inner.serialize_tuple_variant(name, index, variant, len)?, decimal_places })
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, S::Error> {
+        let decimal_places = self.decimal_places;
+        Ok(Compound { inner: self.inner.serialize_map(len)?, decimal_places })
+    }
+
+    fn serialize_struct(self, name: &'static str, len: usize) -> Result<Self::SerializeStruct, S::Error> {
+        let decimal_places = self.decimal_places;
+        Ok(Compound { inner: self.inner.serialize_struct(name, len)?, decimal_places })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        len: usize
+    ) -> Result<Self::SerializeStructVariant, S::Error> {
+        let decimal_places = self.decimal_places;
+        Ok(Compound { inner: self.inner.serialize_struct_variant(name, index, variant, len)?, decimal_places })
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.inner.is_human_readable()
+    }
+}
+
+/// A sequence, map, or struct being serialized, with each of its values rounded in turn
+struct Compound<C> {
+    inner: C,
+    decimal_places: u32
+}
+
+impl<C> Compound<C> {
+    fn round<'a, T: ?Sized>(&self, value: &'a T) -> Rounded<'a, T> {
+        Rounded { value, decimal_places: Some(self.decimal_places) }
+    }
+}
+
+impl<C: ser::SerializeSeq> ser::SerializeSeq for Compound<C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), C::Error> {
+        let value = self.round(value);
+        self.inner.serialize_element(&value)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: ser::SerializeTuple> ser::SerializeTuple for Compound<C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), C::Error> {
+        let value = self.round(value);
+        self.inner.serialize_element(&value)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: ser::SerializeTupleStruct> ser::SerializeTupleStruct for Compound<C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), C::Error> {
+        let value = self.round(value);
+        self.inner.serialize_field(&value)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: ser::SerializeTupleVariant> ser::SerializeTupleVariant for Compound<C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), C::Error> {
+        let value = self.round(value);
+        self.inner.serialize_field(&value)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: ser::SerializeMap> ser::SerializeMap for Compound<C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), C::Error> {
+        let key = self.round(key);
+        self.inner.serialize_key(&key)
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), C::Error> {
+        let value = self.round(value);
+        self.inner.serialize_value(&value)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: ser::SerializeStruct> ser::SerializeStruct for Compound<C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), C::Error> {
+        let value = self.round(value);
+        self.inner.serialize_field(key, &value)
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> Result<(), C::Error> {
+        self.inner.skip_field(key)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: ser::SerializeStructVariant> ser::SerializeStructVariant for Compound<C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), C::Error> {
+        let value = self.round(value);
+        self.inner.serialize_field(key, &value)
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> Result<(), C::Error> {
+        self.inner.skip_field(key)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Note, NoteInfo};
+    use super::*;
+
+    fn round_json(note_info: &NoteInfo, decimal_places: u32) -> serde_json::Value {
+        let json = serde_json::to_string(&Rounded::new(note_info, Some(decimal_places))).unwrap();
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn test_round_half_even() {
+        assert_eq!(round_half_even(0.125, 2), 0.12);
+        assert_eq!(round_half_even(0.375, 2), 0.38);
+        assert_eq!(round_half_even(1.5, 0), 2.0);
+        assert_eq!(round_half_even(-0.5, 0), -0.0);
+        assert_eq!(round_half_even(f64::MAX, 2), f64::MAX);
+    }
+
+    #[test]
+    fn test_rounded_note_info() {
+        let note_info = NoteInfo {
+            notes: Some(vec![Note {
+                time_start: (0.1 + 0.2).into(),
+                time_end: (2.0 / 3.0).into(),
+                duration: Some((2.0 / 3.0 - 0.3).into()),
+                frequency_hz: Some(261.6255653005986),
+                pitch_value: 60,
+                velocity: 100,
+                ..Default::default()
+            }]),
+            ..Default::default()
+        };
+
+        let note = &round_json(&note_info, 2)["notes"][0];
+        assert_eq!(note["time_start"], 0.3);
+        assert_eq!(note["time_end"], 0.67);
+        assert_eq!(note["duration"], 0.37);
+        assert_eq!(note["frequency_hz"], 261.63);
+        assert_eq!(note["pitch_value"], 60);
+
+        let note = &round_json(&note_info, 4)["notes"][0];
+        assert_eq!(note["time_end"], 0.6667);
+        assert_eq!(note["frequency_hz"], 261.6256);
+
+        let note = &round_json(&note_info, 0)["notes"][0];
+        assert_eq!(note["time_start"], 0.0);
+        assert_eq!(note["time_end"], 1.0);
+        assert_eq!(note["frequency_hz"], 262.0);
+        assert_eq!(note["velocity"], 100);
+    }
+
+    #[test]
+    fn test_rounded_keeps_field_order() {
+        let note = Note { time_start: 0.25.into(), ..Default::default() };
+        let rounded = serde_json::to_string(&Rounded::new(&note, Some(1))).unwrap();
+        let full = serde_json::to_string(&note).unwrap();
+        assert_eq!(rounded.replace("0.2,", "0.25,"), full);
+    }
+}
@@ -0,0 +1,1264 @@
+//! Converts a whole MIDI file at once, the way the `midi2json` binary does
+
+use std::collections::HashSet;
+use std::io::{self, Write};
+use rayon::prelude::*;
+use midly::{
+    Event,
+    EventKind,
+    Format,
+    MidiMessage,
+    Smf,
+    Timing
+};
+
+use crate::{
+    InterOnsetIntervals,
+    LoggedEvent,
+    Midi2JsonError,
+    MeasureMap,
+    Note,
+    NoteCounter,
+    NoteInfo,
+    NoteOptions,
+    Result,
+    SortDirection,
+    SortKey,
+    SourceTrack,
+    TempoChange,
+    TimeUnit,
+    TrackNotes,
+    TransposeMode,
+    get_control_changes,
+    Rng,
+    get_density_timeline,
+    get_event_log,
+    get_inter_onset_intervals,
+    get_inter_onset_intervals_per_pitch,
+    get_key_signatures,
+    get_midi_header,
+    get_note_density,
+    get_pitch_entropy,
+    get_notes,
+    get_notes_counted,
+    get_pitch_bends,
+    get_pitch_histogram,
+    get_program_changes,
+    get_statistics,
+    get_tempo_map,
+    get_tempo_map_entries,
+    get_text_events,
+    get_time,
+    get_timecode_tempo_map,
+    get_time_signatures,
+    get_track_name,
+    group_chords,
+    merge_notes,
+    split_by_channel,
+    stream_notes,
+    transpose_note,
+    transpose_notes
+};
+use crate::time::{get_ticks_tempo_map, DEFAULT_MICROS_PER_BEAT};
+
+/// Everything that can be asked for when converting a file
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    /// The tempo for files without any tempo events
+    pub bpm: Option<f32>,
+    /// For files without any tempo events and no `bpm`, write each note's
+    /// times in ticks, as `time_start_ticks` and `time_end_ticks`, instead of
+    /// failing. Only the note times can be written this way.
+    pub no_bpm_required: bool,
+    pub note_options: NoteOptions,
+    /// Write every track's notes as a single `notes` array, or split them up
+    /// by channel for SMF Type 0 files
+    pub merge_tracks: bool,
+    /// Write the notes as `tracks` even for a file with only one track
+    pub keep_tracks: bool,
+    /// Only keep the notes that start at or after this time, if set
+    pub time_start: Option<f64>,
+    /// Only keep the notes that start before this time, if set, ending any
+    /// that are still held then
+    pub time_end: Option<f64>,
+    /// Read `time_start` and `time_end` as quarter-note beats instead of seconds
+    pub time_range_beats: bool,
+    /// Add the index of the track each note came from
+    pub annotate_source: bool,
+    /// Write how many notes have been collected to the log every this many
+    /// notes, and once they all have been, if set
+    pub progress_interval: Option<usize>,
+    /// Semitones to shift every pitch by
+    pub transpose: i32,
+    pub transpose_mode: TransposeMode,
+    pub normalize_start: bool,
+    /// Add each note's time since the previous note started
+    pub relative_time: bool,
+    /// Remove notes with the same pitch, channel, start, and end as an earlier one
+    pub deduplicate: bool,
+    /// Remove duplicates, and add how many there were to the statistics
+    pub deduplicate_report: bool,
+    /// Merge notes of the same pitch and channel that overlap or are at most
+    /// this far apart, if set
+    pub merge_legato: Option<f64>,
+    /// Split notes longer than this into equal segments at most this long, if set
+    pub split_long_notes: Option<f64>,
+    /// Remove notes longer than this, after splitting any long notes, if set
+    pub max_duration: Option<f64>,
+    /// Only keep the first this many notes to end, if set, which stops reading
+    /// each track once that many have
+    pub max_notes: Option<usize>,
+    /// Keep the last `max_notes` notes to end instead of the first
+    pub max_notes_from_end: bool,
+    /// Move each note's start and end by a random amount of at most half this
+    /// many seconds either way, if set
+    pub humanize: Option<f64>,
+    /// The seed for `humanize`, to move the notes the same way every time,
+    /// instead of seeding from the clock
+    pub seed: Option<u64>,
+    /// Tag each note as legato if the next note of its pitch starts less than
+    /// this long after it ends, or as staccato otherwise, if set
+    pub legato_classification: Option<f64>,
+    /// List the notes of the same pitch and channel that overlap
+    pub check_overlaps: bool,
+    /// Shorten overlapping notes so they no longer overlap, listing them too
+    pub resolve_overlaps: bool,
+    /// Group notes from every track that start within this window into chords, if set
+    pub chord_window: Option<f64>,
+    /// Sort the notes by this instead of by start time, if set
+    pub sort_by: Option<SortKey>,
+    /// The direction to sort in, instead of the sort key's default
+    pub sort_direction: Option<SortDirection>,
+    pub include_header: bool,
+    pub include_stats: bool,
+    /// Add the pitch classes' entropy to the statistics
+    pub include_entropy: bool,
+    pub include_tempo_map: bool,
+    pub include_time_signatures: bool,
+    pub include_key_signatures: bool,
+    pub include_text_events: bool,
+    pub annotate_measures: bool,
+    /// Count the notes that start in each measure
+    pub note_density: bool,
+    /// Count how many notes a second start in each window of this many
+    /// seconds, if set
+    pub note_density_window: Option<f64>,
+    /// Count the notes of each pitch
+    pub pitch_histogram: bool,
+    /// List the times between consecutive note starts
+    pub include_ioi: bool,
+    /// List the times between note starts separately for each pitch
+    pub ioi_per_pitch: bool,
+    pub include_controllers: bool,
+    /// Only these controllers are included, if set
+    pub controllers: Option<Vec<u8>>,
+    pub include_program_changes: bool,
+    pub gm_names: bool,
+    pub include_pitch_bend: bool,
+    /// Pitch bends are converted to semitones with this range, if set
+    pub pitch_bend_range: Option<f64>
+}
+
+/// Converts the bytes of a MIDI file into note information
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::{process_bytes, Options};
+///
+/// let bytes = include_bytes!("../input/sample.mid");
+/// let options = Options { bpm: Some(120.0), ..Default::default() };
+/// let note_info = process_bytes(bytes, &options).unwrap();
+/// assert!(note_info.all_notes().count() > 0);
+/// ```
+pub fn process_bytes(data: &[u8], options: &Options) -> Result<NoteInfo> {
+    process_bytes_with_log(data, options, &mut io::sink())
+}
+
+/// Same as `process_bytes`, but writes progress and warnings to `log`
+pub fn process_bytes_with_log(data: &[u8], options: &Options, log: &mut dyn Write) -> Result<NoteInfo> {
+    let smf: Smf<Vec<Event>> = Smf::read(data)?;
+    let (tempo_map, ticks_per_beat, note_options) = prepare(&smf, options, log)?;
+    let tempo_maps = get_track_tempo_maps(&smf, &tempo_map, options)?;
+    let mut counter = options.progress_interval.map(NoteCounter::new);
+    let mut track_notes = get_track_notes(&smf.tracks, &tempo_maps, ticks_per_beat, &note_options, options, counter.as_mut(), log)?;
+    if let Some(counter) = &counter {
+        counter.finish(log)?;
+    }
+    if let Some(max_notes) = options.max_notes {
+        limit_notes(&mut track_notes, max_notes, options.max_notes_from_end);
+    }
+
+    let mut note_info = if matches!(smf.header.format, Format::Sequential) {
+        if options.merge_tracks {
+            writeln!(log, "Warning: the patterns of an SMF Type 2 file each start from time 0, so they aren't merged")?;
+        }
+        NoteInfo {
+            patterns: Some(name_tracks(track_notes, &smf.tracks)),
+            ..Default::default()
+        }
+    } else if options.merge_tracks && matches!(smf.header.format, Format::SingleTrack) {
+        // Every channel shares the one track, so merging tracks would do nothing,
+        // and it's the channels that tell the parts apart
+        NoteInfo {
+            channels: Some(split_by_channel(merge_notes(track_notes))),
+            ..Default::default()
+        }
+    } else if options.merge_tracks || (track_notes.len() == 1 && !options.keep_tracks) {
+        NoteInfo {
+            notes: Some(merge_notes(track_notes)),
+            ..Default::default()
+        }
+    } else {
+        NoteInfo {
+            tracks: Some(name_tracks(track_notes, &smf.tracks)),
+            ..Default::default()
+        }
+    };
+
+    let duplicates_removed = clean_up_notes(&mut note_info, options);
+    if options.note_density {
+        let measure_map = note_options.measure_map.as_ref().unwrap();
+        let measures = note_info.all_notes().filter_map(|note| note.measure);
+        note_info.note_density = Some(get_note_density(measures, get_end_tick(&smf.tracks), measure_map, &tempo_map, ticks_per_beat));
+
+        // The measures were only worked out for the density
+        if !options.annotate_measures {
+            for note in note_info.all_notes_mut() {
+                note.measure = None;
+                note.beat = None;
+            }
+        }
+    }
+    finish_notes(&mut note_info, options, duplicates_removed);
+    if is_ticks_only(options, &note_options) {
+        note_info.move_times_to_ticks();
+    }
+
+    if options.include_header {
+        note_info.midi_header = Some(get_midi_header(&smf.header, smf.tracks.len()));
+    }
+    if options.include_tempo_map {
+        note_info.tempo_map = Some(get_tempo_map_entries(&tempo_map, ticks_per_beat));
+    }
+    if options.include_time_signatures {
+        note_info.time_signatures = Some(get_time_signatures(&smf.tracks, &tempo_map, ticks_per_beat));
+    }
+    if options.include_key_signatures {
+        note_info.key_signatures = Some(get_key_signatures(&smf.tracks, &tempo_map, ticks_per_beat));
+    }
+    if options.include_text_events {
+        note_info.text_events = Some(get_text_events(&smf.tracks, &tempo_map, ticks_per_beat));
+    }
+    if options.include_controllers {
+        note_info.control_changes = Some(get_control_changes(
+            &smf.tracks,
+            &tempo_map,
+            ticks_per_beat,
+            options.controllers.as_deref()
+        ));
+    }
+    if options.include_program_changes {
+        note_info.program_changes = Some(get_program_changes(&smf.tracks, &tempo_map, ticks_per_beat, options.gm_names));
+    }
+    if options.include_pitch_bend {
+        note_info.pitch_bends = Some(get_pitch_bends(&smf.tracks, &tempo_map, ticks_per_beat, options.pitch_bend_range));
+    }
+
+    Ok(note_info)
+}
+
+/// Converts several MIDI files at once, merging the notes of every track of
+/// every file into a single `notes` array in order of start time. Each note's
+/// `source_file` is the name its file was given with.
+///
+/// Only the notes are merged, so the options that describe the rest of a
+/// file, like `include_tempo_map` and `note_density`, aren't supported.
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::{process_files, Options};
+///
+/// let sample = include_bytes!("../input/sample.mid");
+/// let demo = include_bytes!("../input/demo-116bpm.mid");
+/// let options = Options { bpm: Some(120.0), ..Default::default() };
+/// let files: [(&str, &[u8]); 2] = [("sample.mid", sample), ("demo.mid", demo)];
+/// let note_info = process_files(&files, &options).unwrap();
+/// assert_eq!(note_info.notes.unwrap().len(), 19);
+/// ```
+pub fn process_files(files: &[(&str, &[u8])], options: &Options) -> Result<NoteInfo> {
+    process_files_with_log(files, options, &mut io::sink())
+}
+
+/// Same as `process_files`, but writes progress and warnings to `log`
+pub fn process_files_with_log(files: &[(&str, &[u8])], options: &Options, log: &mut dyn Write) -> Result<NoteInfo> {
+    if let Some(option) = get_file_option(options) {
+        return Err(Midi2JsonError::InvalidArgument(format!("{} can't be used when merging several files", option)));
+    }
+
+    let mut notes = Vec::new();
+    let mut counter = options.progress_interval.map(NoteCounter::new);
+    for (file_index, (name, data)) in files.iter().enumerate() {
+        writeln!(log, "Loading {}...", name)?;
+        let smf: Smf<Vec<Event>> = Smf::read(data)?;
+        let (tempo_map, ticks_per_beat, note_options) = prepare(&smf, options, log)?;
+        let tempo_maps = get_track_tempo_maps(&smf, &tempo_map, options)?;
+        let track_notes = get_track_notes(&smf.tracks, &tempo_maps, ticks_per_beat, &note_options, options, counter.as_mut(), log)?;
+        for (track_index, track) in track_notes.into_iter().enumerate() {
+            notes.extend(track.into_iter().map(|note| Note {
+                source_file: Some(name.to_string()),
+                source_track: note.source_track.map(|_| SourceTrack::in_file(file_index, track_index)),
+                ..note
+            }));
+        }
+    }
+
+    if let Some(counter) = &counter {
+        counter.finish(log)?;
+    }
+    let mut notes = vec![notes];
+    if let Some(max_notes) = options.max_notes {
+        limit_notes(&mut notes, max_notes, options.max_notes_from_end);
+    }
+
+    // The sort is stable, so notes that start together stay in file order
+    let mut note_info = NoteInfo {
+        notes: Some(merge_notes(notes)),
+        ..Default::default()
+    };
+    let duplicates_removed = clean_up_notes(&mut note_info, options);
+    finish_notes(&mut note_info, options, duplicates_removed);
+
+    Ok(note_info)
+}
+
+/// Converts the bytes of a MIDI file, passing each note to `on_note` as soon as
+/// it ends rather than collecting them. Only the notes are produced, so the
+/// `include_*` options and the options that need every note at once, like
+/// `normalize_start`, don't apply.
+pub fn stream_bytes<F>(data: &[u8], options: &Options, log: &mut dyn Write, mut on_note: F) -> Result<()>
+where
+    F: FnMut(Note) -> Result<()>
+{
+    let smf: Smf<Vec<Event>> = Smf::read(data)?;
+    let (tempo_map, ticks_per_beat, note_options) = prepare(&smf, options, log)?;
+    let tempo_maps = get_track_tempo_maps(&smf, &tempo_map, options)?;
+
+    writeln!(log, "Handling contents...")?;
+    let mut out_of_range_count = 0;
+    let mut counter = options.progress_interval.map(NoteCounter::new);
+    // Each track stops at the maximum by itself, but it's for the whole file
+    let mut note_count = 0;
+    let max_notes = note_options.max_notes;
+    let ticks_only = is_ticks_only(options, &note_options);
+    for (index, (track, tempo_map)) in smf.tracks.iter().zip(&tempo_maps).enumerate() {
+        stream_notes(track, tempo_map, ticks_per_beat, &note_options, |_, mut note| {
+            if max_notes.is_some_and(|max_notes| note_count >= max_notes) {
+                return Ok(());
+            }
+            note_count += 1;
+            if let Some(counter) = counter.as_mut() {
+                counter.add(log)?;
+            }
+            if options.annotate_source {
+                note.source_track = Some(SourceTrack::Index(index));
+            }
+            if options.transpose != 0 && !transpose_note(&mut note, options.transpose, &note_options) {
+                out_of_range_count += 1;
+                if options.transpose_mode == TransposeMode::Discard {
+                    return Ok(());
+                }
+            }
+            if ticks_only {
+                note.move_times_to_ticks();
+            }
+
+            on_note(note)
+        })?;
+    }
+    if let Some(counter) = &counter {
+        counter.finish(log)?;
+    }
+
+    log_transpose_warning(log, out_of_range_count, options.transpose_mode)
+}
+
+/// Finds when each measure starts, in the unit `process_bytes` would write the
+/// note times in, for drawing the measures alongside the notes. Files without
+/// time signatures, or with SMPTE timecode timing, have no measures to draw.
+///
+/// The times aren't shifted by `normalize_start`.
+pub fn get_measure_starts(data: &[u8], options: &Options) -> Result<Vec<f64>> {
+    let smf: Smf<Vec<Event>> = Smf::read(data)?;
+    let ticks_per_beat = match smf.header.timing {
+        Timing::Metrical(ticks) => ticks.as_int(),
+        Timing::Timecode(..) => return Ok(Vec::new())
+    };
+    let time_signatures = get_time_signatures(&smf.tracks, &[], ticks_per_beat);
+    if time_signatures.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let time_unit = options.note_options.time_unit;
+    let tempo_map = if time_unit == TimeUnit::Seconds {
+        get_tempo_map(&smf.tracks, options.bpm)?
+    } else {
+        Vec::new()
+    };
+    let measure_map = MeasureMap::new(&time_signatures, ticks_per_beat);
+    let (measure_count, _) = measure_map.get_position(get_end_tick(&smf.tracks).saturating_sub(1));
+
+    Ok((1..=measure_count)
+        .map(|measure| get_time(measure_map.get_measure_start(measure), time_unit, &tempo_map, ticks_per_beat).as_f64())
+        .collect())
+}
+
+/// Lists every event in the file, of any kind, as `get_event_log` does. This
+/// needs no tempo, so it works for files `process_bytes` can't convert.
+pub fn read_event_log(data: &[u8]) -> Result<Vec<LoggedEvent>> {
+    let smf: Smf<Vec<Event>> = Smf::read(data)?;
+    Ok(get_event_log(&smf.tracks))
+}
+
+/// The tick the longest track ends on
+fn get_end_tick(tracks: &[Vec<Event>]) -> u32 {
+    tracks.iter()
+        .map(|track| track.iter().map(|event| event.delta.as_int()).sum())
+        .max()
+        .unwrap_or(0)
+}
+
+/// Works out the tempo map, ticks per beat, and note options for a file
+fn prepare<'a>(smf: &Smf<'a, Vec<Event<'a>>>, options: &Options, log: &mut dyn Write) -> Result<(Vec<TempoChange>, u16, NoteOptions)> {
+    // SMPTE timecode files count time in frames rather than beats, so their
+    // tempo events and the BPM don't apply
+    let has_time_range = options.time_start.is_some() || options.time_end.is_some();
+    let (timecode_tempo_map, ticks_per_beat) = match smf.header.timing {
+        // Every time would be divided by 0
+        Timing::Metrical(ticks) if ticks.as_int() == 0 => {
+            return Err(Midi2JsonError::ParseError("the header gives 0 ticks per beat".to_string()));
+        },
+        Timing::Metrical(ticks) => (None, ticks.as_int()),
+        Timing::Timecode(_, 0) => {
+            return Err(Midi2JsonError::ParseError("the header gives 0 ticks per frame".to_string()));
+        },
+        Timing::Timecode(fps, ticks_per_frame) => {
+            if matches!(options.note_options.time_unit, TimeUnit::Beats(_))
+                || options.note_options.swing.is_some()
+                || options.annotate_measures
+                || options.note_density
+                || (has_time_range && options.time_range_beats) {
+                return Err(Midi2JsonError::Unsupported(
+                    "beats and measures with SMPTE timecode timing".to_string()
+                ));
+            }
+            if options.bpm.is_some() {
+                writeln!(log, "Warning: --bpm is ignored for files with SMPTE timecode timing")?;
+            }
+            let (tempo_map, ticks_per_beat) = get_timecode_tempo_map(fps, ticks_per_frame);
+            (Some(tempo_map), ticks_per_beat)
+        }
+    };
+
+    // Only General MIDI files are sure to keep their drums on the drum channel,
+    // and they set up their instruments with program changes
+    if options.note_options.exclude_drums && !has_program_changes(&smf.tracks) {
+        writeln!(log, "Warning: the file has no program changes, so it may not be General MIDI and channel 10 may not be drums")?;
+    }
+
+    // Ticks and beats don't depend on the tempo, so there's no need to require one
+    // unless something else is written in seconds
+    let notes_need_tempo = options.note_options.time_unit == TimeUnit::Seconds;
+    let others_need_tempo = options.include_tempo_map
+        || options.include_time_signatures
+        || options.include_key_signatures
+        || options.include_text_events
+        || options.include_controllers
+        || options.include_program_changes
+        || options.include_pitch_bend
+        || options.note_density
+        || (has_time_range && !options.time_range_beats);
+    let mut note_options = options.note_options.clone();
+    let tempo_map = if let Some(tempo_map) = timecode_tempo_map {
+        tempo_map
+    } else if notes_need_tempo || others_need_tempo {
+        match get_tempo_map(&smf.tracks, options.bpm) {
+            Err(Midi2JsonError::MissingTempo) if options.no_bpm_required && !others_need_tempo => {
+                writeln!(log, "Warning: the file has no tempo events and no BPM was given, so only the note times in ticks are written")?;
+                note_options.time_unit = TimeUnit::Ticks;
+                Vec::new()
+            },
+            result => result?
+        }
+    } else {
+        Vec::new()
+    };
+
+    // Only the first notes can be found without reading every track to the end
+    if !options.max_notes_from_end {
+        note_options.max_notes = options.max_notes;
+    }
+    // Measures only depend on the ticks, so the time signatures' times are irrelevant here
+    if options.annotate_measures || options.note_density {
+        let time_signatures = get_time_signatures(&smf.tracks, &[], ticks_per_beat);
+        note_options.measure_map = Some(MeasureMap::new(&time_signatures, ticks_per_beat));
+    }
+    if has_time_range {
+        let to_ticks = |time: f64| if options.time_range_beats {
+            (time * ticks_per_beat as f64).round() as u32
+        } else {
+            get_ticks_tempo_map(time, &tempo_map, ticks_per_beat)
+        };
+        note_options.time_range = Some((
+            options.time_start.map_or(0, to_ticks),
+            options.time_end.map_or(u32::MAX, to_ticks)
+        ));
+    }
+
+    Ok((tempo_map, ticks_per_beat, note_options))
+}
+
+/// Converts the notes of each of a file's tracks, in track order, counting
+/// them with `counter` if given
+fn get_track_notes(
+    tracks: &[Vec<Event>],
+    tempo_maps: &[Vec<TempoChange>],
+    ticks_per_beat: u16,
+    note_options: &NoteOptions,
+    options: &Options,
+    counter: Option<&mut NoteCounter>,
+    log: &mut dyn Write
+) -> Result<Vec<Vec<Note>>> {
+    writeln!(log, "Handling contents...")?;
+    let mut track_notes = match counter {
+        // The progress goes to the one log, so the tracks are converted one at a time
+        Some(counter) => tracks.iter()
+            .zip(tempo_maps)
+            .map(|(track, tempo_map)| get_notes_counted(track, tempo_map, ticks_per_beat, note_options, counter, log))
+            .collect::<Result<Vec<Vec<Note>>>>()?,
+        // The tempo maps are complete by now, and they're all each track's notes
+        // depend on, so the tracks can be converted in parallel. The results stay in track order.
+        None => tracks.par_iter()
+            .zip(tempo_maps)
+            .map(|(track, tempo_map)| get_notes(track, tempo_map, ticks_per_beat, note_options))
+            .collect::<Result<Vec<Vec<Note>>>>()?
+    };
+
+    if options.annotate_source {
+        for (index, notes) in track_notes.iter_mut().enumerate() {
+            for note in notes {
+                note.source_track = Some(SourceTrack::Index(index));
+            }
+        }
+    }
+    if options.transpose != 0 {
+        let out_of_range_count: usize = track_notes.iter_mut()
+            .map(|notes| transpose_notes(notes, options.transpose, options.transpose_mode, note_options))
+            .sum();
+        log_transpose_warning(log, out_of_range_count, options.transpose_mode)?;
+    }
+
+    Ok(track_notes)
+}
+
+/// Keeps the first `max_notes` notes to end across all the tracks, or the last
+/// if `from_end` is set. Notes that end together are taken in track order.
+fn limit_notes(track_notes: &mut [Vec<Note>], max_notes: usize, from_end: bool) {
+    let mut ends: Vec<(f64, usize, usize)> = track_notes.iter()
+        .enumerate()
+        .flat_map(|(track, notes)| notes.iter().enumerate().map(move |(position, note)| (note.time_end.as_f64(), track, position)))
+        .collect();
+    if ends.len() <= max_notes {
+        return;
+    }
+
+    ends.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let kept: HashSet<(usize, usize)> = if from_end {
+        ends[ends.len() - max_notes..].iter().map(|&(_, track, position)| (track, position)).collect()
+    } else {
+        ends[..max_notes].iter().map(|&(_, track, position)| (track, position)).collect()
+    };
+    for (track, notes) in track_notes.iter_mut().enumerate() {
+        let mut position = 0;
+        notes.retain(|_| {
+            position += 1;
+            kept.contains(&(track, position - 1))
+        });
+    }
+}
+
+/// The tempo map each track's notes are timed with. The patterns of an SMF
+/// Type 2 file each have their own timeline, so only a pattern's own tempo
+/// events apply to it; every other file's tracks share the file's tempo map.
+fn get_track_tempo_maps<'a>(smf: &Smf<'a, Vec<Event<'a>>>, tempo_map: &[TempoChange], options: &Options) -> Result<Vec<Vec<TempoChange>>> {
+    // Timecode files and files written in ticks or beats have nothing to separate
+    let is_metrical = matches!(smf.header.timing, Timing::Metrical(_));
+    if !matches!(smf.header.format, Format::Sequential) || !is_metrical || tempo_map.is_empty() {
+        return Ok(vec![tempo_map.to_vec(); smf.tracks.len()]);
+    }
+
+    smf.tracks.iter()
+        .map(|track| match get_tempo_map(std::slice::from_ref(track), options.bpm) {
+            // Another pattern has tempo events, just not this one
+            Err(Midi2JsonError::MissingTempo) => Ok(vec![TempoChange { tick: 0, micros_per_beat: DEFAULT_MICROS_PER_BEAT }]),
+            result => result
+        })
+        .collect()
+}
+
+/// Pairs each track's notes with its index and name
+fn name_tracks(track_notes: Vec<Vec<Note>>, tracks: &[Vec<Event>]) -> Vec<TrackNotes> {
+    track_notes.into_iter()
+        .zip(tracks)
+        .enumerate()
+        .map(|(index, (notes, track))| TrackNotes {
+            index,
+            name: get_track_name(track),
+            notes
+        })
+        .collect()
+}
+
+/// Removes duplicates, merges legato notes, splits or removes long notes, and
+/// humanizes the timing, as asked for, returning the number of duplicates removed
+fn clean_up_notes(note_info: &mut NoteInfo, options: &Options) -> usize {
+    let duplicates_removed = if options.deduplicate || options.deduplicate_report {
+        note_info.remove_duplicates()
+    } else {
+        0
+    };
+    if let Some(legato_gap) = options.merge_legato {
+        note_info.merge_legato(legato_gap);
+    }
+    if let Some(max_duration) = options.split_long_notes {
+        note_info.split_long_notes(max_duration);
+    }
+    // Splitting first means only the segments that are still too long are removed
+    if let Some(max_duration) = options.max_duration {
+        note_info.remove_long_notes(max_duration);
+    }
+    if let Some(max_offset) = options.humanize {
+        let mut rng = options.seed.map(Rng::new).unwrap_or_else(Rng::from_clock);
+        note_info.humanize(max_offset, &mut rng);
+    }
+
+    duplicates_removed
+}
+
+/// The rest of the changes to the notes, and the other fields that only
+/// depend on them
+fn finish_notes(note_info: &mut NoteInfo, options: &Options, duplicates_removed: usize) {
+    if options.normalize_start {
+        note_info.normalize_start();
+    }
+    if options.check_overlaps || options.resolve_overlaps {
+        note_info.check_overlaps(options.resolve_overlaps);
+    }
+    if let Some(threshold) = options.legato_classification {
+        note_info.classify_articulation(threshold);
+    }
+    if options.relative_time {
+        note_info.set_relative_times();
+    }
+    if let Some(chord_window) = options.chord_window {
+        note_info.chords = Some(group_chords(note_info.all_notes(), chord_window));
+    }
+    if let Some(sort_key) = options.sort_by {
+        note_info.sort_notes(sort_key, options.sort_direction);
+    }
+
+    if options.include_stats || options.deduplicate_report || options.include_entropy {
+        let mut statistics = get_statistics(note_info.all_notes());
+        if options.deduplicate_report {
+            statistics.duplicates_removed = Some(duplicates_removed as u32);
+        }
+        if options.include_entropy {
+            statistics.pitch_entropy = Some(get_pitch_entropy(&statistics.pitch_histogram));
+        }
+        note_info.statistics = Some(statistics);
+    }
+    if let Some(window) = options.note_density_window {
+        note_info.density_timeline = Some(get_density_timeline(note_info.all_notes(), window));
+    }
+    if options.pitch_histogram {
+        note_info.pitch_histogram = Some(get_pitch_histogram(note_info.all_notes()));
+    }
+    if options.ioi_per_pitch {
+        note_info.inter_onset_intervals = Some(InterOnsetIntervals::PerPitch(get_inter_onset_intervals_per_pitch(note_info.all_notes())));
+    } else if options.include_ioi {
+        note_info.inter_onset_intervals = Some(InterOnsetIntervals::All(get_inter_onset_intervals(note_info.all_notes())));
+    }
+}
+
+/// The first option that's set which describes a whole file rather than its
+/// notes, so can't be used when merging files
+fn get_file_option(options: &Options) -> Option<&'static str> {
+    [
+        (options.include_header, "include_header"),
+        (options.include_tempo_map, "include_tempo_map"),
+        (options.include_time_signatures, "include_time_signatures"),
+        (options.include_key_signatures, "include_key_signatures"),
+        (options.include_text_events, "include_text_events"),
+        (options.include_controllers, "include_controllers"),
+        (options.include_program_changes, "include_program_changes"),
+        (options.include_pitch_bend, "include_pitch_bend"),
+        (options.note_density, "note_density"),
+        // Files with and without tempo events would have their notes' times in different units
+        (options.no_bpm_required, "no_bpm_required")
+    ].iter()
+        .find(|(is_set, _)| *is_set)
+        .map(|(_, option)| *option)
+}
+
+/// Whether `prepare` found no tempo, and fell back to converting the notes in ticks
+fn is_ticks_only(options: &Options, note_options: &NoteOptions) -> bool {
+    note_options.time_unit != options.note_options.time_unit
+}
+
+fn has_program_changes(tracks: &[Vec<Event>]) -> bool {
+    tracks.iter()
+        .flatten()
+        .any(|event| matches!(event.kind, EventKind::Midi { message: MidiMessage::ProgramChange(_), .. }))
+}
+
+fn log_transpose_warning(log: &mut dyn Write, out_of_range_count: usize, transpose_mode: TransposeMode) -> Result<()> {
+    if out_of_range_count > 0 {
+        let action = match transpose_mode {
+            TransposeMode::Clamp => "clamped",
+            TransposeMode::Discard => "discarded"
+        };
+        writeln!(log, "Warning: {} notes were transposed outside 0-127 and {}", out_of_range_count, action)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{parse_note_info, TimeValue, TruncatedNotes, QUARTER_NOTE};
+    use super::*;
+
+    const SAMPLE: &[u8] = include_bytes!("../input/sample.mid");
+
+    #[test]
+    fn test_process_bytes_matches_get_notes() {
+        let smf: Smf<Vec<Event>> = Smf::read(SAMPLE).unwrap();
+        let ticks_per_beat = match smf.header.timing {
+            Timing::Metrical(ticks) => ticks.as_int(),
+            _ => panic!("Expected metrical timing")
+        };
+        let tempo_map = get_tempo_map(&smf.tracks, Some(120.0)).unwrap();
+        let expected: Vec<Note> = smf.tracks.iter()
+            .map(|track| get_notes(track, &tempo_map, ticks_per_beat, &NoteOptions::default()).unwrap())
+            .collect::<Vec<Vec<Note>>>()
+            .concat();
+
+        // The sample only has one track, so its notes are already merged
+        let options = Options {
+            bpm: Some(120.0),
+            ..Default::default()
+        };
+        let notes = process_bytes(SAMPLE, &options).unwrap().notes.unwrap();
+        assert_eq!(notes.len(), expected.len());
+        for note in &expected {
+            assert!(notes.iter().any(|other| other.time_start == note.time_start && other.pitch_value == note.pitch_value));
+        }
+    }
+
+    #[test]
+    fn test_process_bytes_includes() {
+        let options = Options {
+            bpm: Some(120.0),
+            include_header: true,
+            include_stats: true,
+            include_tempo_map: true,
+            ..Default::default()
+        };
+        let note_info = process_bytes(SAMPLE, &options).unwrap();
+        assert!(note_info.midi_header.is_some());
+        assert!(note_info.tempo_map.is_some());
+        assert_eq!(
+            note_info.statistics.as_ref().unwrap().total_notes as usize,
+            note_info.all_notes().count()
+        );
+        assert!(note_info.time_signatures.is_none());
+        assert!(note_info.statistics.as_ref().unwrap().pitch_entropy.is_none());
+
+        let options = Options {
+            bpm: Some(120.0),
+            include_entropy: true,
+            ..Default::default()
+        };
+        let statistics = process_bytes(SAMPLE, &options).unwrap().statistics.unwrap();
+        assert_eq!(statistics.pitch_entropy, Some(get_pitch_entropy(&statistics.pitch_histogram)));
+    }
+
+    #[test]
+    fn test_parse_note_info_round_trip() {
+        let demo = include_bytes!("../input/demo-116bpm.mid");
+        let options = Options {
+            bpm: Some(116.0),
+            note_options: NoteOptions {
+                pitch_names: true,
+                velocity_normalized: true,
+                ..Default::default()
+            },
+            keep_tracks: true,
+            annotate_source: true,
+            relative_time: true,
+            legato_classification: Some(0.05),
+            check_overlaps: true,
+            chord_window: Some(0.03),
+            include_header: true,
+            include_stats: true,
+            include_entropy: true,
+            include_tempo_map: true,
+            include_time_signatures: true,
+            include_key_signatures: true,
+            include_text_events: true,
+            include_controllers: true,
+            include_program_changes: true,
+            include_pitch_bend: true,
+            note_density: true,
+            note_density_window: Some(1.0),
+            pitch_histogram: true,
+            ioi_per_pitch: true,
+            ..Default::default()
+        };
+        let note_info = process_bytes(demo, &options).unwrap();
+        let json = serde_json::to_string(&note_info).unwrap();
+        let parsed = parse_note_info(&json).unwrap();
+        assert_eq!(parsed, note_info);
+        assert_eq!(parsed.tracks.as_ref().unwrap()[0].notes.len(), 15);
+
+        // Written the way JavaScript would, with whole seconds as integers
+        fn without_trailing_zeros(value: &mut serde_json::Value) {
+            match value {
+                serde_json::Value::Number(number) if number.as_f64().is_some_and(|number| number.fract() == 0.0) => {
+                    *value = (number.as_f64().unwrap() as i64).into();
+                },
+                serde_json::Value::Array(values) => values.iter_mut().for_each(without_trailing_zeros),
+                serde_json::Value::Object(values) => values.values_mut().for_each(without_trailing_zeros),
+                _ => {}
+            }
+        }
+        let mut value = serde_json::to_value(&note_info).unwrap();
+        without_trailing_zeros(&mut value);
+        assert_eq!(parse_note_info(&value.to_string()).unwrap(), note_info);
+
+        // One time with a fraction makes them all seconds
+        let mut parsed = parse_note_info(r#"{"notes": [
+            {"time_start": 1, "time_end": 1.5, "duration": 0.5, "pitch_value": 60},
+            {"time_start": 2, "time_end": 3, "duration": 1, "pitch_value": 62}
+        ]}"#).unwrap();
+        parsed.normalize_start();
+        let times: Vec<(TimeValue, TimeValue)> = parsed.all_notes().map(|note| (note.time_start, note.time_end)).collect();
+        assert_eq!(times, vec![(TimeValue::Seconds(0.0), TimeValue::Seconds(0.5)), (TimeValue::Seconds(1.0), TimeValue::Seconds(2.0))]);
+
+        // Ticks are written as whole numbers, so they're read back as ticks
+        let options = Options {
+            note_options: NoteOptions { time_unit: TimeUnit::Ticks, ..Default::default() },
+            ..Default::default()
+        };
+        let note_info = process_bytes(demo, &options).unwrap();
+        assert_eq!(parse_note_info(&serde_json::to_string(&note_info).unwrap()).unwrap(), note_info);
+
+        // Times left out without a tempo are still unknown
+        let options = Options { no_bpm_required: true, ..Default::default() };
+        let note_info = process_bytes(demo, &options).unwrap();
+        let parsed = parse_note_info(&serde_json::to_string(&note_info).unwrap()).unwrap();
+        assert_eq!(parsed, note_info);
+        assert!(parsed.all_notes().all(|note| note.time_start.is_unknown() && note.time_start_ticks.is_some()));
+
+        assert!(matches!(parse_note_info("{\"notes\": 3}"), Err(Midi2JsonError::JsonError(_))));
+    }
+
+    #[test]
+    fn test_stream_bytes_matches_process_bytes() {
+        let options = Options {
+            bpm: Some(120.0),
+            ..Default::default()
+        };
+        let mut streamed = 0;
+        stream_bytes(SAMPLE, &options, &mut io::sink(), |_| {
+            streamed += 1;
+            Ok(())
+        }).unwrap();
+
+        let note_info = process_bytes(SAMPLE, &options).unwrap();
+        assert_eq!(streamed, note_info.all_notes().count());
+    }
+
+    /// A Type 0 file at 96 ticks per beat, with one track of `(delta, message)` events
+    fn type_0_file(events: &[(u32, [u8; 3])]) -> Vec<u8> {
+        let mut bytes = b"MThd\x00\x00\x00\x06\x00\x00\x00\x01\x00\x60".to_vec();
+        bytes.extend(track_chunk(events));
+        bytes
+    }
+
+    /// A Type 1 file at 96 ticks per beat, with a track for each list of events
+    fn type_1_file(tracks: &[&[(u32, [u8; 3])]]) -> Vec<u8> {
+        let mut bytes = b"MThd\x00\x00\x00\x06\x00\x01".to_vec();
+        bytes.extend_from_slice(&(tracks.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(&96u16.to_be_bytes());
+        for events in tracks {
+            bytes.extend(track_chunk(events));
+        }
+        bytes
+    }
+
+    /// A Type 2 file at 96 ticks per beat, with a pattern for each list of events
+    fn type_2_file(patterns: &[&[(u32, [u8; 3])]]) -> Vec<u8> {
+        let mut bytes = type_1_file(patterns);
+        bytes[9] = 2;
+        bytes
+    }
+
+    /// An `MTrk` chunk of `(delta, message)` events, ending with an end of track event
+    fn track_chunk(events: &[(u32, [u8; 3])]) -> Vec<u8> {
+        let mut track: Vec<u8> = Vec::new();
+        for (delta, message) in events {
+            // Variable-length, seven bits at a time with the high bit set on all but the last
+            let mut groups = vec![(delta & 0x7f) as u8];
+            let mut rest = delta >> 7;
+            while rest > 0 {
+                groups.push((rest & 0x7f) as u8 | 0x80);
+                rest >>= 7;
+            }
+            track.extend(groups.iter().rev());
+            track.extend_from_slice(message);
+        }
+        track.extend_from_slice(&[0x00, 0xff, 0x2f, 0x00]);
+
+        let mut chunk = b"MTrk".to_vec();
+        chunk.extend_from_slice(&(track.len() as u32).to_be_bytes());
+        chunk.extend(track);
+        chunk
+    }
+
+    #[test]
+    fn test_process_bytes_pitch_histogram() {
+        let bytes = type_0_file(&[
+            (0, [0x90, 60, 80]),
+            (48, [0x80, 60, 0]),
+            (0, [0x90, 62, 80]),
+            (0, [0x91, 60, 80]),
+            (48, [0x80, 62, 0]),
+            (0, [0x81, 60, 0]),
+            (0, [0x90, 60, 80]),
+            (48, [0x80, 60, 0])
+        ]);
+        let options = Options {
+            bpm: Some(120.0),
+            pitch_histogram: true,
+            ..Default::default()
+        };
+        let histogram = process_bytes(&bytes, &options).unwrap().pitch_histogram.unwrap();
+        assert_eq!(histogram.into_iter().collect::<Vec<_>>(), vec![("60".to_string(), 3), ("62".to_string(), 1)]);
+
+        let options = Options {
+            note_options: NoteOptions { pitch_names: true, ..Default::default() },
+            ..options
+        };
+        let histogram = process_bytes(&bytes, &options).unwrap().pitch_histogram.unwrap();
+        assert_eq!(histogram.into_iter().collect::<Vec<_>>(), vec![("C4".to_string(), 3), ("D4".to_string(), 1)]);
+    }
+
+    #[test]
+    fn test_process_bytes_max_duration() {
+        // Half a second is 48 ticks at 60 BPM
+        let bytes = type_0_file(&[
+            (0, [0x90, 60, 80]),
+            (48, [0x80, 60, 0]),
+            (0, [0x90, 62, 80]),
+            (49, [0x80, 62, 0]),
+            // Never ends, so it lasts until the end of the track
+            (0, [0x90, 64, 80]),
+            (0, [0x90, 65, 80]),
+            (24, [0x80, 65, 0]),
+            (96, [0xb0, 1, 0])
+        ]);
+        let options = Options {
+            bpm: Some(60.0),
+            note_options: NoteOptions { truncated_notes: TruncatedNotes::Include, ..Default::default() },
+            max_duration: Some(0.5),
+            ..Default::default()
+        };
+        let pitches: Vec<u32> = process_bytes(&bytes, &options).unwrap().all_notes()
+            .map(|note| note.pitch_value)
+            .collect();
+        assert_eq!(pitches, vec![60, 65]);
+
+        // Split up first, every segment is short enough to keep
+        let options = Options {
+            split_long_notes: Some(0.5),
+            ..options
+        };
+        let pitches: Vec<u32> = process_bytes(&bytes, &options).unwrap().all_notes()
+            .map(|note| note.pitch_value)
+            .collect();
+        assert_eq!(pitches, vec![60, 62, 62, 64, 65, 64, 64]);
+    }
+
+    #[test]
+    fn test_process_annotate_source() {
+        let bytes = type_1_file(&[
+            &[(0, [0x90, 60, 80]), (96, [0x80, 60, 0])],
+            &[(48, [0x91, 48, 70]), (96, [0x81, 48, 0])]
+        ]);
+        let options = Options {
+            bpm: Some(120.0),
+            merge_tracks: true,
+            ..Default::default()
+        };
+        let note_info = process_bytes(&bytes, &options).unwrap();
+        assert!(note_info.all_notes().all(|note| note.source_track.is_none()));
+
+        let options = Options {
+            annotate_source: true,
+            ..options
+        };
+        let note_info = process_bytes(&bytes, &options).unwrap();
+        let sources: Vec<(u32, SourceTrack)> = note_info.all_notes()
+            .map(|note| (note.pitch_value, note.source_track.clone().unwrap()))
+            .collect();
+        assert_eq!(sources, vec![(60, SourceTrack::Index(0)), (48, SourceTrack::Index(1))]);
+        let json = serde_json::to_value(note_info.notes.unwrap()).unwrap();
+        assert_eq!(json[1]["source_track"], 1);
+
+        // Merged files say which file the track was in too
+        let files: [(&str, &[u8]); 2] = [("first.mid", &bytes), ("second.mid", &bytes)];
+        let note_info = process_files(&files, &options).unwrap();
+        let sources: Vec<String> = note_info.all_notes()
+            .map(|note| match note.source_track.clone().unwrap() {
+                SourceTrack::File(source) => source,
+                SourceTrack::Index(_) => panic!("Expected a file and track")
+            })
+            .collect();
+        assert_eq!(sources, ["0:0", "1:0", "0:1", "1:1"]);
+    }
+
+    #[test]
+    fn test_process_bytes_max_notes() {
+        // Notes end at 0.5, 1.0, and 1.5 seconds in the first track, and at 0.75
+        // and 1.25 in the second
+        let bytes = type_1_file(&[
+            &[(0, [0x90, 60, 80]), (48, [0x80, 60, 0]), (0, [0x90, 62, 80]), (48, [0x80, 62, 0]), (0, [0x90, 64, 80]), (48, [0x80, 64, 0])],
+            &[(0, [0x91, 48, 70]), (72, [0x81, 48, 0]), (0, [0x91, 50, 70]), (48, [0x81, 50, 0])]
+        ]);
+        let options = Options {
+            bpm: Some(60.0),
+            merge_tracks: true,
+            max_notes: Some(3),
+            ..Default::default()
+        };
+        let pitches = |options: &Options| -> Vec<u32> {
+            process_bytes(&bytes, options).unwrap().all_notes().map(|note| note.pitch_value).collect()
+        };
+        assert_eq!(pitches(&options), vec![60, 48, 62]);
+
+        let options = Options {
+            max_notes_from_end: true,
+            ..options
+        };
+        assert_eq!(pitches(&options), vec![62, 50, 64]);
+    }
+
+    #[test]
+    fn test_process_bytes_progress() {
+        let bytes = type_1_file(&[
+            &[(0, [0x90, 60, 80]), (96, [0x80, 60, 0]), (0, [0x90, 62, 80]), (96, [0x80, 62, 0])],
+            &[(0, [0x91, 48, 70]), (192, [0x81, 48, 0])]
+        ]);
+        let options = Options {
+            bpm: Some(120.0),
+            progress_interval: Some(2),
+            ..Default::default()
+        };
+        let mut log = Vec::new();
+        process_bytes_with_log(&bytes, &options, &mut log).unwrap();
+        let log = String::from_utf8(log).unwrap();
+        let counts: Vec<&str> = log.lines()
+            .filter_map(|line| line.strip_prefix("Processed "))
+            .map(|line| line.split(' ').next().unwrap())
+            .collect();
+        // The count carries on across tracks, and the last one is written too
+        assert_eq!(counts, ["2", "3"]);
+    }
+
+    #[test]
+    fn test_process_bytes_no_bpm_required() {
+        let bytes = type_1_file(&[
+            &[(0, [0x90, 60, 80]), (96, [0x80, 60, 0])],
+            &[(48, [0x91, 48, 70]), (192, [0x81, 48, 0])]
+        ]);
+        assert!(matches!(process_bytes(&bytes, &Options::default()), Err(Midi2JsonError::MissingTempo)));
+
+        let options = Options {
+            no_bpm_required: true,
+            merge_tracks: true,
+            ..Default::default()
+        };
+        let mut log = Vec::new();
+        let note_info = process_bytes_with_log(&bytes, &options, &mut log).unwrap();
+        let log = String::from_utf8(log).unwrap();
+        assert_eq!(log.matches("Warning: the file has no tempo events").count(), 1);
+
+        let json = serde_json::to_value(&note_info).unwrap();
+        let notes = json["notes"].as_array().unwrap();
+        assert_eq!(notes.len(), 2);
+        for (note, (time_start, time_end)) in notes.iter().zip([(0, 96), (48, 240)]) {
+            assert_eq!((note["time_start_ticks"].as_u64(), note["time_end_ticks"].as_u64()), (Some(time_start), Some(time_end)));
+            assert!(note.get("time_start").is_none() && note.get("time_end").is_none());
+        }
+
+        // A tempo, when there is one, is still used
+        let options = Options { bpm: Some(120.0), ..options };
+        let note = process_bytes(&bytes, &options).unwrap().notes.unwrap().remove(0);
+        assert_eq!((note.time_end, note.time_end_ticks), (TimeValue::Seconds(0.5), None));
+
+        // Anything else written in seconds still needs one
+        let options = Options { bpm: None, include_tempo_map: true, ..options };
+        assert!(matches!(process_bytes(&bytes, &options), Err(Midi2JsonError::MissingTempo)));
+    }
+
+    #[test]
+    fn test_process_bytes_type_2_patterns() {
+        // Both patterns start at tick 0 on their own timelines
+        let bytes = type_2_file(&[
+            &[(0, [0x90, 60, 80]), (96, [0x80, 60, 0])],
+            &[(0, [0x90, 60, 80]), (48, [0x80, 60, 0])]
+        ]);
+        let options = Options {
+            bpm: Some(120.0),
+            deduplicate: true,
+            ..Default::default()
+        };
+        let note_info = process_bytes(&bytes, &options).unwrap();
+        assert!(note_info.notes.is_none() && note_info.tracks.is_none());
+        let patterns = note_info.patterns.unwrap();
+        let times: Vec<Vec<(f64, f64)>> = patterns.iter()
+            .map(|pattern| pattern.notes.iter().map(|note| (note.time_start.as_f64(), note.time_end.as_f64())).collect())
+            .collect();
+        assert_eq!(times, vec![vec![(0.0, 0.5)], vec![(0.0, 0.25)]]);
+        assert_eq!(patterns[1].index, 1);
+
+        // Merging the tracks would line the patterns up as if they played together
+        let options = Options {
+            merge_tracks: true,
+            ..options
+        };
+        let mut log = Vec::new();
+        let note_info = process_bytes_with_log(&bytes, &options, &mut log).unwrap();
+        assert!(note_info.notes.is_none());
+        assert_eq!(note_info.patterns.unwrap().len(), 2);
+        assert!(String::from_utf8(log).unwrap().contains("aren't merged"));
+    }
+
+    #[test]
+    fn test_process_bytes_type_0_by_channel() {
+        let bytes = type_0_file(&[
+            (0, [0x99, 36, 100]),
+            (0, [0x90, 60, 80]),
+            (0, [0x91, 48, 70]),
+            (48, [0x89, 36, 0]),
+            (0, [0x99, 42, 90]),
+            (48, [0x80, 60, 0]),
+            (0, [0x81, 48, 0]),
+            (0, [0x89, 42, 0]),
+            (0, [0x90, 64, 80]),
+            (96, [0x80, 64, 0])
+        ]);
+        let options = Options {
+            bpm: Some(120.0),
+            merge_tracks: true,
+            ..Default::default()
+        };
+        let note_info = process_bytes(&bytes, &options).unwrap();
+        assert!(note_info.notes.is_none() && note_info.tracks.is_none());
+        let channels: Vec<(u8, Vec<u32>)> = note_info.channels.unwrap().iter()
+            .map(|channel| (channel.channel, channel.notes.iter().map(|note| note.pitch_value).collect()))
+            .collect();
+        assert_eq!(channels, vec![(0, vec![60, 64]), (1, vec![48]), (9, vec![36, 42])]);
+
+        let options = Options {
+            merge_tracks: false,
+            ..options
+        };
+        let note_info = process_bytes(&bytes, &options).unwrap();
+        assert!(note_info.channels.is_none());
+        assert_eq!(note_info.notes.unwrap().len(), 5);
+    }
+
+    #[test]
+    fn test_process_bytes_note_density() {
+        // A beat-long note at the start of each of four bars of 4/4
+        let bytes = type_0_file(&[
+            (0, [0x90, 60, 100]),
+            (96, [0x80, 60, 0]),
+            (288, [0x90, 62, 100]),
+            (96, [0x80, 62, 0]),
+            (288, [0x90, 64, 100]),
+            (96, [0x80, 64, 0]),
+            (288, [0x90, 65, 100]),
+            (96, [0x80, 65, 0])
+        ]);
+        let options = Options {
+            bpm: Some(120.0),
+            note_density: true,
+            ..Default::default()
+        };
+        let note_info = process_bytes(&bytes, &options).unwrap();
+        let density: Vec<(u32, u32, f64)> = note_info.note_density.unwrap().iter()
+            .map(|measure| (measure.measure, measure.count, measure.notes_per_second))
+            .collect();
+        assert_eq!(density, vec![(1, 1, 0.5), (2, 1, 0.5), (3, 1, 0.5), (4, 1, 0.5)]);
+        assert!(note_info.notes.unwrap().iter().all(|note| note.measure.is_none()));
+    }
+
+    #[test]
+    fn test_process_bytes_time_range() {
+        // A two-beat note every two beats at 120 BPM, so one a second
+        let bytes = type_0_file(&[
+            (0, [0x90, 60, 100]),
+            (192, [0x80, 60, 0]),
+            (0, [0x90, 62, 100]),
+            (192, [0x80, 62, 0]),
+            (0, [0x90, 64, 100]),
+            (192, [0x80, 64, 0])
+        ]);
+        let get_times = |options: &Options| -> Vec<(f64, f64)> {
+            process_bytes(&bytes, options).unwrap().notes.unwrap().iter()
+                .map(|note| (note.time_start.as_f64(), note.time_end.as_f64()))
+                .collect()
+        };
+
+        let options = Options {
+            bpm: Some(120.0),
+            time_start: Some(1.0),
+            time_end: Some(2.5),
+            ..Default::default()
+        };
+        assert_eq!(get_times(&options), vec![(1.0, 2.0), (2.0, 2.5)]);
+
+        // The same range in beats, which doesn't need a tempo
+        let options = Options {
+            bpm: None,
+            time_start: Some(2.0),
+            time_end: Some(5.0),
+            time_range_beats: true,
+            note_options: NoteOptions { time_unit: TimeUnit::Beats(QUARTER_NOTE), ..Default::default() },
+            ..options
+        };
+        assert_eq!(get_times(&options), vec![(2.0, 4.0), (4.0, 5.0)]);
+    }
+
+    #[test]
+    fn test_process_bytes_invalid() {
+        assert!(matches!(
+            process_bytes(b"not a MIDI file", &Options::default()),
+            Err(Midi2JsonError::ParseError(_))
+        ));
+    }
+}
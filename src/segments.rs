@@ -0,0 +1,151 @@
+//! Breaking long notes up into shorter ones
+
+use crate::{Note, TimeValue};
+
+/// Splits every note longer than `max_duration` into the fewest equal segments
+/// that are each at most that long, in place, and returns how many notes were
+/// split. The duration is in the same unit as the note times; in ticks, the
+/// segments are rounded to whole ticks, so they may differ by one.
+///
+/// Each segment keeps the rest of the original note's fields, like its pitch,
+/// velocity, and channel, and the segments end exactly where it did. The notes
+/// are left in order of start time.
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::{split_long_notes, Note};
+///
+/// let mut notes = vec![Note { time_start: 0.0.into(), time_end: 1.0.into(), duration: Some(1.0.into()), ..Default::default() }];
+/// split_long_notes(&mut notes, 0.4);
+/// assert_eq!(notes.len(), 3);
+/// assert_eq!(notes[2].time_end, 1.0.into());
+/// ```
+pub fn split_long_notes(notes: &mut Vec<Note>, max_duration: f64) -> usize {
+    let mut split_count = 0;
+    let mut segments = Vec::with_capacity(notes.len());
+    for note in notes.drain(..) {
+        let duration = note.time_end.since(note.time_start).as_f64();
+        if duration <= max_duration {
+            segments.push(note);
+            continue;
+        }
+
+        split_count += 1;
+        let segment_count = (duration / max_duration).ceil() as u32;
+        let boundaries: Vec<TimeValue> = (0..=segment_count)
+            .map(|segment| interpolate(note.time_start, note.time_end, segment, segment_count))
+            .collect();
+        for pair in boundaries.windows(2) {
+            segments.push(Note {
+                time_start: pair[0],
+                time_end: pair[1],
+                duration: Some(pair[1].since(pair[0])),
+                ..note.clone()
+            });
+        }
+    }
+
+    // Later segments can start after the notes that followed the original
+    segments.sort_by(|a, b| a.time_start.as_f64().partial_cmp(&b.time_start.as_f64()).unwrap());
+    *notes = segments;
+    split_count
+}
+
+/// Removes every note longer than `max_duration`, in the same unit as the note
+/// times, and returns how many were removed
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::{remove_long_notes, Note};
+///
+/// let mut notes = vec![
+///     Note { time_start: 0.0.into(), time_end: 30.0.into(), ..Default::default() },
+///     Note { time_start: 1.0.into(), time_end: 2.0.into(), ..Default::default() }
+/// ];
+/// assert_eq!(remove_long_notes(&mut notes, 10.0), 1);
+/// assert_eq!(notes[0].time_start, 1.0.into());
+/// ```
+pub fn remove_long_notes(notes: &mut Vec<Note>, max_duration: f64) -> usize {
+    let original_count = notes.len();
+    notes.retain(|note| note.time_end.since(note.time_start).as_f64() <= max_duration);
+    original_count - notes.len()
+}
+
+/// The time `numerator / denominator` of the way from `start` to `end`
+fn interpolate(start: TimeValue, end: TimeValue, numerator: u32, denominator: u32) -> TimeValue {
+    // The last boundary is exactly the end, with no rounding
+    if numerator == denominator {
+        return end;
+    }
+
+    let fraction = numerator as f64 / denominator as f64;
+    match (start, end) {
+        (TimeValue::Ticks(start), TimeValue::Ticks(end)) => {
+            TimeValue::Ticks(start + ((end - start) as u64 * numerator as u64 / denominator as u64) as u32)
+        },
+        (TimeValue::Seconds(start), TimeValue::Seconds(end)) => TimeValue::Seconds(start + (end - start) * fraction),
+        (TimeValue::Beats(start), TimeValue::Beats(end)) => TimeValue::Beats(start + (end - start) * fraction),
+        _ => panic!("Cannot interpolate between times in different units")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn note(time_start: f64, time_end: f64) -> Note {
+        Note {
+            time_start: time_start.into(),
+            time_end: time_end.into(),
+            duration: Some((time_end - time_start).into()),
+            pitch_value: 60,
+            channel: 2,
+            velocity: 90,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_split_long_notes() {
+        let mut notes = vec![note(0.0, 1.0), note(2.0, 2.25)];
+        assert_eq!(split_long_notes(&mut notes, 0.25), 1);
+
+        let times: Vec<(f64, f64, f64)> = notes.iter()
+            .map(|note| (note.time_start.as_f64(), note.time_end.as_f64(), note.duration.unwrap().as_f64()))
+            .collect();
+        assert_eq!(times, vec![
+            (0.0, 0.25, 0.25),
+            (0.25, 0.5, 0.25),
+            (0.5, 0.75, 0.25),
+            (0.75, 1.0, 0.25),
+            (2.0, 2.25, 0.25)
+        ]);
+        assert!(notes.iter().all(|note| note.pitch_value == 60 && note.channel == 2 && note.velocity == 90));
+
+        let mut notes = vec![note(0.0, 0.3)];
+        assert_eq!(split_long_notes(&mut notes, 0.5), 0);
+        assert_eq!(notes.len(), 1);
+    }
+
+    #[test]
+    fn test_split_long_notes_ticks() {
+        let mut notes = vec![Note {
+            time_start: TimeValue::Ticks(10),
+            time_end: TimeValue::Ticks(110),
+            ..Default::default()
+        }];
+        split_long_notes(&mut notes, 30.0);
+
+        let times: Vec<(TimeValue, TimeValue)> = notes.iter()
+            .map(|note| (note.time_start, note.time_end))
+            .collect();
+        assert_eq!(times, vec![
+            (TimeValue::Ticks(10), TimeValue::Ticks(35)),
+            (TimeValue::Ticks(35), TimeValue::Ticks(60)),
+            (TimeValue::Ticks(60), TimeValue::Ticks(85)),
+            (TimeValue::Ticks(85), TimeValue::Ticks(110))
+        ]);
+    }
+}
@@ -0,0 +1,152 @@
+//! Orders for the notes other than by start time
+
+use std::cmp::Ordering;
+use crate::Note;
+
+/// What to sort the notes by
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortKey {
+    /// By pitch, then by start time
+    Pitch,
+    /// By how long the notes last, then by start time
+    Duration
+}
+
+impl SortKey {
+    /// The direction the notes are sorted in unless another one is asked for
+    pub fn default_direction(self) -> SortDirection {
+        match self {
+            SortKey::Pitch => SortDirection::Ascending,
+            // Long notes tend to be the structural ones, so they come first
+            SortKey::Duration => SortDirection::Descending
+        }
+    }
+
+    fn compare(self, a: &Note, b: &Note) -> Ordering {
+        match self {
+            SortKey::Pitch => a.pitch_value.cmp(&b.pitch_value),
+            SortKey::Duration => get_duration(a).partial_cmp(&get_duration(b)).unwrap()
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortDirection {
+    Ascending,
+    Descending
+}
+
+/// Sorts the notes by a key, in the key's default direction unless `direction`
+/// is given. Notes with the same key are in order of start time either way.
+///
+/// The sort is stable, so notes that compare equal keep their order.
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::{sort_notes, Note, SortKey};
+///
+/// let mut notes = vec![
+///     Note { time_start: 0.0.into(), pitch_value: 64, ..Default::default() },
+///     Note { time_start: 1.0.into(), pitch_value: 60, ..Default::default() }
+/// ];
+/// sort_notes(&mut notes, SortKey::Pitch, None);
+/// assert_eq!(notes[0].pitch_value, 60);
+/// ```
+pub fn sort_notes(notes: &mut [Note], key: SortKey, direction: Option<SortDirection>) {
+    let direction = direction.unwrap_or_else(|| key.default_direction());
+    notes.sort_by(|a, b| {
+        let ordering = match direction {
+            SortDirection::Ascending => key.compare(a, b),
+            SortDirection::Descending => key.compare(b, a)
+        };
+        ordering.then_with(|| a.time_start.as_f64().partial_cmp(&b.time_start.as_f64()).unwrap())
+    });
+}
+
+fn get_duration(note: &Note) -> f64 {
+    note.time_end.as_f64() - note.time_start.as_f64()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn note(time_start: f64, pitch_value: u32, velocity: u8) -> Note {
+        Note {
+            time_start: time_start.into(),
+            pitch_value,
+            velocity,
+            ..Default::default()
+        }
+    }
+
+    fn timed_note(time_start: f64, time_end: f64, pitch_value: u32) -> Note {
+        Note {
+            time_start: time_start.into(),
+            time_end: time_end.into(),
+            pitch_value,
+            ..Default::default()
+        }
+    }
+
+    fn get_times(notes: &[Note]) -> Vec<(f64, f64)> {
+        notes.iter()
+            .map(|note| (note.time_start.as_f64(), note.time_end.as_f64()))
+            .collect()
+    }
+
+    fn get_keys(notes: &[Note]) -> Vec<(u32, f64, u8)> {
+        notes.iter()
+            .map(|note| (note.pitch_value, note.time_start.as_f64(), note.velocity))
+            .collect()
+    }
+
+    #[test]
+    fn test_sort_notes_by_pitch() {
+        let mut notes = vec![note(1.0, 64, 1), note(0.0, 67, 2), note(0.5, 64, 3), note(0.0, 60, 4)];
+        sort_notes(&mut notes, SortKey::Pitch, None);
+        assert_eq!(get_keys(&notes), vec![(60, 0.0, 4), (64, 0.5, 3), (64, 1.0, 1), (67, 0.0, 2)]);
+
+        sort_notes(&mut notes, SortKey::Pitch, Some(SortDirection::Descending));
+        assert_eq!(get_keys(&notes), vec![(67, 0.0, 2), (64, 0.5, 3), (64, 1.0, 1), (60, 0.0, 4)]);
+    }
+
+    #[test]
+    fn test_sort_notes_stable() {
+        // Same pitch and start, so only the velocities tell them apart
+        let mut notes = vec![note(0.0, 60, 1), note(0.0, 55, 2), note(0.0, 60, 3), note(0.0, 60, 4)];
+        sort_notes(&mut notes, SortKey::Pitch, None);
+        assert_eq!(get_keys(&notes), vec![(55, 0.0, 2), (60, 0.0, 1), (60, 0.0, 3), (60, 0.0, 4)]);
+
+        let mut notes = vec![note(0.0, 60, 1), note(0.0, 55, 2), note(0.0, 60, 3), note(0.0, 60, 4)];
+        sort_notes(&mut notes, SortKey::Pitch, Some(SortDirection::Descending));
+        assert_eq!(get_keys(&notes), vec![(60, 0.0, 1), (60, 0.0, 3), (60, 0.0, 4), (55, 0.0, 2)]);
+    }
+
+    #[test]
+    fn test_sort_notes_by_duration() {
+        let mut notes = vec![
+            timed_note(0.0, 0.5, 60),
+            timed_note(1.0, 3.0, 62),
+            timed_note(2.0, 2.25, 64),
+            timed_note(0.5, 2.5, 65)
+        ];
+        sort_notes(&mut notes, SortKey::Duration, None);
+        assert_eq!(get_times(&notes), vec![(0.5, 2.5), (1.0, 3.0), (0.0, 0.5), (2.0, 2.25)]);
+
+        sort_notes(&mut notes, SortKey::Duration, Some(SortDirection::Ascending));
+        assert_eq!(get_times(&notes), vec![(2.0, 2.25), (0.0, 0.5), (0.5, 2.5), (1.0, 3.0)]);
+    }
+
+    #[test]
+    fn test_sort_notes_by_duration_ties() {
+        // Every note lasts a beat, so they come out in start order whichever way they're sorted
+        let mut notes = vec![timed_note(3.0, 4.0, 60), timed_note(1.0, 2.0, 62), timed_note(2.0, 3.0, 64)];
+        sort_notes(&mut notes, SortKey::Duration, None);
+        assert_eq!(get_times(&notes), vec![(1.0, 2.0), (2.0, 3.0), (3.0, 4.0)]);
+
+        sort_notes(&mut notes, SortKey::Duration, Some(SortDirection::Ascending));
+        assert_eq!(get_times(&notes), vec![(1.0, 2.0), (2.0, 3.0), (3.0, 4.0)]);
+    }
+}
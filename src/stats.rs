@@ -0,0 +1,432 @@
+//! Summary statistics over a set of notes
+
+use std::collections::BTreeMap;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde::de;
+use schemars::JsonSchema;
+use crate::Note;
+use crate::meter::MeasureMap;
+use crate::time::{TempoChange, TimeValue, get_time_seconds_tempo_map};
+
+/// Aggregate information about the notes, so consumers don't have to go through them all
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Statistics {
+    pub total_notes: u32,
+    /// The lowest pitch, or `None` if there are no notes
+    pub min_pitch: Option<u8>,
+    /// The highest pitch, or `None` if there are no notes
+    pub max_pitch: Option<u8>,
+    /// The sum of every note's duration, in the same unit as the note times
+    pub total_duration_seconds: f64,
+    pub notes_per_channel: BTreeMap<u8, u32>,
+    pub pitch_histogram: BTreeMap<u8, u32>,
+    /// How many duplicate notes were removed, if requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duplicates_removed: Option<u32>,
+    /// The Shannon entropy of the pitch classes in bits, if requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pitch_entropy: Option<f64>
+}
+
+/// Computes the statistics in a single pass over the notes
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::{get_statistics, Note};
+///
+/// let notes = vec![
+///     Note { pitch_value: 60, duration: Some(0.5.into()), ..Default::default() },
+///     Note { pitch_value: 64, duration: Some(0.25.into()), ..Default::default() }
+/// ];
+/// let statistics = get_statistics(notes.iter());
+/// assert_eq!(statistics.total_notes, 2);
+/// assert_eq!(statistics.max_pitch, Some(64));
+/// assert_eq!(statistics.total_duration_seconds, 0.75);
+/// ```
+pub fn get_statistics<'a>(notes: impl Iterator<Item = &'a Note>) -> Statistics {
+    let mut statistics = Statistics::default();
+    for note in notes {
+        let pitch = note.pitch_value as u8;
+        statistics.total_notes += 1;
+        statistics.min_pitch = Some(statistics.min_pitch.map_or(pitch, |min_pitch| min_pitch.min(pitch)));
+        statistics.max_pitch = Some(statistics.max_pitch.map_or(pitch, |max_pitch| max_pitch.max(pitch)));
+        if let Some(duration) = note.duration {
+            statistics.total_duration_seconds += duration.as_f64();
+        }
+        *statistics.notes_per_channel.entry(note.channel).or_insert(0) += 1;
+        *statistics.pitch_histogram.entry(pitch).or_insert(0) += 1;
+    }
+
+    statistics
+}
+
+/// The Shannon entropy, in bits, of how the notes in a pitch histogram are
+/// spread over the 12 pitch classes: 0 when every note is the same pitch class,
+/// up to log2(12) (about 3.585) when each one is equally common. Octaves are
+/// folded together, so a melody doesn't count as more complex for being played
+/// in two octaves at once.
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::get_pitch_entropy;
+///
+/// let histogram = vec![(60, 3), (72, 1)].into_iter().collect();
+/// assert_eq!(get_pitch_entropy(&histogram), 0.0);
+///
+/// let histogram = vec![(60, 1), (67, 1)].into_iter().collect();
+/// assert_eq!(get_pitch_entropy(&histogram), 1.0);
+/// ```
+pub fn get_pitch_entropy(pitch_histogram: &BTreeMap<u8, u32>) -> f64 {
+    let mut pitch_class_counts = [0u32; 12];
+    for (&pitch, &count) in pitch_histogram {
+        pitch_class_counts[(pitch % 12) as usize] += count;
+    }
+
+    let total: u32 = pitch_class_counts.iter().sum();
+    let entropy: f64 = pitch_class_counts.iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let probability = count as f64 / total as f64;
+            -probability * probability.log2()
+        })
+        .sum();
+    // A single pitch class would otherwise come out as -0.0
+    entropy.max(0.0)
+}
+
+/// Counts the notes of each pitch, keyed by the pitch's name (e.g. "C4") if the
+/// notes have names, or by its MIDI number otherwise
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::{get_pitch_histogram, Note};
+///
+/// let notes = vec![
+///     Note { pitch_value: 60, ..Default::default() },
+///     Note { pitch_value: 60, ..Default::default() },
+///     Note { pitch_value: 62, ..Default::default() }
+/// ];
+/// let histogram = get_pitch_histogram(notes.iter());
+/// assert_eq!(histogram["60"], 2);
+/// assert_eq!(histogram["62"], 1);
+/// ```
+pub fn get_pitch_histogram<'a>(notes: impl Iterator<Item = &'a Note>) -> BTreeMap<String, u32> {
+    let mut histogram = BTreeMap::new();
+    for note in notes {
+        let key = note.pitch_name.clone().unwrap_or_else(|| note.pitch_value.to_string());
+        *histogram.entry(key).or_insert(0) += 1;
+    }
+
+    histogram
+}
+
+/// The time between each note's start and the next one's, as written to the output
+#[derive(Clone, Debug, PartialEq, Serialize, JsonSchema)]
+#[serde(untagged)]
+pub enum InterOnsetIntervals {
+    /// Between consecutive notes of any pitch
+    All(Vec<TimeValue>),
+    /// Between consecutive notes of each pitch, keyed by MIDI number
+    PerPitch(BTreeMap<u32, Vec<TimeValue>>)
+}
+
+/// `InterOnsetIntervals` as it's read, before the pitches are parsed. Serde
+/// can only try each variant of an untagged enum on JSON it's already read, and
+/// then it can't read the object's keys as numbers.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonIntervals {
+    All(Vec<TimeValue>),
+    PerPitch(BTreeMap<String, Vec<TimeValue>>)
+}
+
+impl<'de> Deserialize<'de> for InterOnsetIntervals {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match JsonIntervals::deserialize(deserializer)? {
+            JsonIntervals::All(intervals) => Ok(InterOnsetIntervals::All(intervals)),
+            JsonIntervals::PerPitch(intervals) => intervals.into_iter()
+                .map(|(pitch, intervals)| match pitch.parse() {
+                    Ok(pitch) => Ok((pitch, intervals)),
+                    Err(_) => Err(de::Error::invalid_value(de::Unexpected::Str(&pitch), &"a MIDI pitch number"))
+                })
+                .collect::<Result<_, _>>()
+                .map(InterOnsetIntervals::PerPitch)
+        }
+    }
+}
+
+/// The inter-onset intervals of the notes: the time from each note's start to
+/// the next one's, in order of start time and regardless of pitch, so there is
+/// one fewer than there are notes. Notes that start together have an interval
+/// of 0. The intervals are in the same unit as the note times.
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::{get_inter_onset_intervals, Note};
+///
+/// let notes = vec![
+///     Note { time_start: 0.0.into(), ..Default::default() },
+///     Note { time_start: 1.5.into(), ..Default::default() },
+///     Note { time_start: 0.5.into(), ..Default::default() }
+/// ];
+/// let intervals = get_inter_onset_intervals(notes.iter());
+/// assert_eq!(intervals, vec![0.5.into(), 1.0.into()]);
+/// ```
+pub fn get_inter_onset_intervals<'a>(notes: impl Iterator<Item = &'a Note>) -> Vec<TimeValue> {
+    let mut starts: Vec<TimeValue> = notes.map(|note| note.time_start).collect();
+    starts.sort_by(|a, b| a.as_f64().partial_cmp(&b.as_f64()).unwrap());
+    starts.windows(2)
+        .map(|pair| pair[1].since(pair[0]))
+        .collect()
+}
+
+/// The inter-onset intervals of the notes of each pitch separately. See
+/// `get_inter_onset_intervals`.
+pub fn get_inter_onset_intervals_per_pitch<'a>(notes: impl Iterator<Item = &'a Note>) -> BTreeMap<u32, Vec<TimeValue>> {
+    let mut by_pitch = BTreeMap::<u32, Vec<&Note>>::new();
+    for note in notes {
+        by_pitch.entry(note.pitch_value).or_default().push(note);
+    }
+
+    by_pitch.into_iter()
+        .map(|(pitch, notes)| (pitch, get_inter_onset_intervals(notes.into_iter())))
+        .collect()
+}
+
+/// How many notes start in one measure, as written to the output
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct NoteDensity {
+    /// The 1-indexed measure
+    pub measure: u32,
+    pub count: u32,
+    /// The count divided by how long the measure lasts
+    pub notes_per_second: f64
+}
+
+/// Counts the notes that start in each measure, given the measure each note
+/// starts in. Every measure up to the one containing `end_tick` is listed, even
+/// if no notes start in it.
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::{get_note_density, TempoChange};
+/// use midi2json::meter::MeasureMap;
+///
+/// // Two bars of 4/4 at 120 BPM, with three notes in the first
+/// let measure_map = MeasureMap::new(&[], 96);
+/// let tempo_map = vec![TempoChange { tick: 0, micros_per_beat: 500_000 }];
+/// let density = get_note_density(vec![1, 1, 1].into_iter(), 768, &measure_map, &tempo_map, 96);
+/// assert_eq!(density.len(), 2);
+/// assert_eq!(density[0].count, 3);
+/// assert_eq!(density[0].notes_per_second, 1.5);
+/// assert_eq!(density[1].count, 0);
+/// ```
+pub fn get_note_density(
+    measures: impl Iterator<Item = u32>,
+    end_tick: u32,
+    measure_map: &MeasureMap,
+    tempo_map: &[TempoChange],
+    ticks_per_beat: u16
+) -> Vec<NoteDensity> {
+    // A file that ends right on a bar line doesn't have another measure after it
+    let (mut measure_count, _) = measure_map.get_position(end_tick.saturating_sub(1));
+    let mut counts = BTreeMap::<u32, u32>::new();
+    for measure in measures {
+        *counts.entry(measure).or_insert(0) += 1;
+        measure_count = measure_count.max(measure);
+    }
+
+    (1..=measure_count)
+        .map(|measure| {
+            let count = counts.get(&measure).copied().unwrap_or(0);
+            let start = get_time_seconds_tempo_map(measure_map.get_measure_start(measure), tempo_map, ticks_per_beat);
+            let end = get_time_seconds_tempo_map(measure_map.get_measure_start(measure + 1), tempo_map, ticks_per_beat);
+            NoteDensity {
+                measure,
+                count,
+                notes_per_second: count as f64 / (end - start)
+            }
+        })
+        .collect()
+}
+
+/// How many notes start in one window of time, as written to the output
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct DensitySample {
+    /// When the window starts
+    pub time: f64,
+    /// The notes that start in the window, divided by how long it is
+    pub notes_per_second: f64
+}
+
+/// Counts the notes that start in each window of `window` seconds, from 0 up to
+/// the window the last note starts in. A note that starts right on the edge of
+/// two windows is counted in the later one.
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::{get_density_timeline, Note};
+///
+/// let notes: Vec<Note> = [0.0, 0.25, 1.5].iter()
+///     .map(|&time_start| Note { time_start: time_start.into(), ..Default::default() })
+///     .collect();
+/// let timeline = get_density_timeline(notes.iter(), 1.0);
+/// assert_eq!(timeline.len(), 2);
+/// assert_eq!(timeline[0].notes_per_second, 2.0);
+/// assert_eq!(timeline[1].time, 1.0);
+/// ```
+pub fn get_density_timeline<'a>(notes: impl Iterator<Item = &'a Note>, window: f64) -> Vec<DensitySample> {
+    let mut counts = Vec::<u32>::new();
+    for note in notes {
+        let index = (note.time_start.as_f64() / window).floor().max(0.0) as usize;
+        if index >= counts.len() {
+            counts.resize(index + 1, 0);
+        }
+        counts[index] += 1;
+    }
+
+    counts.into_iter()
+        .enumerate()
+        .map(|(index, count)| DensitySample {
+            time: index as f64 * window,
+            notes_per_second: count as f64 / window
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn note(pitch_value: u32, channel: u8, duration: f64) -> Note {
+        Note {
+            pitch_value,
+            channel,
+            duration: Some(duration.into()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_get_statistics() {
+        let notes = [
+            note(60, 0, 0.5),
+            note(64, 0, 0.25),
+            note(60, 9, 1.0),
+            note(36, 9, 0.125)
+        ];
+        let statistics = get_statistics(notes.iter());
+        assert_eq!(statistics, Statistics {
+            total_notes: 4,
+            min_pitch: Some(36),
+            max_pitch: Some(64),
+            total_duration_seconds: 1.875,
+            notes_per_channel: vec![(0, 2), (9, 2)].into_iter().collect(),
+            pitch_histogram: vec![(36, 1), (60, 2), (64, 1)].into_iter().collect(),
+            duplicates_removed: None,
+            pitch_entropy: None
+        });
+    }
+
+    #[test]
+    fn test_get_pitch_entropy() {
+        let single_pitch = vec![(64, 20)].into_iter().collect();
+        assert_eq!(get_pitch_entropy(&single_pitch), 0.0);
+        assert_eq!(get_pitch_entropy(&BTreeMap::new()), 0.0);
+
+        let chromatic = (60..72).map(|pitch| (pitch, 5)).collect();
+        assert!((get_pitch_entropy(&chromatic) - 12f64.log2()).abs() < 1e-12);
+        assert!((get_pitch_entropy(&chromatic) - 3.585).abs() < 0.001);
+
+        // The same pitch classes spread over several octaves
+        let spread = (36..84).map(|pitch| (pitch, 1)).collect();
+        assert!((get_pitch_entropy(&spread) - 12f64.log2()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_get_inter_onset_intervals() {
+        let notes: Vec<Note> = [(0.0, 60), (0.5, 64), (0.5, 67), (1.25, 60), (2.0, 64)].iter()
+            .map(|&(time_start, pitch_value)| Note { time_start: time_start.into(), pitch_value, ..Default::default() })
+            .collect();
+
+        let intervals = get_inter_onset_intervals(notes.iter());
+        assert_eq!(intervals.len(), notes.len() - 1);
+        assert_eq!(intervals, vec![0.5.into(), 0.0.into(), 0.75.into(), 0.75.into()]);
+
+        let per_pitch = get_inter_onset_intervals_per_pitch(notes.iter());
+        assert_eq!(per_pitch[&60], vec![1.25.into()]);
+        assert_eq!(per_pitch[&64], vec![1.5.into()]);
+        assert!(per_pitch[&67].is_empty());
+
+        assert!(get_inter_onset_intervals([].iter()).is_empty());
+    }
+
+    #[test]
+    fn test_get_density_timeline() {
+        // Four evenly spaced notes a second for three seconds
+        let notes: Vec<Note> = (0..12)
+            .map(|index| Note { time_start: (index as f64 * 0.25).into(), ..Default::default() })
+            .collect();
+        let timeline = get_density_timeline(notes.iter(), 1.0);
+        assert_eq!(timeline, vec![
+            DensitySample { time: 0.0, notes_per_second: 4.0 },
+            DensitySample { time: 1.0, notes_per_second: 4.0 },
+            DensitySample { time: 2.0, notes_per_second: 4.0 }
+        ]);
+
+        // Half-second windows hold two notes each, which is still four a second
+        let timeline = get_density_timeline(notes.iter(), 0.5);
+        assert_eq!(timeline.len(), 6);
+        assert!(timeline.iter().all(|sample| sample.notes_per_second == 4.0));
+
+        assert!(get_density_timeline([].iter(), 1.0).is_empty());
+    }
+
+    #[test]
+    fn test_get_note_density_tempo_change() {
+        // 3/4 at 120 BPM, then twice as fast from the third measure
+        let measure_map = MeasureMap::new(&[crate::TimeSignature {
+            tick: 0,
+            time_seconds: 0.0,
+            numerator: 3,
+            denominator: 4,
+            clocks_per_click: 24,
+            thirty_second_notes_per_quarter: 8
+        }], 96);
+        let tempo_map = vec![
+            TempoChange { tick: 0, micros_per_beat: 500_000 },
+            TempoChange { tick: 576, micros_per_beat: 250_000 }
+        ];
+        let density = get_note_density(vec![1, 3, 3, 3].into_iter(), 864, &measure_map, &tempo_map, 96);
+        let summary: Vec<(u32, u32, f64)> = density.iter()
+            .map(|measure| (measure.measure, measure.count, measure.notes_per_second))
+            .collect();
+        assert_eq!(summary, vec![(1, 1, 1.0 / 1.5), (2, 0, 0.0), (3, 3, 4.0)]);
+    }
+
+    #[test]
+    fn test_get_note_density_past_end() {
+        let measure_map = MeasureMap::new(&[], 96);
+        let tempo_map = vec![TempoChange { tick: 0, micros_per_beat: 500_000 }];
+        assert_eq!(get_note_density(vec![].into_iter(), 0, &measure_map, &tempo_map, 96).len(), 1);
+        assert_eq!(get_note_density(vec![3].into_iter(), 384, &measure_map, &tempo_map, 96).len(), 3);
+    }
+
+    #[test]
+    fn test_get_statistics_json() {
+        let notes = [note(60, 9, 0.5)];
+        let json = serde_json::to_value(get_statistics(notes.iter())).unwrap();
+        assert_eq!(json["notes_per_channel"]["9"], 1);
+        assert_eq!(json["pitch_histogram"]["60"], 1);
+
+        let empty = serde_json::to_value(get_statistics([].iter())).unwrap();
+        assert_eq!(empty["total_notes"], 0);
+        assert!(empty["min_pitch"].is_null());
+    }
+}
@@ -0,0 +1,737 @@
+//! Tempo maps and conversions from ticks to other units of time
+
+use std::convert::TryFrom;
+use std::fmt;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::de::{self, Visitor};
+use schemars::JsonSchema;
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use midly::{
+    Event,
+    EventKind,
+    Fps,
+    MetaMessage
+};
+use crate::error::{Midi2JsonError, Result};
+
+/// The tempo assumed by the MIDI spec until the first tempo event (120 BPM)
+pub(crate) const DEFAULT_MICROS_PER_BEAT: u32 = 500_000;
+
+/// The unit that note times are written in
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum TimeUnit {
+    #[default]
+    Seconds,
+    Ticks,
+    /// Beats that are each this fraction of a whole note, like a time
+    /// signature's denominator: 4 for quarter notes, which are the file's ticks
+    /// per beat, 8 for eighth notes, or 3 for half-note triplets
+    Beats(u32)
+}
+
+/// The usual beat, for `TimeUnit::Beats`
+pub const QUARTER_NOTE: u32 = 4;
+
+/// A point in time (or a length of time) in one of the supported units
+///
+/// Seconds and beats are written out as floats, and ticks as integers.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimeValue {
+    Seconds(f64),
+    Ticks(u32),
+    Beats(f64),
+    /// A time that couldn't be worked out, like one in seconds for a file with
+    /// no tempo. Notes leave out times like these, and they're `null` elsewhere.
+    Unknown
+}
+
+impl TimeValue {
+    /// The raw value, in whatever unit this is, or NaN if it's unknown
+    pub fn as_f64(self) -> f64 {
+        match self {
+            TimeValue::Seconds(seconds) => seconds,
+            TimeValue::Ticks(ticks) => ticks as f64,
+            TimeValue::Beats(beats) => beats,
+            TimeValue::Unknown => f64::NAN
+        }
+    }
+
+    pub fn is_unknown(&self) -> bool {
+        *self == TimeValue::Unknown
+    }
+
+    /// The time between an earlier value and this one, in the same unit
+    pub fn since(self, earlier: TimeValue) -> TimeValue {
+        match (self, earlier) {
+            (TimeValue::Unknown, _) | (_, TimeValue::Unknown) => TimeValue::Unknown,
+            (TimeValue::Ticks(ticks), TimeValue::Ticks(earlier_ticks)) => {
+                TimeValue::Ticks(ticks - earlier_ticks)
+            },
+            (TimeValue::Seconds(seconds), TimeValue::Seconds(earlier_seconds)) => {
+                TimeValue::Seconds(seconds - earlier_seconds)
+            },
+            (TimeValue::Beats(beats), TimeValue::Beats(earlier_beats)) => {
+                TimeValue::Beats(beats - earlier_beats)
+            },
+            _ => panic!("Cannot compare times in different units")
+        }
+    }
+}
+
+impl Default for TimeValue {
+    fn default() -> Self {
+        TimeValue::Seconds(0.0)
+    }
+}
+
+impl From<f64> for TimeValue {
+    fn from(seconds: f64) -> Self {
+        TimeValue::Seconds(seconds)
+    }
+}
+
+impl fmt::Display for TimeValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TimeValue::Seconds(seconds) => write!(f, "{}", seconds),
+            TimeValue::Ticks(ticks) => write!(f, "{}", ticks),
+            TimeValue::Beats(beats) => write!(f, "{}", beats),
+            TimeValue::Unknown => write!(f, "unknown")
+        }
+    }
+}
+
+impl Serialize for TimeValue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match self {
+            TimeValue::Seconds(seconds) => serializer.serialize_f64(*seconds),
+            TimeValue::Ticks(ticks) => serializer.serialize_u32(*ticks),
+            TimeValue::Beats(beats) => serializer.serialize_f64(*beats),
+            TimeValue::Unknown => serializer.serialize_none()
+        }
+    }
+}
+
+/// Reads a time back from the output. The JSON doesn't say which unit a time
+/// is in, so whole numbers are read as ticks and the rest as seconds, which
+/// includes beats. `null` is unknown.
+impl<'de> Deserialize<'de> for TimeValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        deserializer.deserialize_any(TimeValueVisitor)
+    }
+}
+
+struct TimeValueVisitor;
+
+impl<'de> Visitor<'de> for TimeValueVisitor {
+    type Value = TimeValue;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a time in seconds, ticks, or beats")
+    }
+
+    fn visit_u64<E: de::Error>(self, ticks: u64) -> std::result::Result<TimeValue, E> {
+        u32::try_from(ticks)
+            .map(TimeValue::Ticks)
+            .map_err(|_| E::invalid_value(de::Unexpected::Unsigned(ticks), &self))
+    }
+
+    fn visit_i64<E: de::Error>(self, ticks: i64) -> std::result::Result<TimeValue, E> {
+        u32::try_from(ticks)
+            .map(TimeValue::Ticks)
+            .map_err(|_| E::invalid_value(de::Unexpected::Signed(ticks), &self))
+    }
+
+    fn visit_f64<E: de::Error>(self, seconds: f64) -> std::result::Result<TimeValue, E> {
+        Ok(TimeValue::Seconds(seconds))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> std::result::Result<TimeValue, E> {
+        Ok(TimeValue::Unknown)
+    }
+
+    fn visit_none<E: de::Error>(self) -> std::result::Result<TimeValue, E> {
+        Ok(TimeValue::Unknown)
+    }
+}
+
+impl JsonSchema for TimeValue {
+    fn schema_name() -> String {
+        "TimeValue".to_string()
+    }
+
+    // Just a number, in whichever unit the times are in
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        f64::json_schema(generator)
+    }
+}
+
+/// A tempo change, taking effect at an absolute tick position
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TempoChange {
+    pub tick: u32,
+    pub micros_per_beat: u32
+}
+
+/// A tempo change as written to the output
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct TempoMapEntry {
+    pub tick: u32,
+    pub bpm: f64,
+    pub time_seconds: f64
+}
+
+/// Collects the tempo changes from every track.
+///
+/// In multi-track files the tempo events usually live in the first track, but
+/// they apply to all of them. The fallback BPM only applies before the first
+/// tempo event, or to the whole file if it has no tempo events at all.
+///
+/// Fails with `MissingTempo` if there are no tempo events and no fallback BPM.
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::{get_tempo_map, TempoChange};
+///
+/// let tracks: Vec<Vec<midly::Event>> = vec![];
+/// let tempo_map = get_tempo_map(&tracks, Some(60.0)).unwrap();
+/// assert_eq!(tempo_map, vec![TempoChange { tick: 0, micros_per_beat: 1_000_000 }]);
+/// ```
+pub fn get_tempo_map<'a, T: AsRef<[Event<'a>]>>(tracks: &[T], fallback_bpm: Option<f32>) -> Result<Vec<TempoChange>> {
+    let mut tempo_map = Vec::<TempoChange>::new();
+    for track in tracks {
+        let mut cur_time: u32 = 0;
+        for event in track.as_ref() {
+            cur_time += event.delta.as_int();
+
+            if let EventKind::Meta(MetaMessage::Tempo(micros_per_beat)) = event.kind {
+                tempo_map.push(TempoChange {
+                    tick: cur_time,
+                    micros_per_beat: micros_per_beat.as_int()
+                });
+            }
+        }
+    }
+    tempo_map.sort_by_key(|change| change.tick);
+
+    let fallback_micros_per_beat = match fallback_bpm {
+        Some(bpm) => (60_000_000.0 / (bpm as f64)).round() as u32,
+        None if tempo_map.is_empty() => return Err(Midi2JsonError::MissingTempo),
+        None => DEFAULT_MICROS_PER_BEAT
+    };
+
+    if tempo_map.first().is_none_or(|change| change.tick > 0) {
+        tempo_map.insert(0, TempoChange {
+            tick: 0,
+            micros_per_beat: fallback_micros_per_beat
+        });
+    }
+
+    Ok(tempo_map)
+}
+
+/// Describes each tempo change with its BPM and the time it takes effect
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::{get_tempo_map_entries, TempoChange};
+///
+/// let tempo_map = vec![
+///     TempoChange { tick: 0, micros_per_beat: 500_000 },
+///     TempoChange { tick: 192, micros_per_beat: 1_000_000 }
+/// ];
+/// let entries = get_tempo_map_entries(&tempo_map, 96);
+/// assert_eq!(entries[1].bpm, 60.0);
+/// assert_eq!(entries[1].time_seconds, 1.0);
+/// ```
+pub fn get_tempo_map_entries(tempo_map: &[TempoChange], ticks_per_beat: u16) -> Vec<TempoMapEntry> {
+    // Each change's time follows on from the one before, the same way
+    // get_time_seconds_tempo_map adds them up
+    let mut time_seconds = 0.0;
+    let mut previous = TempoChange { tick: 0, micros_per_beat: DEFAULT_MICROS_PER_BEAT };
+    tempo_map.iter()
+        .map(|change| {
+            time_seconds += ticks_to_seconds(change.tick - previous.tick, previous.micros_per_beat, ticks_per_beat);
+            previous = *change;
+            TempoMapEntry {
+                tick: change.tick,
+                bpm: 60_000_000.0 / (change.micros_per_beat as f64),
+                time_seconds
+            }
+        })
+        .collect()
+}
+
+/// Converts a tick position to seconds, accounting for every tempo change before it
+///
+/// The seconds up to the last tempo change at or before the tick are added up
+/// one tempo at a time, then the ticks since that change are converted at its
+/// tempo. The tempos are used as exact microseconds per beat, so each time is
+/// as accurate as the ticks allow. Before the first tempo change, the MIDI
+/// default of 120 BPM applies.
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::{get_time_seconds_tempo_map, TempoChange};
+///
+/// // Two beats at 120 BPM, then 60 BPM
+/// let tempo_map = vec![
+///     TempoChange { tick: 0, micros_per_beat: 500_000 },
+///     TempoChange { tick: 192, micros_per_beat: 1_000_000 }
+/// ];
+/// assert_eq!(get_time_seconds_tempo_map(288, &tempo_map, 96), 2.0);
+/// ```
+pub fn get_time_seconds_tempo_map(ticks: u32, tempo_map: &[TempoChange], ticks_per_beat: u16) -> f64 {
+    // The seconds when the current tempo took effect
+    let mut change_seconds = 0.0;
+    let mut current = TempoChange { tick: 0, micros_per_beat: DEFAULT_MICROS_PER_BEAT };
+    for change in tempo_map {
+        if change.tick > ticks {
+            break;
+        }
+
+        change_seconds += ticks_to_seconds(change.tick - current.tick, current.micros_per_beat, ticks_per_beat);
+        current = *change;
+    }
+
+    change_seconds + ticks_to_seconds(ticks - current.tick, current.micros_per_beat, ticks_per_beat)
+}
+
+/// Converts a time in seconds to the nearest tick, following the tempo map.
+/// This is the reverse of `get_time_seconds_tempo_map`.
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::TempoChange;
+/// use midi2json::time::get_ticks_tempo_map;
+///
+/// // Two beats at 120 BPM, then 60 BPM
+/// let tempo_map = vec![
+///     TempoChange { tick: 0, micros_per_beat: 500_000 },
+///     TempoChange { tick: 192, micros_per_beat: 1_000_000 }
+/// ];
+/// assert_eq!(get_ticks_tempo_map(2.0, &tempo_map, 96), 288);
+/// ```
+pub fn get_ticks_tempo_map(seconds: f64, tempo_map: &[TempoChange], ticks_per_beat: u16) -> u32 {
+    let mut segment_tick = 0;
+    let mut segment_seconds = 0.0;
+    let mut micros_per_beat = DEFAULT_MICROS_PER_BEAT;
+    for change in tempo_map {
+        let change_seconds = segment_seconds + ticks_to_seconds(change.tick - segment_tick, micros_per_beat, ticks_per_beat);
+        if change_seconds > seconds {
+            break;
+        }
+
+        segment_tick = change.tick;
+        segment_seconds = change_seconds;
+        micros_per_beat = change.micros_per_beat;
+    }
+
+    let beats = (seconds - segment_seconds).max(0.0) * 1_000_000.0 / (micros_per_beat as f64);
+    segment_tick + (beats * ticks_per_beat as f64).round() as u32
+}
+
+fn ticks_to_seconds(ticks: u32, micros_per_beat: u32, ticks_per_beat: u16) -> f64 {
+    (ticks as f64) / (ticks_per_beat as f64) * (micros_per_beat as f64) / 1_000_000.0
+}
+
+/// Converts a tick position to seconds at a constant tempo. For files that
+/// can change tempo, use `get_time_seconds_tempo_map` instead.
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::get_time_seconds;
+///
+/// assert_eq!(get_time_seconds(240, 120.0, 480), 0.25);
+/// ```
+pub fn get_time_seconds(ticks: u32, bpm: f32, ticks_per_beat: u16) -> f64 {
+    // The header gives us ticks per beat, and the tempo gives us beats per minute.
+    let ticks_per_sec = (bpm as f64) * (ticks_per_beat as f64) / 60.0;
+
+    (ticks as f64) / ticks_per_sec
+}
+
+/// Converts a tick position to seconds in a file with SMPTE timecode timing,
+/// which has a fixed number of ticks per frame instead of per beat
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::get_time_seconds_timecode;
+///
+/// assert_eq!(get_time_seconds_timecode(50, 25.0, 4), 0.5);
+/// ```
+pub fn get_time_seconds_timecode(ticks: u32, frames_per_second: f32, ticks_per_frame: u8) -> f64 {
+    (ticks as f64) / ((frames_per_second as f64) * (ticks_per_frame as f64))
+}
+
+/// Builds a tempo map and ticks per beat that give the same times as SMPTE
+/// timecode timing, so timecode files can be converted like any other.
+///
+/// A beat is made to last exactly one frame's worth of seconds times the frame
+/// rate: one second, or 1.001 seconds at 29.97 fps. Tempo events don't apply to
+/// timecode files, so there is only ever the one tempo.
+///
+/// # Examples
+///
+/// ```
+/// use midly::Fps;
+/// use midi2json::{get_time_seconds_tempo_map, get_timecode_tempo_map};
+///
+/// let (tempo_map, ticks_per_beat) = get_timecode_tempo_map(Fps::Fps30, 10);
+/// assert_eq!(get_time_seconds_tempo_map(150, &tempo_map, ticks_per_beat), 0.5);
+/// ```
+pub fn get_timecode_tempo_map(fps: Fps, ticks_per_frame: u8) -> (Vec<TempoChange>, u16) {
+    let micros_per_beat = match fps {
+        Fps::Fps29 => 1_001_000,
+        _ => 1_000_000
+    };
+    let tempo_map = vec![TempoChange { tick: 0, micros_per_beat }];
+
+    // midly counts 29.97 fps as 29, but with the longer beat it needs a whole 30 frames
+    let frames_per_beat = fps.as_f32().round() as u16;
+
+    (tempo_map, frames_per_beat * (ticks_per_frame as u16))
+}
+
+/// Rounds a tick position to the nearest multiple of a grid, given in beats
+/// (e.g. 0.25 for sixteenth notes). Positions halfway between round up.
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::quantize_ticks;
+///
+/// assert_eq!(quantize_ticks(130, 0.25, 480), 120);
+/// assert_eq!(quantize_ticks(190, 0.25, 480), 240);
+/// ```
+pub fn quantize_ticks(ticks: u32, grid_beats: f64, ticks_per_beat: u16) -> u32 {
+    let grid_ticks = grid_beats * (ticks_per_beat as f64);
+    ((ticks as f64 / grid_ticks).round() * grid_ticks).round() as u32
+}
+
+/// How many ticks to delay a note that starts at a tick position by, to swing
+/// it with the given ratio. Notes on the eighth note between two beats move
+/// from halfway through the beat to `ratio` of the way through it, so 0.5 is
+/// straight and 0.67 is about a triplet; notes anywhere else aren't delayed,
+/// and nothing is with 0 ticks per beat.
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::swing_delay_ticks;
+///
+/// assert_eq!(swing_delay_ticks(144, 0.75, 96), 24);
+/// assert_eq!(swing_delay_ticks(96, 0.75, 96), 0);
+/// ```
+pub fn swing_delay_ticks(ticks: u32, ratio: f64, ticks_per_beat: u16) -> u32 {
+    // Doubled, so a half beat is a whole number of ticks even when the beat isn't
+    let doubled_ticks = ticks as u64 * 2;
+    let ticks_per_beat = ticks_per_beat as u64;
+    if ticks_per_beat > 0 && doubled_ticks.is_multiple_of(ticks_per_beat) && (doubled_ticks / ticks_per_beat) % 2 == 1 {
+        ((ratio - 0.5) * ticks_per_beat as f64).round() as u32
+    } else {
+        0
+    }
+}
+
+/// Converts a tick position to the given unit
+pub fn get_time(ticks: u32, unit: TimeUnit, tempo_map: &[TempoChange], ticks_per_beat: u16) -> TimeValue {
+    match unit {
+        TimeUnit::Seconds => TimeValue::Seconds(get_time_seconds_tempo_map(ticks, tempo_map, ticks_per_beat)),
+        TimeUnit::Ticks => TimeValue::Ticks(ticks),
+        TimeUnit::Beats(subdivision) => TimeValue::Beats(get_time_beats(ticks, ticks_per_beat) * subdivision as f64 / QUARTER_NOTE as f64)
+    }
+}
+
+/// Converts a tick position to quarter-note beats, which doesn't depend on the tempo
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::time::get_time_beats;
+///
+/// assert_eq!(get_time_beats(720, 480), 1.5);
+/// ```
+pub fn get_time_beats(ticks: u32, ticks_per_beat: u16) -> f64 {
+    (ticks as f64) / (ticks_per_beat as f64)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use midly::number::{u24, VarlenInt};
+
+    #[test]
+    fn test_get_time_seconds_120() {
+        assert_eq!(get_time_seconds(0, 120.0, 96), 0.0);
+        assert_eq!(get_time_seconds(48, 120.0, 96), 0.25);
+        assert_eq!(get_time_seconds(192, 120.0, 96), 1.0);
+    }
+
+    #[test]
+    fn test_get_time_seconds_60() {
+        assert_eq!(get_time_seconds(0, 60.0, 96), 0.0);
+        assert_eq!(get_time_seconds(48, 60.0, 96), 0.5);
+        assert_eq!(get_time_seconds(96, 60.0, 96), 1.0);
+    }
+
+    #[test]
+    fn test_get_time_seconds_properties() {
+        // Random ticks and tempos from a fixed seed, plus the extremes
+        let mut rng = crate::Rng::new(98);
+        let mut cases: Vec<(u32, u32, f32)> = vec![(0, u32::MAX, 1.0), (u32::MAX - 1, u32::MAX, 300.0)];
+        for _ in 0..10_000 {
+            let (a, b) = (rng.next_u64() as u32, rng.next_u64() as u32);
+            cases.push((a.min(b), a.max(b), rng.next_f64(1.0, 300.0) as f32));
+        }
+
+        for (earlier, later, bpm) in cases {
+            for &ticks_per_beat in &[24, 96, 480, 960] {
+                assert_eq!(get_time_seconds(0, bpm, ticks_per_beat), 0.0, "at {} BPM", bpm);
+                let (start, end) = (get_time_seconds(earlier, bpm, ticks_per_beat), get_time_seconds(later, bpm, ticks_per_beat));
+                assert!(start <= end && start >= 0.0 && end.is_finite(), "ticks {} and {} at {} BPM and {} PPQ", earlier, later, bpm, ticks_per_beat);
+            }
+        }
+    }
+
+    fn tempo_event(delta: u32, micros_per_beat: u32) -> Event<'static> {
+        Event {
+            delta: VarlenInt::from(delta),
+            kind: EventKind::Meta(MetaMessage::Tempo(u24::from(micros_per_beat)))
+        }
+    }
+
+    #[test]
+    fn test_get_time_seconds_480_ppq() {
+        assert_eq!(get_time_seconds(0, 120.0, 480), 0.0);
+        assert_eq!(get_time_seconds(240, 120.0, 480), 0.25);
+        assert_eq!(get_time_seconds(960, 120.0, 480), 1.0);
+        assert_eq!(get_time_seconds(480, 60.0, 480), 1.0);
+    }
+
+    #[test]
+    fn test_get_tempo_map() {
+        let track = vec![
+            tempo_event(0, 500_000),
+            tempo_event(192, 1_000_000)
+        ];
+        assert_eq!(get_tempo_map(&[&track], None).unwrap(), vec![
+            TempoChange { tick: 0, micros_per_beat: 500_000 },
+            TempoChange { tick: 192, micros_per_beat: 1_000_000 }
+        ]);
+    }
+
+    #[test]
+    fn test_get_tempo_map_fallback_bpm() {
+        let no_tracks: Vec<Vec<Event>> = vec![];
+        assert_eq!(get_tempo_map(&no_tracks, Some(60.0)).unwrap(), vec![
+            TempoChange { tick: 0, micros_per_beat: 1_000_000 }
+        ]);
+
+        let track = vec![tempo_event(96, 1_000_000)];
+        assert_eq!(get_tempo_map(&[&track], Some(120.0)).unwrap(), vec![
+            TempoChange { tick: 0, micros_per_beat: 500_000 },
+            TempoChange { tick: 96, micros_per_beat: 1_000_000 }
+        ]);
+    }
+
+    #[test]
+    fn test_get_time_seconds_tempo_change() {
+        // 120 BPM for the first two beats, then 60 BPM
+        let tempo_map = vec![
+            TempoChange { tick: 0, micros_per_beat: 500_000 },
+            TempoChange { tick: 192, micros_per_beat: 1_000_000 }
+        ];
+        assert_eq!(get_time_seconds_tempo_map(0, &tempo_map, 96), 0.0);
+        assert_eq!(get_time_seconds_tempo_map(96, &tempo_map, 96), 0.5);
+        assert_eq!(get_time_seconds_tempo_map(192, &tempo_map, 96), 1.0);
+        assert_eq!(get_time_seconds_tempo_map(288, &tempo_map, 96), 2.0);
+        assert_eq!(get_time_seconds_tempo_map(480, &tempo_map, 96), 4.0);
+    }
+
+    #[test]
+    fn test_get_ticks_tempo_map() {
+        let tempo_map = vec![
+            TempoChange { tick: 0, micros_per_beat: 500_000 },
+            TempoChange { tick: 192, micros_per_beat: 1_000_000 }
+        ];
+        for ticks in [0, 1, 95, 96, 191, 192, 193, 288, 480] {
+            let seconds = get_time_seconds_tempo_map(ticks, &tempo_map, 96);
+            assert_eq!(get_ticks_tempo_map(seconds, &tempo_map, 96), ticks);
+        }
+        // Between ticks, it rounds to the nearest one
+        assert_eq!(get_ticks_tempo_map(0.5 + 0.004, &tempo_map, 96), 97);
+        assert_eq!(get_ticks_tempo_map(-1.0, &tempo_map, 96), 0);
+        // 120 BPM until the first tempo event
+        assert_eq!(get_ticks_tempo_map(1.0, &[], 96), 192);
+    }
+
+    #[test]
+    fn test_get_time_seconds_tempo_map_exact() {
+        // 140 BPM doesn't divide a minute into whole microseconds, so this is
+        // the tempo a file would really have; converting it through a float BPM
+        // would be off in the last places
+        let tempo_map = vec![
+            TempoChange { tick: 0, micros_per_beat: 428_571 },
+            TempoChange { tick: 960, micros_per_beat: 600_000 }
+        ];
+        assert_eq!(get_time_seconds_tempo_map(960, &tempo_map, 480), 0.857142);
+        assert_eq!(get_time_seconds_tempo_map(1200, &tempo_map, 480), 0.857142 + 0.3);
+        // Ticks before a file's first tempo change are at 120 BPM
+        assert_eq!(get_time_seconds_tempo_map(96, &[TempoChange { tick: 192, micros_per_beat: 1_000_000 }], 96), 0.5);
+    }
+
+    #[test]
+    fn test_get_tempo_map_multiple_tracks() {
+        let conductor_track = vec![tempo_event(0, 500_000), tempo_event(192, 1_000_000)];
+        let other_track = vec![tempo_event(96, 750_000)];
+        assert_eq!(get_tempo_map(&[conductor_track, other_track], None).unwrap(), vec![
+            TempoChange { tick: 0, micros_per_beat: 500_000 },
+            TempoChange { tick: 96, micros_per_beat: 750_000 },
+            TempoChange { tick: 192, micros_per_beat: 1_000_000 }
+        ]);
+    }
+
+    #[test]
+    fn test_get_time_ticks_ignores_tempo() {
+        let slow_tempo_map = vec![TempoChange { tick: 0, micros_per_beat: 1_000_000 }];
+        let fast_tempo_map = vec![TempoChange { tick: 0, micros_per_beat: 250_000 }];
+        assert_eq!(get_time(480, TimeUnit::Ticks, &slow_tempo_map, 96), TimeValue::Ticks(480));
+        assert_eq!(get_time(480, TimeUnit::Ticks, &fast_tempo_map, 96), TimeValue::Ticks(480));
+        assert_eq!(get_time(480, TimeUnit::Seconds, &slow_tempo_map, 96), TimeValue::Seconds(5.0));
+        assert_eq!(get_time(480, TimeUnit::Seconds, &fast_tempo_map, 96), TimeValue::Seconds(1.25));
+    }
+
+    #[test]
+    fn test_time_value_json() {
+        assert_eq!(serde_json::to_string(&TimeValue::Seconds(1.5)).unwrap(), "1.5");
+        assert_eq!(serde_json::to_string(&TimeValue::Ticks(480)).unwrap(), "480");
+        assert_eq!(TimeValue::Ticks(480).since(TimeValue::Ticks(96)), TimeValue::Ticks(384));
+    }
+
+    #[test]
+    fn test_get_time_beats() {
+        assert_eq!(get_time_beats(0, 480), 0.0);
+        assert_eq!(get_time_beats(480, 480), 1.0);
+        assert_eq!(get_time_beats(960, 480), 2.0);
+        assert_eq!(get_time_beats(96, 96), 1.0);
+        assert_eq!(get_time_beats(48, 96), 0.5);
+        assert_eq!(get_time_beats(1920, 960), 2.0);
+        assert_eq!(get_time_beats(120, 480), 0.25);
+    }
+
+    #[test]
+    fn test_get_time_beats_ignores_tempo() {
+        let tempo_map = vec![TempoChange { tick: 0, micros_per_beat: 1_000_000 }];
+        assert_eq!(get_time(480, TimeUnit::Beats(QUARTER_NOTE), &tempo_map, 480), TimeValue::Beats(1.0));
+        assert_eq!(get_time(480, TimeUnit::Beats(QUARTER_NOTE), &[], 480), TimeValue::Beats(1.0));
+    }
+
+    #[test]
+    fn test_get_time_beat_resolution() {
+        // An eighth note into a file at 480 ticks per quarter note
+        let beats = |subdivision| get_time(240, TimeUnit::Beats(subdivision), &[], 480).as_f64();
+        assert_eq!(beats(4), 0.5);
+        assert_eq!(beats(8), 1.0);
+        assert_eq!(beats(16), 2.0);
+        assert_eq!(beats(32), 4.0);
+        // Half-note triplets, three to a whole note
+        assert_eq!(beats(3), 0.375);
+        assert_eq!(get_time(1920, TimeUnit::Beats(8), &[], 480).as_f64(), 8.0);
+    }
+
+    #[test]
+    fn test_get_tempo_map_missing_tempo() {
+        let no_tracks: Vec<Vec<Event>> = vec![];
+        assert!(matches!(get_tempo_map(&no_tracks, None), Err(Midi2JsonError::MissingTempo)));
+    }
+
+    #[test]
+    fn test_get_tempo_map_entries() {
+        let track = vec![
+            tempo_event(0, 500_000),
+            tempo_event(192, 1_000_000),
+            tempo_event(96, 400_000)
+        ];
+        let tempo_map = get_tempo_map(&[&track], None).unwrap();
+        assert_eq!(get_tempo_map_entries(&tempo_map, 96), vec![
+            TempoMapEntry { tick: 0, bpm: 120.0, time_seconds: 0.0 },
+            TempoMapEntry { tick: 192, bpm: 60.0, time_seconds: 1.0 },
+            TempoMapEntry { tick: 288, bpm: 150.0, time_seconds: 2.0 }
+        ]);
+    }
+
+    #[test]
+    fn test_swing_delay_ticks() {
+        // Straight eighths don't move
+        assert!((0..960).all(|ticks| swing_delay_ticks(ticks, 0.5, 96) == 0));
+
+        // Triplet swing moves the off-beat eighths a sixth of a beat later
+        assert_eq!(swing_delay_ticks(48, 2.0 / 3.0, 96), 16);
+        assert_eq!(swing_delay_ticks(240, 2.0 / 3.0, 480), 80);
+        assert_eq!(swing_delay_ticks(720, 0.67, 480), 82);
+        for &ticks in &[0, 24, 47, 49, 72, 96, 192] {
+            assert_eq!(swing_delay_ticks(ticks, 2.0 / 3.0, 96), 0);
+        }
+
+        // With an odd number of ticks per beat, no tick is halfway through a beat
+        assert!((0..1000).all(|ticks| swing_delay_ticks(ticks, 0.75, 25) == 0));
+        assert!((0..1000).all(|ticks| swing_delay_ticks(ticks, 0.75, 0) == 0));
+    }
+
+    #[test]
+    fn test_quantize_ticks() {
+        // Sixteenth notes at 480 ticks per beat are 120 ticks apart
+        assert_eq!(quantize_ticks(0, 0.25, 480), 0);
+        assert_eq!(quantize_ticks(59, 0.25, 480), 0);
+        assert_eq!(quantize_ticks(60, 0.25, 480), 120);
+        assert_eq!(quantize_ticks(121, 0.25, 480), 120);
+        assert_eq!(quantize_ticks(179, 0.25, 480), 120);
+        assert_eq!(quantize_ticks(181, 0.25, 480), 240);
+
+        // Triplet eighths don't divide the beat evenly into ticks
+        assert_eq!(quantize_ticks(95, 1.0 / 3.0, 96), 96);
+        assert_eq!(quantize_ticks(50, 1.0 / 3.0, 96), 64);
+        assert_eq!(quantize_ticks(47, 1.0 / 3.0, 100), 33);
+
+        // Coarser grids than a beat
+        assert_eq!(quantize_ticks(1000, 2.0, 480), 960);
+    }
+
+    #[test]
+    fn test_get_time_seconds_timecode() {
+        assert_eq!(get_time_seconds_timecode(0, 25.0, 4), 0.0);
+        assert_eq!(get_time_seconds_timecode(100, 25.0, 4), 1.0);
+        assert_eq!(get_time_seconds_timecode(25, 25.0, 4), 0.25);
+        assert_eq!(get_time_seconds_timecode(300, 30.0, 10), 1.0);
+        assert_eq!(get_time_seconds_timecode(450, 30.0, 10), 1.5);
+    }
+
+    #[test]
+    fn test_get_timecode_tempo_map() {
+        let (tempo_map, ticks_per_beat) = get_timecode_tempo_map(Fps::Fps25, 4);
+        assert_eq!(ticks_per_beat, 100);
+        for ticks in [0, 25, 100, 1234] {
+            assert_eq!(
+                get_time_seconds_tempo_map(ticks, &tempo_map, ticks_per_beat),
+                get_time_seconds_timecode(ticks, 25.0, 4)
+            );
+        }
+
+        let (tempo_map, ticks_per_beat) = get_timecode_tempo_map(Fps::Fps30, 10);
+        assert_eq!(get_time_seconds_tempo_map(300, &tempo_map, ticks_per_beat), 1.0);
+        assert_eq!(get_time_seconds_tempo_map(450, &tempo_map, ticks_per_beat), 1.5);
+
+        // 29.97 fps drop-frame
+        let (tempo_map, ticks_per_beat) = get_timecode_tempo_map(Fps::Fps29, 10);
+        let seconds = get_time_seconds_tempo_map(2997, &tempo_map, ticks_per_beat);
+        assert!((seconds - 2997.0 / (30.0 / 1.001 * 10.0)).abs() < 1e-5, "{}", seconds);
+    }
+}
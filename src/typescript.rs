@@ -0,0 +1,238 @@
+//! TypeScript definitions for the JSON output
+
+use std::io::{self, Write};
+
+/// An interface for each structure in the JSON output.
+///
+/// Fields that are only written when a flag asks for them are optional (`?:`),
+/// and fields that are always written but can be `null` are typed that way.
+/// Note times are numbers whichever unit they're in.
+pub const DEFINITIONS: &str = r#"export interface NoteInfo {
+  midi_header?: MidiHeader;
+  notes?: Note[];
+  tracks?: TrackNotes[];
+  patterns?: TrackNotes[];
+  channels?: ChannelNotes[];
+  chords?: Chord[];
+  tempo_map?: TempoMapEntry[];
+  time_signatures?: TimeSignature[];
+  control_changes?: ControlChange[];
+  program_changes?: ProgramChange[];
+  pitch_bends?: PitchBend[];
+  key_signatures?: KeySignature[];
+  text_events?: TextEvent[];
+  overlaps?: Overlap[];
+  note_density?: NoteDensity[];
+  density_timeline?: DensitySample[];
+  pitch_histogram?: Record<string, number>;
+  inter_onset_intervals?: number[] | Record<string, number[]>;
+  offset_seconds?: number;
+  statistics?: Statistics;
+}
+
+export interface Note {
+  time_start?: number;
+  time_end?: number;
+  time_start_ticks?: number;
+  time_end_ticks?: number;
+  duration: number | null;
+  delta_start?: number;
+  measure?: number;
+  beat?: number;
+  pitch_value: number;
+  pitch_name?: string;
+  pitch_class?: number;
+  pitch_class_name?: string;
+  frequency_hz?: number;
+  drum_name?: string;
+  channel: number;
+  velocity: number;
+  release_velocity: number | null;
+  velocity_f?: number;
+  truncated?: boolean;
+  articulation?: "legato" | "staccato";
+  source_file?: string;
+  source_track?: number | string;
+}
+
+export interface TrackNotes {
+  index: number;
+  name: string | null;
+  notes: Note[];
+}
+
+export interface ChannelNotes {
+  channel: number;
+  notes: Note[];
+}
+
+export interface Chord {
+  time_start: number;
+  time_end: number;
+  notes: Note[];
+}
+
+export interface MidiHeader {
+  format: number;
+  track_count: number;
+  timing_type: "metrical" | "timecode";
+  ticks_per_quarter_note?: number;
+  frames_per_second?: number;
+  ticks_per_frame?: number;
+}
+
+export interface TempoMapEntry {
+  tick: number;
+  bpm: number;
+  time_seconds: number;
+}
+
+export interface TimeSignature {
+  tick: number;
+  time_seconds: number;
+  numerator: number;
+  denominator: number;
+  clocks_per_click: number;
+  thirty_second_notes_per_quarter: number;
+}
+
+export interface ControlChange {
+  tick: number;
+  time_seconds: number;
+  channel: number;
+  controller: number;
+  value: number;
+}
+
+export interface ProgramChange {
+  tick: number;
+  time_seconds: number;
+  channel: number;
+  program: number;
+  program_name?: string;
+}
+
+export interface PitchBend {
+  tick: number;
+  time_seconds: number;
+  channel: number;
+  value: number;
+  semitones?: number;
+}
+
+export interface KeySignature {
+  tick: number;
+  time_seconds: number;
+  sharps_flats: number;
+  major: boolean;
+  key_name: string;
+}
+
+export interface TextEvent {
+  tick: number;
+  time_seconds: number;
+  type: "text" | "copyright" | "track_name" | "instrument_name" | "lyric" | "marker" | "cue_point" | "program_name" | "device_name";
+  text: string;
+}
+
+export interface Overlap {
+  track?: number;
+  channel: number;
+  pitch_value: number;
+  first_start: number;
+  first_end: number;
+  second_start: number;
+  second_end: number;
+}
+
+export interface NoteDensity {
+  measure: number;
+  count: number;
+  notes_per_second: number;
+}
+
+export interface DensitySample {
+  time: number;
+  notes_per_second: number;
+}
+
+export interface Statistics {
+  total_notes: number;
+  min_pitch: number | null;
+  max_pitch: number | null;
+  total_duration_seconds: number;
+  notes_per_channel: Record<string, number>;
+  pitch_histogram: Record<string, number>;
+  duplicates_removed?: number;
+  pitch_entropy?: number;
+}
+"#;
+
+/// Writes the TypeScript definitions for the JSON output
+///
+/// # Examples
+///
+/// ```
+/// use midi2json::typescript::write_typescript;
+///
+/// let mut output = Vec::new();
+/// write_typescript(&mut output).unwrap();
+/// assert!(String::from_utf8(output).unwrap().contains("export interface Note {"));
+/// ```
+pub fn write_typescript<W: Write>(writer: &mut W) -> io::Result<()> {
+    writer.write_all(DEFINITIONS.as_bytes())
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+    use serde_json::Value;
+    use crate::NoteInfo;
+    use super::*;
+
+    /// Each interface's fields, and whether each one is optional
+    fn parse_interfaces() -> BTreeMap<String, BTreeMap<String, bool>> {
+        let mut interfaces = BTreeMap::new();
+        let mut current: Option<(String, BTreeMap<String, bool>)> = None;
+        for line in DEFINITIONS.lines() {
+            if let Some(name) = line.strip_prefix("export interface ") {
+                current = Some((name.trim_end_matches(" {").to_string(), BTreeMap::new()));
+            } else if line == "}" {
+                let (name, fields) = current.take().unwrap();
+                interfaces.insert(name, fields);
+            } else if let Some((_, fields)) = current.as_mut() {
+                let (field, _) = line.trim().split_once(':').unwrap();
+                match field.strip_suffix('?') {
+                    Some(field) => fields.insert(field.to_string(), true),
+                    None => fields.insert(field.to_string(), false)
+                };
+            }
+        }
+        interfaces
+    }
+
+    #[test]
+    fn test_definitions_match_schema() {
+        let schema = serde_json::to_value(schemars::schema_for!(NoteInfo)).unwrap();
+        let mut structs: Vec<(&str, &Value)> = vec![("NoteInfo", &schema)];
+        for (name, definition) in schema["definitions"].as_object().unwrap() {
+            // TimeValue is written as a plain number
+            if definition["properties"].is_object() {
+                structs.push((name, definition));
+            }
+        }
+
+        let interfaces = parse_interfaces();
+        assert_eq!(interfaces.len(), structs.len());
+        for (name, definition) in structs {
+            let fields = &interfaces[name];
+            let properties: Vec<&String> = definition["properties"].as_object().unwrap().keys().collect();
+            assert_eq!(fields.keys().collect::<Vec<_>>(), properties, "fields of {}", name);
+
+            // The schema doesn't require nullable fields, so only check one way
+            for field in definition["required"].as_array().into_iter().flatten() {
+                assert!(!fields[field.as_str().unwrap()], "{}.{} should be required", name, field);
+            }
+        }
+    }
+}
@@ -0,0 +1,935 @@
+use std::fs;
+use std::io::{Read, Write};
+use std::process::{Command, Output, Stdio};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_midi2json"))
+        .args(args)
+        .output()
+        .expect("Failed to run midi2json")
+}
+
+fn run_with_stdin(args: &[&str], stdin: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_midi2json"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to run midi2json");
+    child.stdin.take().unwrap().write_all(stdin).unwrap();
+    child.wait_with_output().expect("Failed to run midi2json")
+}
+
+fn gunzip(bytes: &[u8]) -> Vec<u8> {
+    let mut decompressed = Vec::new();
+    GzDecoder::new(bytes).read_to_end(&mut decompressed).expect("output is not valid gzip");
+    decompressed
+}
+
+fn parse_notes(stdout: &[u8]) -> Vec<serde_json::Value> {
+    let json: serde_json::Value = serde_json::from_slice(stdout)
+        .expect("stdout is not valid JSON");
+    json["notes"].as_array().expect("JSON has no notes array").clone()
+}
+
+#[test]
+fn test_stdout_flag_writes_json() {
+    let output = run(&["--input", "input/sample.mid", "--bpm", "120", "--stdout"]);
+    assert!(output.status.success());
+
+    let notes = parse_notes(&output.stdout);
+    assert!(!notes.is_empty());
+    assert_eq!(notes[0]["pitch_value"], 60);
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Loading MIDI file..."));
+}
+
+#[test]
+fn test_output_dash_writes_json() {
+    let output = run(&["--input", "input/sample.mid", "--bpm", "120", "--output", "-"]);
+    assert!(output.status.success());
+    assert!(!parse_notes(&output.stdout).is_empty());
+}
+
+#[test]
+fn test_missing_input_file_fails_cleanly() {
+    let output = run(&["--input", "input/missing.mid", "--bpm", "120", "--stdout"]);
+    assert_eq!(output.status.code(), Some(1));
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Error: Could not read input/missing.mid"));
+    assert!(!stderr.contains("panicked"));
+}
+
+#[test]
+fn test_invalid_bpm_fails_cleanly() {
+    let output = run(&["--input", "input/sample.mid", "--bpm", "fast", "--stdout"]);
+    assert_eq!(output.status.code(), Some(1));
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Error: Invalid BPM: fast"));
+}
+
+#[test]
+fn test_no_bpm_required() {
+    let output = run(&["--input", "input/sample.mid", "--stdout"]);
+    assert_eq!(output.status.code(), Some(1));
+
+    let output = run(&["--input", "input/sample.mid", "--stdout", "--no-bpm-required"]);
+    assert!(output.status.success());
+    let notes = parse_notes(&output.stdout);
+    assert_eq!(notes.len(), 4);
+    for note in &notes {
+        assert!(note["time_start_ticks"].is_u64() && note["time_end_ticks"].is_u64());
+        assert!(note.get("time_start").is_none() && note.get("time_end").is_none());
+    }
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(stderr.matches("Warning: the file has no tempo events").count(), 1);
+}
+
+#[test]
+fn test_swing() {
+    // Played loosely, so they're quantized to eighths first
+    let args = ["--input", "input/demo-116bpm.mid", "--bpm", "116", "--output", "-", "--output-ticks", "--quantize", "0.5"];
+    let straight = parse_notes(&run(&args).stdout);
+    let output = run(&[&args[..], &["--swing", "0.75"]].concat());
+    assert_eq!(output.status.code(), Some(0));
+    let swung = parse_notes(&output.stdout);
+    assert_eq!(straight.len(), swung.len());
+    assert!(straight.iter().any(|note| note["time_start"].as_u64().unwrap() % 96 == 48));
+    for (straight, swung) in straight.iter().zip(&swung) {
+        // A quarter of a beat is 24 ticks at 96 ticks per beat
+        let start = straight["time_start"].as_u64().unwrap();
+        let delay = if start % 96 == 48 { 24 } else { 0 };
+        assert_eq!(swung["time_start"].as_u64().unwrap(), start + delay);
+        assert_eq!(swung["time_end"].as_u64().unwrap(), straight["time_end"].as_u64().unwrap() + delay);
+    }
+
+    // An off-beat eighth held past --time-end is still cut off there
+    let mut track = vec![0x30, 0x90, 60, 100, 0x60, 0x80, 60, 0];
+    track.extend_from_slice(&[0x00, 0xFF, 0x2F, 0x00]);
+    let mut bytes = b"MThd\x00\x00\x00\x06\x00\x00\x00\x01\x00\x60MTrk".to_vec();
+    bytes.extend_from_slice(&(track.len() as u32).to_be_bytes());
+    bytes.extend_from_slice(&track);
+    let output = run_with_stdin(&["--input", "-", "--bpm", "120", "--output", "-", "--time-end", "0.5", "--swing", "0.75"], &bytes);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let notes = parse_notes(&output.stdout);
+    assert_eq!(notes.len(), 1);
+    assert_eq!((notes[0]["time_start"].as_f64(), notes[0]["time_end"].as_f64()), (Some(0.375), Some(0.5)));
+
+    let output = run(&["--input", "input/sample.mid", "--bpm", "120", "--swing", "0.3"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stderr).unwrap().contains("Swing ratio must be a number from 0.5 to 1"));
+}
+
+#[test]
+fn test_check() {
+    let output = run(&["--input", "input/sample.mid", "--bpm", "120", "--check", "-q"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty() && output.stderr.is_empty());
+
+    let dir = std::env::temp_dir().join(format!("midi2json-check-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let malformed_path = dir.join("malformed.mid");
+    fs::write(&malformed_path, b"MThd\x00\x00\x00\x06\x00\x00").unwrap();
+    let output = run(&["--input", malformed_path.to_str().unwrap(), "--bpm", "120", "--check"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stderr).unwrap().contains("Error: Could not parse MIDI file"));
+
+    // Two notes that are never let go
+    let mut track = vec![0x00, 0x90, 60, 100, 0x60, 0x80, 60, 0, 0x00, 0x90, 62, 100, 0x00, 0x90, 64, 100];
+    track.extend_from_slice(&[0x60, 0xFF, 0x2F, 0x00]);
+    let mut bytes = b"MThd\x00\x00\x00\x06\x00\x00\x00\x01\x00\x60MTrk".to_vec();
+    bytes.extend_from_slice(&(track.len() as u32).to_be_bytes());
+    bytes.extend_from_slice(&track);
+    let held_path = dir.join("held.mid");
+    fs::write(&held_path, &bytes).unwrap();
+
+    let output = run(&["--input", held_path.to_str().unwrap(), "--bpm", "120", "--check"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stderr).unwrap().contains("has no matching NoteOff"));
+
+    let output = run(&["--input", held_path.to_str().unwrap(), "--bpm", "120", "--check-report"]);
+    assert_eq!(output.status.code(), Some(0));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("3 notes"), "{}", stderr);
+    assert!(stderr.contains("Warning: 2 notes have no matching NoteOff"), "{}", stderr);
+    assert!(!dir.join("held.json").exists());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_invalid_values_fail_cleanly() {
+    let cases: &[(&[&str], &str)] = &[
+        (&["--bpm", "0"], "Error: Invalid BPM: 0"),
+        (&["--filter-channel", "16"], "Error: Channel must be a number from 0 to 15, got 16"),
+        (&["--min-pitch", "200"], "Error: Pitch must be a number from 0 to 127, got 200"),
+        (&["--min-velocity", "90", "--max-velocity", "10"], "Error: --min-velocity 90 is above --max-velocity 10"),
+        (&["--time-start", "later"], "Error: Time must be a number of at least 0, got later"),
+        (&["--format", "xml"], "'xml' isn't a valid value for '--format <FORMAT>'")
+    ];
+    for (args, message) in cases {
+        let mut all_args = vec!["--input", "input/sample.mid", "--stdout"];
+        all_args.extend_from_slice(args);
+        let output = run(&all_args);
+        assert_eq!(output.status.code(), Some(1), "{:?}", args);
+
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains(message), "{:?}: {}", args, stderr);
+        assert!(!stderr.contains("panicked"));
+    }
+}
+
+#[test]
+fn test_jsonl_matches_json() {
+    let json_output = run(&["--input", "input/sample.mid", "--bpm", "120", "--stdout"]);
+    let jsonl_output = run(&["--input", "input/sample.mid", "--bpm", "120", "--format", "jsonl", "--stdout"]);
+    assert!(jsonl_output.status.success());
+
+    // The lines come out in the order the notes end, so compare them unordered
+    let mut json_notes: Vec<String> = parse_notes(&json_output.stdout).iter()
+        .map(|note| note.to_string())
+        .collect();
+    let mut jsonl_notes: Vec<String> = String::from_utf8(jsonl_output.stdout).unwrap()
+        .lines()
+        .map(|line| {
+            let note: serde_json::Value = serde_json::from_str(line).expect("line is not valid JSON");
+            note.to_string()
+        })
+        .collect();
+    json_notes.sort();
+    jsonl_notes.sort();
+    assert_eq!(jsonl_notes, json_notes);
+}
+
+#[test]
+fn test_jsonl_rejects_whole_file_options() {
+    for flags in [&["--note-density"][..], &["--include-stats"], &["--include-entropy"], &["--pitch-histogram"], &["--include-ioi"], &["--include-ioi", "--ioi-per-pitch"], &["--note-density-window", "1"], &["--include-tempo-map"]] {
+        let output = run(&[&["--input", "input/sample.mid", "--bpm", "120", "--format", "jsonl", "--stdout"][..], flags].concat());
+        assert_eq!(output.status.code(), Some(1), "{:?}", flags);
+        assert!(output.stdout.is_empty(), "{:?}", flags);
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains(&format!("{} ", flags[0])), "{:?}: {}", flags, stderr);
+        assert!(stderr.contains("can't be used with --format jsonl"), "{:?}: {}", flags, stderr);
+    }
+}
+
+#[test]
+fn test_stdin_input_matches_file_input() {
+    let file_output = run(&["--input", "input/sample.mid", "--bpm", "120", "--stdout"]);
+    let bytes = fs::read("input/sample.mid").unwrap();
+    let stdin_output = run_with_stdin(&["--input", "-", "--bpm", "120", "--stdout"], &bytes);
+    assert!(stdin_output.status.success());
+    assert_eq!(stdin_output.stdout, file_output.stdout);
+}
+
+#[test]
+fn test_batch_continues_past_failures() {
+    let output_dir = std::env::temp_dir().join(format!("midi2json-batch-{}", std::process::id()));
+    fs::create_dir_all(&output_dir).unwrap();
+
+    let output = run(&[
+        "--batch",
+        "--input", "input/missing.mid",
+        "--input", "input/sample.mid",
+        "--bpm", "120",
+        "--output-dir", output_dir.to_str().unwrap()
+    ]);
+    assert_eq!(output.status.code(), Some(1));
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("input/missing.mid"));
+    assert!(stderr.contains("Error: 1 of 2 files could not be converted"));
+
+    // The file after the failure was still converted
+    let json = fs::read(output_dir.join("sample.json")).unwrap();
+    assert!(!parse_notes(&json).is_empty());
+
+    fs::remove_dir_all(&output_dir).unwrap();
+}
+
+#[test]
+fn test_input_glob() {
+    let dir = std::env::temp_dir().join(format!("midi2json-glob-{}", std::process::id()));
+    let music_dir = dir.join("music");
+    let output_dir = dir.join("out");
+    fs::create_dir_all(music_dir.join("rock/live")).unwrap();
+    fs::create_dir_all(&output_dir).unwrap();
+    let bytes = fs::read("input/sample.mid").unwrap();
+    fs::write(music_dir.join("intro.mid"), &bytes).unwrap();
+    fs::write(music_dir.join("rock/song.midi"), &bytes).unwrap();
+    fs::write(music_dir.join("rock/live/song.mid"), &bytes).unwrap();
+    fs::write(music_dir.join("notes.txt"), "not a MIDI file").unwrap();
+    fs::write(music_dir.join("rock/cover.png"), "not a MIDI file either").unwrap();
+
+    // No --batch needed, and the tool expands the pattern, not the shell
+    let pattern = format!("{}/**/*", music_dir.to_str().unwrap());
+    let output = run(&["--input-glob", &pattern, "--bpm", "120", "--output-dir", output_dir.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    // The outputs keep their directories, so the two songs don't collide
+    for output_path in ["intro.json", "rock/song.json", "rock/live/song.json"] {
+        let json = fs::read(output_dir.join(output_path)).unwrap();
+        assert_eq!(parse_notes(&json).len(), 4, "{}", output_path);
+    }
+    let mut output_names: Vec<String> = fs::read_dir(&output_dir).unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    output_names.sort();
+    assert_eq!(output_names, ["intro.json", "rock"]);
+
+    // A pattern that matches no MIDI files is an error
+    let pattern = format!("{}/*.txt", music_dir.to_str().unwrap());
+    let output = run(&["--input-glob", &pattern, "--bpm", "120"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stderr).unwrap().contains("No MIDI files match"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_gzip_input_matches_uncompressed() {
+    let bytes = fs::read("input/sample.mid").unwrap();
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&bytes).unwrap();
+    let compressed = encoder.finish().unwrap();
+
+    let file_output = run(&["--input", "input/sample.mid", "--bpm", "120", "--stdout"]);
+    let gzip_output = run_with_stdin(&["--input", "-", "--bpm", "120", "--stdout"], &compressed);
+    assert!(gzip_output.status.success());
+    assert_eq!(gzip_output.stdout, file_output.stdout);
+
+    // Forcing it off parses the compressed bytes as MIDI, which fails
+    let raw_output = run_with_stdin(&["--input", "-", "--bpm", "120", "--stdout", "--no-compressed"], &compressed);
+    assert_eq!(raw_output.status.code(), Some(1));
+    let stderr = String::from_utf8(raw_output.stderr).unwrap();
+    assert!(stderr.contains("Error: Could not parse MIDI file"));
+}
+
+#[test]
+fn test_gzip_output_decompresses_to_json() {
+    let json_output = run(&["--input", "input/sample.mid", "--bpm", "120", "--stdout"]);
+
+    let gzip_output = run(&["--input", "input/sample.mid", "--bpm", "120", "--stdout", "--gzip"]);
+    assert!(gzip_output.status.success());
+    assert_eq!(&gzip_output.stdout[..2], &[0x1f, 0x8b]);
+    assert_eq!(gunzip(&gzip_output.stdout), json_output.stdout);
+
+    // Output files get .gz added
+    let output_dir = std::env::temp_dir().join(format!("midi2json-gzip-{}", std::process::id()));
+    fs::create_dir_all(&output_dir).unwrap();
+    let output_path = output_dir.join("notes.json");
+    let file_output = run(&[
+        "--input", "input/sample.mid",
+        "--bpm", "120",
+        "--gzip",
+        "--output", output_path.to_str().unwrap()
+    ]);
+    assert!(file_output.status.success());
+    assert!(!output_path.exists());
+    let compressed = fs::read(output_dir.join("notes.json.gz")).unwrap();
+    assert_eq!(gunzip(&compressed), json_output.stdout);
+
+    fs::remove_dir_all(&output_dir).unwrap();
+}
+
+#[test]
+fn test_config_file_and_overrides() {
+    let config_dir = std::env::temp_dir().join(format!("midi2json-config-{}", std::process::id()));
+    fs::create_dir_all(&config_dir).unwrap();
+    let config_path = config_dir.join("midi2json.toml");
+    fs::write(&config_path, "bpm = 120.0\nstdout = true\noutput_ticks = true\n").unwrap();
+    let config = config_path.to_str().unwrap();
+
+    // The sample has no tempo events, so this only works with the config's BPM
+    let output = run(&["--input", "input/sample.mid", "--config", config]);
+    assert!(output.status.success());
+    let notes = parse_notes(&output.stdout);
+    assert!(notes[0]["time_start"].is_u64());
+
+    // The command line wins over the config
+    let output = run(&["--input", "input/sample.mid", "--config", config, "--bpm", "fast"]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Error: Invalid BPM: fast"));
+
+    fs::write(&config_path, "bpm = [[]]").unwrap();
+    let output = run(&["--input", "input/sample.mid", "--config", config]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Error: Invalid config file"));
+
+    fs::remove_dir_all(&config_dir).unwrap();
+}
+
+#[test]
+fn test_output_validates_against_schema() {
+    let output = run(&["--schema"]);
+    assert!(output.status.success());
+    let schema: serde_json::Value = serde_json::from_slice(&output.stdout).expect("schema is not valid JSON");
+    assert_eq!(schema["$schema"], "http://json-schema.org/draft-07/schema#");
+    let validator = jsonschema::JSONSchema::compile(&schema).expect("schema is not a valid JSON Schema");
+
+    let outputs = [
+        run(&["--input", "input/sample.mid", "--bpm", "120", "--stdout"]),
+        run(&[
+            "--input", "input/demo-116bpm.mid",
+            "--bpm", "116",
+            "--stdout",
+            "--pitch-names",
+            "--velocity-normalized",
+            "--include-header",
+            "--include-stats",
+            "--deduplicate-report",
+            "--include-tempo-map",
+            "--include-time-signatures",
+            "--include-key-signatures",
+            "--include-text-events",
+            "--include-controllers",
+            "--include-program-changes",
+            "--include-pitch-bend",
+            "--annotate-measures",
+            "--note-density",
+            "--check-overlaps",
+            "--legato-classification",
+            "--chord-window", "0.05",
+            "--relative-time",
+            "--truncated-notes", "include"
+        ]),
+        run(&["--input", "input/demo-116bpm.mid", "--stdout", "--output-ticks", "--normalize-start"]),
+        run(&["--input", "input/demo-116bpm.mid", "--stdout", "--output-ticks", "--sort-by-pitch"]),
+        run(&["--input", "input/sample.mid", "--stdout", "--output-ticks", "--all-tracks", "--check-overlaps"])
+    ];
+    for output in &outputs {
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        let errors: Vec<String> = match validator.validate(&json) {
+            Ok(()) => Vec::new(),
+            Err(errors) => errors.map(|error| error.to_string()).collect()
+        };
+        assert!(errors.is_empty(), "Output does not match the schema: {:?}", errors);
+    }
+}
+
+#[test]
+fn test_sort_with_note_density() {
+    for sort in &["--sort-by-pitch", "--sort-by-duration"] {
+        let output = run(&["--input", "input/demo-116bpm.mid", "--bpm", "116", "--stdout", sort, "--note-density"]);
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert!(!json["note_density"].as_array().unwrap().is_empty());
+        assert_eq!(json["notes"].as_array().unwrap().len(), 15);
+    }
+
+    let output = run(&["--input", "input/demo-116bpm.mid", "--bpm", "116", "--stdout", "--sort-by-pitch", "--note-density"]);
+    let pitches: Vec<u64> = parse_notes(&output.stdout).iter().map(|note| note["pitch_value"].as_u64().unwrap()).collect();
+    assert!(pitches.windows(2).all(|pair| pair[0] <= pair[1]));
+}
+
+#[test]
+fn test_emit_typescript() {
+    let output = run(&["--emit-typescript"]);
+    assert!(output.status.success());
+    let typescript = String::from_utf8(output.stdout).unwrap();
+    for interface in &["NoteInfo", "Note", "TrackNotes", "ChannelNotes", "Chord", "MidiHeader", "Statistics"] {
+        assert!(
+            typescript.contains(&format!("export interface {} {{\n", interface)),
+            "missing interface {}", interface
+        );
+    }
+    assert!(typescript.contains("\n  time_start: number;\n"));
+    assert!(typescript.contains("\n  notes?: Note[];\n"));
+    assert!(typescript.contains("\n  release_velocity: number | null;\n"));
+
+    // Every field is on its own line, and every brace is closed
+    for line in typescript.lines().filter(|line| line.starts_with("  ")) {
+        assert!(line.ends_with(';') && line.contains(": "), "unexpected line {:?}", line);
+    }
+    assert_eq!(typescript.matches('{').count(), typescript.matches('}').count());
+}
+
+#[test]
+fn test_visualize() {
+    let output = run(&["--input", "input/sample.mid", "--bpm", "120", "--visualize-only", "--visualize-columns", "40"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let piano_roll = String::from_utf8(output.stdout).unwrap();
+    // A row for each key, framed with the measures from the file's time signature
+    assert_eq!(piano_roll.lines().count(), 90);
+    assert!(piano_roll.starts_with("     +|"));
+    assert!(piano_roll.lines().all(|line| line.chars().count() == 4 + 2 + 40 + 1));
+    assert!(piano_roll.contains('█'));
+
+    // The piano roll goes to stderr, leaving the JSON as it was
+    let plain = run(&["--input", "input/sample.mid", "--bpm", "120", "--stdout"]);
+    // The piano roll doesn't depend on the order of the notes
+    for flags in [&["--sort-by-pitch", "--visualize-only"][..], &["--stdout", "--note-density", "--visualize"]] {
+        let output = run(&[&["--input", "input/sample.mid", "--bpm", "120"][..], flags].concat());
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    }
+
+    let visualized = run(&["--input", "input/sample.mid", "--bpm", "120", "--stdout", "--visualize"]);
+    assert!(visualized.status.success());
+    assert_eq!(visualized.stdout, plain.stdout);
+    assert!(String::from_utf8_lossy(&visualized.stderr).contains('█'));
+}
+
+/// The notes' times, pitches, channels, and velocities
+fn get_note_summary(json: &[u8]) -> Vec<(f64, f64, u64, u64, u64)> {
+    let json: serde_json::Value = serde_json::from_slice(json).expect("stdout is not valid JSON");
+    let mut notes: Vec<&serde_json::Value> = json["notes"].as_array().into_iter().flatten().collect();
+    for track in json["tracks"].as_array().into_iter().flatten() {
+        notes.extend(track["notes"].as_array().unwrap());
+    }
+    let mut summary: Vec<(f64, f64, u64, u64, u64)> = notes.iter()
+        .map(|note| (
+            note["time_start"].as_f64().unwrap(),
+            note["time_end"].as_f64().unwrap(),
+            note["pitch_value"].as_u64().unwrap(),
+            note["channel"].as_u64().unwrap(),
+            note["velocity"].as_u64().unwrap()
+        ))
+        .collect();
+    summary.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    summary
+}
+
+#[test]
+fn test_json2midi_round_trip() {
+    let dir = std::env::temp_dir().join(format!("midi2json-json2midi-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let json_path = dir.join("notes.json");
+    let midi_path = dir.join("notes.mid");
+
+    for input in &["input/sample.mid", "input/demo-116bpm.mid"] {
+        let first = run(&["--input", input, "--bpm", "100", "--stdout"]);
+        assert!(first.status.success());
+        fs::write(&json_path, &first.stdout).unwrap();
+
+        let output = run(&[
+            "json2midi",
+            "--input", json_path.to_str().unwrap(),
+            "--output", midi_path.to_str().unwrap(),
+            "--bpm", "100"
+        ]);
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+        // The MIDI file has a tempo event now, so it doesn't need --bpm
+        let second = run(&["--input", midi_path.to_str().unwrap(), "--stdout"]);
+        assert!(second.status.success(), "{}", String::from_utf8_lossy(&second.stderr));
+
+        let first_notes = get_note_summary(&first.stdout);
+        let second_notes = get_note_summary(&second.stdout);
+        assert!(!first_notes.is_empty());
+        assert_eq!(first_notes.len(), second_notes.len());
+        for (first_note, second_note) in first_notes.iter().zip(&second_notes) {
+            assert!((first_note.0 - second_note.0).abs() < 1e-6, "{:?} != {:?}", first_note, second_note);
+            assert!((first_note.1 - second_note.1).abs() < 1e-6, "{:?} != {:?}", first_note, second_note);
+            assert_eq!((first_note.2, first_note.3, first_note.4), (second_note.2, second_note.3, second_note.4));
+        }
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_merge_inputs() {
+    let dir = std::env::temp_dir().join(format!("midi2json-merge-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let first_path = dir.join("first.mid");
+    fs::write(&first_path, multi_channel_file()).unwrap();
+
+    // Three notes an eighth note after each of the first file's first three
+    let mut track = vec![0x30];
+    for &pitch in &[72u8, 74, 76] {
+        track.extend_from_slice(&[0x90, pitch, 100, 0x30, 0x80, pitch, 0, 0x30]);
+    }
+    track.extend_from_slice(&[0xFF, 0x2F, 0x00]);
+    let mut second = b"MThd\x00\x00\x00\x06\x00\x00\x00\x01\x00\x60MTrk".to_vec();
+    second.extend_from_slice(&(track.len() as u32).to_be_bytes());
+    second.extend_from_slice(&track);
+    let second_path = dir.join("second.mid");
+    fs::write(&second_path, second).unwrap();
+
+    let first = first_path.to_str().unwrap();
+    let second = second_path.to_str().unwrap();
+    let output = run(&["--input", first, "--input", second, "--bpm", "120", "--stdout"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let notes = parse_notes(&output.stdout);
+    let starts: Vec<f64> = notes.iter().map(|note| note["time_start"].as_f64().unwrap()).collect();
+    assert_eq!(starts, [0.0, 0.25, 0.5, 0.75, 1.0, 1.25, 1.5, 2.0]);
+    let sources: Vec<&str> = notes.iter().map(|note| note["source_file"].as_str().unwrap()).collect();
+    assert_eq!(sources, [first, second, first, second, first, second, first, first]);
+
+    // Only the notes can be merged
+    let output = run(&["--input", first, "--input", second, "--bpm", "120", "--stdout", "--include-tempo-map"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--include-tempo-map can't be used when merging"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_output_dir() {
+    let dir = std::env::temp_dir().join(format!("midi2json-output-dir-{}", std::process::id()));
+    let input_dir = dir.join("music");
+    let output_dir = dir.join("json");
+    fs::create_dir_all(&input_dir).unwrap();
+    fs::create_dir_all(&output_dir).unwrap();
+    let input_path = input_dir.join("song.mid");
+    fs::copy("input/sample.mid", &input_path).unwrap();
+    let input = input_path.to_str().unwrap();
+
+    let output = run(&["--input", input, "--bpm", "120", "--output-dir", output_dir.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(parse_notes(&fs::read(output_dir.join("song.json")).unwrap()).len(), 4);
+
+    // "." puts it next to the input
+    let output = run(&["--input", input, "--bpm", "120", "--output-dir", "."]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(input_dir.join("song.json").is_file());
+
+    let missing_dir = dir.join("missing");
+    let output = run(&["--input", input, "--bpm", "120", "--output-dir", missing_dir.to_str().unwrap()]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Output directory does not exist"), "{}", stderr);
+    assert!(stderr.contains("missing"), "{}", stderr);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+/// A Type 0 file with notes on channels 0, 3, and 9
+fn multi_channel_file() -> Vec<u8> {
+    let mut track = Vec::new();
+    for &(channel, pitch) in &[(0u8, 60u8), (9, 36), (0, 64), (3, 48), (9, 42)] {
+        track.extend_from_slice(&[0x00, 0x90 | channel, pitch, 100]);
+        track.extend_from_slice(&[0x60, 0x80 | channel, pitch, 0]);
+    }
+    track.extend_from_slice(&[0x00, 0xFF, 0x2F, 0x00]);
+
+    let mut bytes = b"MThd\x00\x00\x00\x06\x00\x00\x00\x01\x00\x60MTrk".to_vec();
+    bytes.extend_from_slice(&(track.len() as u32).to_be_bytes());
+    bytes.extend_from_slice(&track);
+    bytes
+}
+
+#[test]
+fn test_split_by_channel() {
+    let dir = std::env::temp_dir().join(format!("midi2json-split-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let input_path = dir.join("song.mid");
+    fs::write(&input_path, multi_channel_file()).unwrap();
+
+    let output = run(&[
+        "--input", input_path.to_str().unwrap(),
+        "--bpm", "120",
+        "--split-by-channel",
+        "--output", dir.to_str().unwrap()
+    ]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let mut file_names: Vec<String> = fs::read_dir(&dir).unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    file_names.sort();
+    assert_eq!(file_names, ["song.json", "song.mid", "song_ch0.json", "song_ch3.json", "song_ch9.json"]);
+
+    // Every note is in exactly one channel's file
+    let all_notes = parse_notes(&fs::read(dir.join("song.json")).unwrap());
+    let mut split_notes = Vec::new();
+    for channel in &[0, 3, 9] {
+        let notes = parse_notes(&fs::read(dir.join(format!("song_ch{}.json", channel))).unwrap());
+        assert!(notes.iter().all(|note| note["channel"] == *channel));
+        split_notes.extend(notes);
+    }
+    assert_eq!(split_notes.len(), all_notes.len());
+    for note in &all_notes {
+        assert_eq!(split_notes.iter().filter(|split_note| *split_note == note).count(), 1);
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_split_by_channel_no_bpm_required() {
+    let dir = std::env::temp_dir().join(format!("midi2json-split-ticks-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    // A Type 1 file with no tempo, and the later note on channel 0 in the first track
+    let mut bytes = b"MThd\x00\x00\x00\x06\x00\x01\x00\x02\x00\x60".to_vec();
+    for track in &[vec![0x60, 0x90, 60, 100, 0x60, 0x80, 60, 0], vec![0x00, 0x90, 64, 100, 0x30, 0x91, 48, 100, 0x30, 0x80, 64, 0, 0x00, 0x81, 48, 0]] {
+        let mut track = track.clone();
+        track.extend_from_slice(&[0x00, 0xFF, 0x2F, 0x00]);
+        bytes.extend_from_slice(b"MTrk");
+        bytes.extend_from_slice(&(track.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&track);
+    }
+    let input_path = dir.join("song.mid");
+    fs::write(&input_path, bytes).unwrap();
+
+    let output = run(&[
+        "--input", input_path.to_str().unwrap(),
+        "--no-bpm-required",
+        "--split-by-channel",
+        "--output", dir.to_str().unwrap()
+    ]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let starts = |channel: u8| -> Vec<u64> {
+        parse_notes(&fs::read(dir.join(format!("song_ch{}.json", channel))).unwrap()).iter()
+            .map(|note| note["time_start_ticks"].as_u64().unwrap())
+            .collect()
+    };
+    assert_eq!(starts(0), vec![0, 96]);
+    assert_eq!(starts(1), vec![48]);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_split_by_channel_merge_into() {
+    let dir = std::env::temp_dir().join(format!("midi2json-split-merge-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    let output = run_with_stdin(&[
+        "--input", "-",
+        "--bpm", "120",
+        "--include-stats",
+        "--split-by-channel",
+        "--merge-into",
+        "--output-dir", dir.to_str().unwrap()
+    ], &multi_channel_file());
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    // No separate file for the whole output, but each channel's has the statistics
+    assert!(!dir.join("notes.json").exists());
+    let json: serde_json::Value = serde_json::from_slice(&fs::read(dir.join("notes_ch9.json")).unwrap()).unwrap();
+    assert_eq!(json["notes"].as_array().unwrap().len(), 2);
+    assert_eq!(json["statistics"]["total_notes"], 5);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+/// A Type 1 file with an empty track named "Conductor", a track named "Lead
+/// Vox" with two notes, and a track without a name with one note
+fn multi_track_file() -> Vec<u8> {
+    let conductor = b"\x00\xFF\x03\x09Conductor\x00\xFF\x2F\x00".to_vec();
+    let lead = b"\x00\xFF\x03\x08Lead Vox\x00\x90\x3C\x64\x60\x80\x3C\x00\x00\x90\x3E\x64\x60\x80\x3E\x00\x00\xFF\x2F\x00".to_vec();
+    let unnamed = b"\x00\x91\x30\x64\x60\x81\x30\x00\x00\xFF\x2F\x00".to_vec();
+
+    let mut bytes = b"MThd\x00\x00\x00\x06\x00\x01\x00\x03\x00\x60".to_vec();
+    for track in &[conductor, lead, unnamed] {
+        bytes.extend_from_slice(b"MTrk");
+        bytes.extend_from_slice(&(track.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(track);
+    }
+    bytes
+}
+
+#[test]
+fn test_split_by_track() {
+    let dir = std::env::temp_dir().join(format!("midi2json-split-track-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let input_path = dir.join("song.mid");
+    fs::write(&input_path, multi_track_file()).unwrap();
+
+    let output = run(&[
+        "--input", input_path.to_str().unwrap(),
+        "--bpm", "120",
+        "--split-by-track",
+        "--output-dir", dir.to_str().unwrap()
+    ]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let json: serde_json::Value = serde_json::from_slice(&fs::read(dir.join("song_Lead_Vox.json")).unwrap()).unwrap();
+    assert_eq!(json["tracks"][0]["index"], 1);
+    assert_eq!(json["tracks"][0]["name"], "Lead Vox");
+    assert_eq!(json["tracks"][0]["notes"].as_array().unwrap().len(), 2);
+    let json: serde_json::Value = serde_json::from_slice(&fs::read(dir.join("song_track2.json")).unwrap()).unwrap();
+    assert_eq!(json["tracks"][0]["name"], serde_json::Value::Null);
+    assert_eq!(json["tracks"][0]["notes"].as_array().unwrap().len(), 1);
+    // The track without any notes is skipped
+    assert!(!dir.join("song_Conductor.json").exists());
+    assert!(dir.join("song.json").exists());
+
+    let output = run(&[
+        "--input", input_path.to_str().unwrap(),
+        "--bpm", "120",
+        "--split-by-track",
+        "--include-empty-tracks",
+        "--output-dir", dir.to_str().unwrap()
+    ]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let json: serde_json::Value = serde_json::from_slice(&fs::read(dir.join("song_Conductor.json")).unwrap()).unwrap();
+    assert_eq!(json["tracks"][0]["notes"].as_array().unwrap().len(), 0);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_watch_updates_output() {
+    let dir = std::env::temp_dir().join(format!("midi2json-watch-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let input_path = dir.join("song.mid");
+    let output_path = dir.join("song.json");
+    fs::copy("input/sample.mid", &input_path).unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_midi2json"))
+        .args([
+            "--input", input_path.to_str().unwrap(),
+            "--bpm", "120",
+            "--output", output_path.to_str().unwrap(),
+            "--watch"
+        ])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("Failed to run midi2json");
+
+    // Waits for the output to have this many notes, since it's written in the background
+    let wait_for_notes = |count: usize| {
+        for _ in 0..100 {
+            if let Ok(json) = fs::read(&output_path) {
+                if parse_notes(&json).len() == count {
+                    return true;
+                }
+            }
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+        false
+    };
+
+    let first_count = parse_notes(&run(&["--input", "input/sample.mid", "--bpm", "120", "--stdout"]).stdout).len();
+    let second_count = parse_notes(&run(&["--input", "input/demo-116bpm.mid", "--bpm", "120", "--stdout"]).stdout).len();
+    assert_ne!(first_count, second_count);
+
+    let first_written = wait_for_notes(first_count);
+    fs::write(&input_path, fs::read("input/demo-116bpm.mid").unwrap()).unwrap();
+    let second_written = wait_for_notes(second_count);
+    child.kill().unwrap();
+    child.wait().unwrap();
+
+    assert!(first_written);
+    assert!(second_written);
+    // Only the finished output is left behind
+    assert!(!dir.join("song.json.tmp").exists());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_verbose_levels() {
+    let stderr_with = |verbose: &[&str]| {
+        let mut args = vec!["--input", "input/sample.mid", "--bpm", "120", "--stdout"];
+        args.extend_from_slice(verbose);
+        let output = run(&args);
+        assert!(output.status.success());
+        String::from_utf8(output.stderr).unwrap()
+    };
+
+    // By default there's only the progress, without each note
+    let stderr = stderr_with(&[]);
+    assert!(stderr.contains("Loading MIDI file..."));
+    assert!(!stderr.contains("Notes:"));
+    assert!(!stderr.contains("chunk at byte"));
+
+    let stderr = stderr_with(&["-v"]);
+    assert!(stderr.contains("Notes:"));
+    assert!(stderr.contains(": pitch 60"));
+    assert!(!stderr.contains("chunk at byte"));
+
+    let stderr = stderr_with(&["-vv"]);
+    assert!(stderr.contains("Notes:"));
+    assert!(stderr.contains("MTrk chunk at byte 14"));
+}
+
+#[test]
+fn test_include_running_stats() {
+    let output = run(&["--input", "input/demo-116bpm.mid", "--bpm", "116", "--stdout", "--include-running-stats", "4"]);
+    assert!(output.status.success());
+    let notes = parse_notes(&output.stdout);
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let progress: Vec<&str> = stderr.lines().filter(|line| line.starts_with("Processed ")).collect();
+    let counts: Vec<usize> = progress.iter()
+        .map(|line| {
+            let (count, elapsed) = line["Processed ".len()..].split_once(" notes (").unwrap();
+            let seconds = elapsed.strip_suffix("s elapsed)").unwrap();
+            assert_eq!(seconds.split_once('.').unwrap().1.len(), 2, "{}", line);
+            count.parse().unwrap()
+        })
+        .collect();
+    assert_eq!(counts, vec![4, 8, 12, 15]);
+    assert_eq!(*counts.last().unwrap(), notes.len());
+
+    let output = run(&["--input", "input/demo-116bpm.mid", "--bpm", "116", "--stdout", "--include-running-stats", "4", "-q"]);
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn test_include_running_stats_to_file() {
+    let dir = std::env::temp_dir().join(format!("midi2json-running-stats-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let output_path = dir.join("notes.json");
+
+    // Writing to a file leaves stdout empty for whatever it's piped to
+    let output = run(&["--input", "input/demo-116bpm.mid", "--bpm", "116", "--output", output_path.to_str().unwrap(), "--include-running-stats", "4"]);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty(), "{}", String::from_utf8_lossy(&output.stdout));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.lines().any(|line| line.starts_with("Processed 15 notes (")), "{}", stderr);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_event_log() {
+    let dir = std::env::temp_dir().join(format!("midi2json-event-log-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let event_log_path = dir.join("events.json");
+
+    let output = run(&["--input", "input/sample.mid", "--bpm", "120", "--output", "-", "--event-log", event_log_path.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert_eq!(parse_notes(&output.stdout).len(), 4);
+
+    let bytes = fs::read("input/sample.mid").unwrap();
+    let smf: midly::Smf<Vec<midly::Event>> = midly::Smf::read(&bytes).unwrap();
+    let event_log: Vec<serde_json::Value> = serde_json::from_slice(&fs::read(&event_log_path).unwrap()).unwrap();
+    assert_eq!(event_log.len(), smf.tracks[0].len());
+    assert_eq!(event_log.iter().filter(|event| event["kind"] == "note_on").count(), 4);
+    assert!(event_log.iter().all(|event| event["tick"].is_u64() && event["data"].is_object()));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_quiet() {
+    let dir = std::env::temp_dir().join(format!("midi2json-quiet-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let output_path = dir.join("notes.json");
+
+    let output = run(&["--input", "input/sample.mid", "--bpm", "120", "--output", output_path.to_str().unwrap(), "-q"]);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(output.stderr.is_empty());
+    assert!(!parse_notes(&fs::read(&output_path).unwrap()).is_empty());
+
+    // Errors are still reported
+    let output = run(&["--input", "input/missing.mid", "--bpm", "120", "--output", output_path.to_str().unwrap(), "--quiet"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stderr).unwrap().starts_with("Error: "));
+
+    let output = run(&["--input", "input/sample.mid", "-q", "-v"]);
+    assert!(!output.status.success());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
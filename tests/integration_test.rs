@@ -0,0 +1,48 @@
+use std::fs;
+use std::path::Path;
+use midi2json::{Midi2JsonError, NoteInfo, Options, parse_note_info, process_bytes};
+
+/// Reads `tests/fixtures/<name>`
+fn read_fixture(name: &str) -> Vec<u8> {
+    fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures").join(name)).unwrap()
+}
+
+/// Converts `tests/fixtures/<name>.mid` with the default options, and compares
+/// it with `tests/fixtures/<name>.json`
+fn assert_matches_golden(name: &str) {
+    let bytes = read_fixture(&format!("{}.mid", name));
+    let golden = String::from_utf8(read_fixture(&format!("{}.json", name))).unwrap();
+
+    let note_info = process_bytes(&bytes, &Options::default()).unwrap();
+    let expected: NoteInfo = parse_note_info(&golden).unwrap();
+    assert_eq!(note_info, expected, "{}.mid", name);
+}
+
+#[test]
+fn test_type_0() {
+    assert_matches_golden("type-0");
+}
+
+#[test]
+fn test_type_1_tempo_changes() {
+    assert_matches_golden("type-1-tempo-changes");
+}
+
+#[test]
+fn test_chords() {
+    assert_matches_golden("chords");
+}
+
+#[test]
+fn test_velocity_0_note_off() {
+    assert_matches_golden("velocity-0-note-off");
+}
+
+#[test]
+fn test_zero_ticks_per_beat() {
+    let bytes = read_fixture("zero-ticks-per-beat.mid");
+    match process_bytes(&bytes, &Options::default()) {
+        Err(Midi2JsonError::ParseError(message)) => assert_eq!(message, "the header gives 0 ticks per beat"),
+        other => panic!("Expected a parse error, got {:?}", other)
+    }
+}